@@ -0,0 +1,127 @@
+//! Scrittura atomica dei file di output.
+//!
+//! I dati vanno in un file temporaneo nella stessa directory della destinazione,
+//! che viene sincronizzato su disco e poi rinominato sul percorso finale. Un errore
+//! o un'interruzione non lasciano mai un output troncato e, con `--force`, il file
+//! esistente resta intatto fino al rename, che ne mantiene i permessi.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// File temporanei ancora aperti, rimossi da [`cleanup_partial_outputs`]
+static PENDING: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Distingue i file temporanei creati dallo stesso processo
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+fn pending() -> std::sync::MutexGuard<'static, Vec<PathBuf>> {
+    PENDING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// File di output scritto in modo atomico.
+///
+/// Finché non viene chiamato [`AtomicFile::commit`] il percorso finale non viene
+/// toccato; se l'`AtomicFile` viene scartato il file temporaneo è rimosso.
+pub struct AtomicFile {
+    file: File,
+    temp_path: PathBuf,
+    final_path: PathBuf,
+    committed: bool,
+}
+
+impl AtomicFile {
+    /// Crea il file temporaneo accanto a `path`
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        let file_name = path.file_name().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "Nome file non valido")
+        })?;
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(file_name);
+        temp_name.push(format!(
+            ".{}-{}.tmp",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let temp_path = path.with_file_name(temp_name);
+
+        let file = File::options()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+        pending().push(temp_path.clone());
+
+        Ok(Self {
+            file,
+            temp_path,
+            final_path: path.to_path_buf(),
+            committed: false,
+        })
+    }
+
+    /// Percorso del file temporaneo
+    pub fn temp_path(&self) -> &Path {
+        &self.temp_path
+    }
+
+    /// Sincronizza i dati su disco e rinomina il file temporaneo sul percorso finale
+    pub fn commit(mut self) -> std::io::Result<()> {
+        // Come la vecchia troncatura, sovrascrivere non deve allargare i permessi
+        match std::fs::metadata(&self.final_path) {
+            Ok(existing) => self.file.set_permissions(existing.permissions())?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        self.file.flush()?;
+        self.file.sync_all()?;
+        std::fs::rename(&self.temp_path, &self.final_path)?;
+        self.committed = true;
+        pending().retain(|p| p != &self.temp_path);
+
+        // Rende persistente anche la voce di directory del rename
+        #[cfg(unix)]
+        if let Some(parent) = self.final_path.parent() {
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            if let Ok(dir) = File::open(parent) {
+                let _ = dir.sync_all();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.temp_path);
+            pending().retain(|p| p != &self.temp_path);
+        }
+    }
+}
+
+/// Rimuove i file temporanei di tutte le scritture in corso.
+///
+/// Pensata per gli handler di segnali (Ctrl-C), che terminano il processo senza
+/// eseguire i distruttori.
+pub fn cleanup_partial_outputs() {
+    for path in pending().drain(..) {
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,685 @@
+//! Backend di compressione intercambiabili.
+//!
+//! Zstandard è sempre disponibile; lz4, gzip, xz e brotli sono abilitati dalle
+//! rispettive cargo feature (tutte attive di default).
+
+use crate::CompressionDictionary;
+use std::io::{BufRead, Read, Write};
+use std::path::Path;
+
+/// Algoritmo di compressione
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompressionAlgorithm {
+    /// Zstandard (default)
+    #[default]
+    Zstd,
+    /// LZ4 frame - velocità massima
+    Lz4,
+    /// Gzip - compatibilità con strumenti legacy
+    Gzip,
+    /// Xz (LZMA2) - ratio alto, lento
+    Xz,
+    /// Brotli - ottimo per testo/web
+    Brotli,
+}
+
+impl CompressionAlgorithm {
+    /// Tutti gli algoritmi conosciuti, disponibili o meno
+    pub const ALL: [CompressionAlgorithm; 5] = [
+        CompressionAlgorithm::Zstd,
+        CompressionAlgorithm::Lz4,
+        CompressionAlgorithm::Gzip,
+        CompressionAlgorithm::Xz,
+        CompressionAlgorithm::Brotli,
+    ];
+
+    /// Nome dell'algoritmo (usato anche dalla CLI)
+    pub fn name(self) -> &'static str {
+        match self {
+            CompressionAlgorithm::Zstd => "zstd",
+            CompressionAlgorithm::Lz4 => "lz4",
+            CompressionAlgorithm::Gzip => "gzip",
+            CompressionAlgorithm::Xz => "xz",
+            CompressionAlgorithm::Brotli => "brotli",
+        }
+    }
+
+    /// Estensione dei file compressi (senza punto)
+    pub fn extension(self) -> &'static str {
+        match self {
+            CompressionAlgorithm::Zstd => "zst",
+            CompressionAlgorithm::Lz4 => "lz4",
+            CompressionAlgorithm::Gzip => "gz",
+            CompressionAlgorithm::Xz => "xz",
+            CompressionAlgorithm::Brotli => "br",
+        }
+    }
+
+    /// Livello massimo accettato dall'algoritmo; i livelli più alti vengono limitati
+    pub fn max_level(self) -> i32 {
+        match self {
+            CompressionAlgorithm::Zstd => 21,
+            CompressionAlgorithm::Lz4 => 1, // il formato frame di lz4_flex non ha livelli
+            CompressionAlgorithm::Gzip | CompressionAlgorithm::Xz => 9,
+            CompressionAlgorithm::Brotli => 11,
+        }
+    }
+
+    /// Ritorna true se i decoder standard del formato saltano i frame skippable
+    /// (zstd e lz4); gzip, xz e brotli li tratterebbero come dati corrotti
+    pub fn skips_skippable_frames(self) -> bool {
+        matches!(self, CompressionAlgorithm::Zstd | CompressionAlgorithm::Lz4)
+    }
+
+    /// Ritorna true se il backend è stato compilato (cargo feature attiva)
+    pub fn is_available(self) -> bool {
+        match self {
+            CompressionAlgorithm::Zstd => true,
+            CompressionAlgorithm::Lz4 => cfg!(feature = "lz4"),
+            CompressionAlgorithm::Gzip => cfg!(feature = "gzip"),
+            CompressionAlgorithm::Xz => cfg!(feature = "xz"),
+            CompressionAlgorithm::Brotli => cfg!(feature = "brotli"),
+        }
+    }
+
+    /// Rileva l'algoritmo dall'estensione del file (.zst, .gz, .tgz, .lz4, .xz, .txz, .br)
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path
+            .extension()
+            .and_then(|s| s.to_str())
+            .map(|s| s.to_lowercase());
+
+        match extension.as_deref() {
+            Some("zst" | "tzst") => Some(CompressionAlgorithm::Zstd),
+            Some("lz4") => Some(CompressionAlgorithm::Lz4),
+            Some("gz" | "tgz") => Some(CompressionAlgorithm::Gzip),
+            Some("xz" | "txz") => Some(CompressionAlgorithm::Xz),
+            Some("br") => Some(CompressionAlgorithm::Brotli),
+            _ => None,
+        }
+    }
+
+    /// Riconosce l'algoritmo dai primi bytes di un frame (magic number).
+    ///
+    /// Brotli non ha un magic number e non viene mai riconosciuto.
+    pub fn from_magic(header: &[u8]) -> Option<Self> {
+        if header.starts_with(&ZSTD_MAGIC.to_le_bytes()) {
+            Some(CompressionAlgorithm::Zstd)
+        } else if header.starts_with(&LZ4_MAGIC.to_le_bytes()) {
+            Some(CompressionAlgorithm::Lz4)
+        } else if header.starts_with(&GZIP_MAGIC) {
+            Some(CompressionAlgorithm::Gzip)
+        } else if header.starts_with(&XZ_MAGIC) {
+            Some(CompressionAlgorithm::Xz)
+        } else {
+            None
+        }
+    }
+
+    /// Rileva l'algoritmo leggendo l'inizio di uno stream.
+    ///
+    /// I frame skippable (magic 0x184D2A50-0x184D2A5F, condivisi da zstd e lz4)
+    /// vengono saltati; uno stream fatto solo di frame skippable è zstd valido.
+    pub fn sniff<R: Read>(reader: &mut R) -> std::io::Result<Option<Self>> {
+        let mut seen_skippable = false;
+
+        loop {
+            let mut magic = [0u8; 4];
+            let n = read_up_to(reader, &mut magic)?;
+            if n == 0 && seen_skippable {
+                return Ok(Some(CompressionAlgorithm::Zstd));
+            }
+
+            if n == 4 && is_skippable_magic(u32::from_le_bytes(magic)) {
+                let mut size = [0u8; 4];
+                if read_up_to(reader, &mut size)? < 4 {
+                    return Ok(None);
+                }
+                let size = u64::from(u32::from_le_bytes(size));
+                let skipped = std::io::copy(&mut reader.by_ref().take(size), &mut std::io::sink())?;
+                if skipped < size {
+                    return Ok(None);
+                }
+                seen_skippable = true;
+                continue;
+            }
+
+            // xz ha il magic più lungo (6 bytes)
+            let mut header = [0u8; 6];
+            header[..n].copy_from_slice(&magic[..n]);
+            let len = if n == 4 {
+                4 + read_up_to(reader, &mut header[4..])?
+            } else {
+                n
+            };
+            return Ok(Self::from_magic(&header[..len]));
+        }
+    }
+
+    /// Rileva l'algoritmo di un file dal contenuto.
+    ///
+    /// Solo per brotli, che non ha magic number, si ricorre all'estensione `.br`.
+    pub fn detect(path: &Path) -> std::io::Result<Option<Self>> {
+        let (mut file, _) = crate::volume::open_input(path)?;
+        if let Some(algorithm) = Self::sniff(&mut file)? {
+            return Ok(Some(algorithm));
+        }
+
+        Ok(match Self::from_path(&crate::volume::logical_path(path)) {
+            Some(CompressionAlgorithm::Brotli) => Some(CompressionAlgorithm::Brotli),
+            _ => None,
+        })
+    }
+
+    /// Restituisce il backend per questo algoritmo
+    pub fn compressor(self) -> std::io::Result<Box<dyn Compressor>> {
+        match self {
+            CompressionAlgorithm::Zstd => Ok(Box::new(ZstdCompressor)),
+            #[cfg(feature = "lz4")]
+            CompressionAlgorithm::Lz4 => Ok(Box::new(Lz4Compressor)),
+            #[cfg(feature = "gzip")]
+            CompressionAlgorithm::Gzip => Ok(Box::new(GzipCompressor)),
+            #[cfg(feature = "xz")]
+            CompressionAlgorithm::Xz => Ok(Box::new(XzCompressor)),
+            #[cfg(feature = "brotli")]
+            CompressionAlgorithm::Brotli => Ok(Box::new(BrotliCompressor)),
+            #[allow(unreachable_patterns)]
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!(
+                    "Algoritmo {} non disponibile: ricompila con --features {}",
+                    self.name(),
+                    self.name()
+                ),
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for CompressionAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Magic number di un frame zstd (little endian)
+pub const ZSTD_MAGIC: u32 = 0xFD2F_B528;
+/// Magic number di un frame lz4 (little endian)
+pub const LZ4_MAGIC: u32 = 0x184D_2204;
+/// Primo magic number dei frame skippable; gli ultimi 4 bit sono liberi
+pub const SKIPPABLE_MAGIC_START: u32 = 0x184D_2A50;
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const XZ_MAGIC: [u8; 6] = [0xFD, b'7', b'z', b'X', b'Z', 0x00];
+
+/// Ritorna true se il magic number indica un frame skippable
+pub fn is_skippable_magic(magic: u32) -> bool {
+    magic & 0xFFFF_FFF0 == SKIPPABLE_MAGIC_START
+}
+
+/// Dimensione massima dell'header di un frame zstd
+const ZSTD_FRAME_HEADER_MAX: usize = 18;
+
+/// Informazioni dall'header del primo frame zstd di un file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZstdFrameHeader {
+    /// Dimensione decompressa, se l'header la riporta (assente da stream)
+    pub content_size: Option<u64>,
+    /// Il frame termina con il checksum del contenuto, verificato dal decoder
+    pub checksum: bool,
+}
+
+impl ZstdFrameHeader {
+    /// Legge l'header del primo frame, saltando i frame skippable iniziali.
+    ///
+    /// Ritorna `None` se il file non è zstd; per file con più frame le
+    /// informazioni valgono solo per il primo.
+    pub fn read(path: &Path) -> std::io::Result<Option<Self>> {
+        let mut file = std::io::BufReader::new(crate::volume::open_input(path)?.0);
+        loop {
+            let mut header = [0u8; ZSTD_FRAME_HEADER_MAX];
+            let n = read_up_to(&mut file, &mut header)?;
+            if n < 8 {
+                return Ok(None);
+            }
+
+            let magic = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+            if is_skippable_magic(magic) {
+                let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+                // Parte del contenuto del frame skippable è già stata letta con l'header
+                file.seek_relative(i64::from(size) - (n as i64 - 8))?;
+                continue;
+            }
+            if magic != ZSTD_MAGIC {
+                return Ok(None);
+            }
+            return Ok(Some(Self {
+                content_size: zstd::zstd_safe::get_frame_content_size(&header[..n])
+                    .ok()
+                    .flatten(),
+                // Bit 2 del Frame_Header_Descriptor: Content_Checksum_flag
+                checksum: header[4] & 0b100 != 0,
+            }));
+        }
+    }
+}
+
+/// Dimensione decompressa dichiarata nell'header del primo frame zstd di un file
+pub fn zstd_content_size(path: &Path) -> std::io::Result<Option<u64>> {
+    Ok(ZstdFrameHeader::read(path)?.and_then(|h| h.content_size))
+}
+
+/// Legge fino a riempire `buf` o fino a EOF; ritorna i bytes letti
+pub(crate) fn read_up_to<R: Read + ?Sized>(
+    reader: &mut R,
+    buf: &mut [u8],
+) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Reader che conserva una copia dei bytes letti
+struct Recorder<'a, R> {
+    inner: &'a mut R,
+    recorded: Vec<u8>,
+}
+
+impl<R: Read> Read for Recorder<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.recorded.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Rileva l'algoritmo da uno stream non riavvolgibile (es. stdin).
+///
+/// Ritorna anche i bytes consumati, da rimettere davanti allo stream prima di decodificarlo.
+pub fn sniff_stream<R: Read>(
+    reader: &mut R,
+) -> std::io::Result<(Option<CompressionAlgorithm>, Vec<u8>)> {
+    let mut recorder = Recorder {
+        inner: reader,
+        recorded: Vec::new(),
+    };
+    let algorithm = CompressionAlgorithm::sniff(&mut recorder)?;
+    Ok((algorithm, recorder.recorded))
+}
+
+/// Valida il nome di un algoritmo di compressione
+pub fn parse_algorithm(s: &str) -> Result<CompressionAlgorithm, String> {
+    let algorithm = match s.to_lowercase().as_str() {
+        "zstd" | "zst" => CompressionAlgorithm::Zstd,
+        "lz4" => CompressionAlgorithm::Lz4,
+        "gzip" | "gz" => CompressionAlgorithm::Gzip,
+        "xz" | "lzma" => CompressionAlgorithm::Xz,
+        "brotli" | "br" => CompressionAlgorithm::Brotli,
+        _ => {
+            return Err(format!(
+                "Algoritmo '{}' non valido: usa zstd, lz4, gzip, xz o brotli",
+                s
+            ))
+        }
+    };
+
+    if !algorithm.is_available() {
+        return Err(format!(
+            "Algoritmo '{}' non disponibile in questa build (feature '{}' disattivata)",
+            s,
+            algorithm.name()
+        ));
+    }
+
+    Ok(algorithm)
+}
+
+/// Impostazioni per creare un encoder
+#[derive(Debug, Clone, Default)]
+pub struct EncoderSettings<'a> {
+    /// Livello di compressione (1-21), limitato al massimo dell'algoritmo
+    pub level: i32,
+    /// Dizionario (solo zstd)
+    pub dictionary: Option<&'a CompressionDictionary>,
+    /// Dimensione dell'input se nota in anticipo
+    pub pledged_size: Option<u64>,
+    /// Parametri avanzati applicati solo all'encoder zstd
+    pub zstd_parameters: Vec<zstd::zstd_safe::CParameter>,
+}
+
+impl EncoderSettings<'_> {
+    pub(crate) fn level_for(&self, algorithm: CompressionAlgorithm) -> i32 {
+        self.level.clamp(1, algorithm.max_level())
+    }
+}
+
+/// Stream di compressione che deve essere finalizzato esplicitamente
+pub trait FinishWrite: Write {
+    /// Scrive gli ultimi blocchi e il footer del formato
+    fn finish(self: Box<Self>) -> std::io::Result<()>;
+}
+
+/// Backend di compressione per un singolo algoritmo
+pub trait Compressor: Send + Sync {
+    /// Algoritmo implementato
+    fn algorithm(&self) -> CompressionAlgorithm;
+
+    /// Crea un encoder che scrive i dati compressi su `writer`
+    fn encoder<'a>(
+        &self,
+        writer: &'a mut dyn Write,
+        settings: &EncoderSettings,
+    ) -> std::io::Result<Box<dyn FinishWrite + 'a>>;
+
+    /// Crea un decoder che legge i dati compressi da `reader`
+    fn decoder<'a>(
+        &self,
+        reader: &'a mut dyn BufRead,
+        dictionary: Option<&CompressionDictionary>,
+    ) -> std::io::Result<Box<dyn Read + 'a>>;
+}
+
+/// Errore per dizionari usati con algoritmi che non li supportano
+#[cfg(any(feature = "lz4", feature = "gzip", feature = "xz", feature = "brotli"))]
+fn dictionary_unsupported(algorithm: CompressionAlgorithm) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!(
+            "I dizionari sono supportati solo con zstd, non con {}",
+            algorithm
+        ),
+    )
+}
+
+/// Backend Zstandard
+pub struct ZstdCompressor;
+
+impl<W: Write> FinishWrite for zstd::Encoder<'_, W> {
+    fn finish(self: Box<Self>) -> std::io::Result<()> {
+        (*self).finish().map(|_| ())
+    }
+}
+
+impl Compressor for ZstdCompressor {
+    fn algorithm(&self) -> CompressionAlgorithm {
+        CompressionAlgorithm::Zstd
+    }
+
+    fn encoder<'a>(
+        &self,
+        writer: &'a mut dyn Write,
+        settings: &EncoderSettings,
+    ) -> std::io::Result<Box<dyn FinishWrite + 'a>> {
+        let level = settings.level_for(CompressionAlgorithm::Zstd);
+        let mut encoder = match settings.dictionary {
+            Some(dict) => zstd::Encoder::with_dictionary(writer, level, dict.as_bytes())?,
+            None => zstd::Encoder::new(writer, level)?,
+        };
+
+        // Pledged source size: migliora ratio di compressione del 2-5%
+        if settings.pledged_size.is_some() {
+            encoder.set_pledged_src_size(settings.pledged_size)?;
+        }
+
+        for param in &settings.zstd_parameters {
+            encoder.set_parameter(*param)?;
+        }
+
+        Ok(Box::new(encoder))
+    }
+
+    fn decoder<'a>(
+        &self,
+        reader: &'a mut dyn BufRead,
+        dictionary: Option<&CompressionDictionary>,
+    ) -> std::io::Result<Box<dyn Read + 'a>> {
+        let decoder = match dictionary {
+            Some(dict) => zstd::Decoder::with_dictionary(reader, dict.as_bytes())?,
+            None => zstd::Decoder::with_buffer(reader)?,
+        };
+        Ok(Box::new(decoder))
+    }
+}
+
+/// Backend LZ4 (formato frame, compatibile con `lz4` CLI)
+#[cfg(feature = "lz4")]
+pub struct Lz4Compressor;
+
+#[cfg(feature = "lz4")]
+impl<W: Write> FinishWrite for lz4_flex::frame::FrameEncoder<W> {
+    fn finish(self: Box<Self>) -> std::io::Result<()> {
+        (*self).finish().map(|_| ()).map_err(std::io::Error::from)
+    }
+}
+
+#[cfg(feature = "lz4")]
+impl Compressor for Lz4Compressor {
+    fn algorithm(&self) -> CompressionAlgorithm {
+        CompressionAlgorithm::Lz4
+    }
+
+    fn encoder<'a>(
+        &self,
+        writer: &'a mut dyn Write,
+        settings: &EncoderSettings,
+    ) -> std::io::Result<Box<dyn FinishWrite + 'a>> {
+        if settings.dictionary.is_some() {
+            return Err(dictionary_unsupported(CompressionAlgorithm::Lz4));
+        }
+
+        let mut frame_info = lz4_flex::frame::FrameInfo::new();
+        if let Some(size) = settings.pledged_size {
+            frame_info = frame_info.content_size(Some(size));
+        }
+
+        Ok(Box::new(lz4_flex::frame::FrameEncoder::with_frame_info(
+            frame_info, writer,
+        )))
+    }
+
+    fn decoder<'a>(
+        &self,
+        reader: &'a mut dyn BufRead,
+        dictionary: Option<&CompressionDictionary>,
+    ) -> std::io::Result<Box<dyn Read + 'a>> {
+        if dictionary.is_some() {
+            return Err(dictionary_unsupported(CompressionAlgorithm::Lz4));
+        }
+        Ok(Box::new(lz4_flex::frame::FrameDecoder::new(reader)))
+    }
+}
+
+/// Backend gzip
+#[cfg(feature = "gzip")]
+pub struct GzipCompressor;
+
+#[cfg(feature = "gzip")]
+impl<W: Write> FinishWrite for flate2::write::GzEncoder<W> {
+    fn finish(self: Box<Self>) -> std::io::Result<()> {
+        (*self).finish().map(|_| ())
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl Compressor for GzipCompressor {
+    fn algorithm(&self) -> CompressionAlgorithm {
+        CompressionAlgorithm::Gzip
+    }
+
+    fn encoder<'a>(
+        &self,
+        writer: &'a mut dyn Write,
+        settings: &EncoderSettings,
+    ) -> std::io::Result<Box<dyn FinishWrite + 'a>> {
+        if settings.dictionary.is_some() {
+            return Err(dictionary_unsupported(CompressionAlgorithm::Gzip));
+        }
+
+        let level = settings.level_for(CompressionAlgorithm::Gzip) as u32;
+        Ok(Box::new(flate2::write::GzEncoder::new(
+            writer,
+            flate2::Compression::new(level),
+        )))
+    }
+
+    fn decoder<'a>(
+        &self,
+        reader: &'a mut dyn BufRead,
+        dictionary: Option<&CompressionDictionary>,
+    ) -> std::io::Result<Box<dyn Read + 'a>> {
+        if dictionary.is_some() {
+            return Err(dictionary_unsupported(CompressionAlgorithm::Gzip));
+        }
+        // MultiGzDecoder gestisce anche file con più membri concatenati
+        Ok(Box::new(flate2::bufread::MultiGzDecoder::new(reader)))
+    }
+}
+
+/// Backend xz (LZMA2)
+#[cfg(feature = "xz")]
+pub struct XzCompressor;
+
+#[cfg(feature = "xz")]
+impl<W: Write> FinishWrite for xz2::write::XzEncoder<W> {
+    fn finish(self: Box<Self>) -> std::io::Result<()> {
+        (*self).finish().map(|_| ())
+    }
+}
+
+#[cfg(feature = "xz")]
+impl Compressor for XzCompressor {
+    fn algorithm(&self) -> CompressionAlgorithm {
+        CompressionAlgorithm::Xz
+    }
+
+    fn encoder<'a>(
+        &self,
+        writer: &'a mut dyn Write,
+        settings: &EncoderSettings,
+    ) -> std::io::Result<Box<dyn FinishWrite + 'a>> {
+        if settings.dictionary.is_some() {
+            return Err(dictionary_unsupported(CompressionAlgorithm::Xz));
+        }
+
+        let level = settings.level_for(CompressionAlgorithm::Xz) as u32;
+        Ok(Box::new(xz2::write::XzEncoder::new(writer, level)))
+    }
+
+    fn decoder<'a>(
+        &self,
+        reader: &'a mut dyn BufRead,
+        dictionary: Option<&CompressionDictionary>,
+    ) -> std::io::Result<Box<dyn Read + 'a>> {
+        if dictionary.is_some() {
+            return Err(dictionary_unsupported(CompressionAlgorithm::Xz));
+        }
+        Ok(Box::new(xz2::bufread::XzDecoder::new_multi_decoder(reader)))
+    }
+}
+
+/// Backend brotli
+#[cfg(feature = "brotli")]
+pub struct BrotliCompressor;
+
+/// Dimensione della finestra brotli (log2), come il default di `brotli` CLI
+#[cfg(feature = "brotli")]
+const BROTLI_LGWIN: u32 = 22;
+
+/// Writer che conserva il primo errore di I/O: `brotli::CompressorWriter`
+/// ignora gli errori in `into_inner`, così li recuperiamo in `finish`
+#[cfg(feature = "brotli")]
+struct ErrorCapture<'a> {
+    inner: &'a mut dyn Write,
+    error: Option<std::io::Error>,
+}
+
+#[cfg(feature = "brotli")]
+impl Write for ErrorCapture<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.write(buf).inspect_err(|e| {
+            self.error
+                .get_or_insert_with(|| std::io::Error::new(e.kind(), e.to_string()));
+        })
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush().inspect_err(|e| {
+            self.error
+                .get_or_insert_with(|| std::io::Error::new(e.kind(), e.to_string()));
+        })
+    }
+}
+
+#[cfg(feature = "brotli")]
+struct BrotliEncoder<'a>(brotli::CompressorWriter<ErrorCapture<'a>>);
+
+#[cfg(feature = "brotli")]
+impl Write for BrotliEncoder<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(feature = "brotli")]
+impl FinishWrite for BrotliEncoder<'_> {
+    fn finish(self: Box<Self>) -> std::io::Result<()> {
+        let capture = self.0.into_inner();
+        match capture.error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "brotli")]
+impl Compressor for BrotliCompressor {
+    fn algorithm(&self) -> CompressionAlgorithm {
+        CompressionAlgorithm::Brotli
+    }
+
+    fn encoder<'a>(
+        &self,
+        writer: &'a mut dyn Write,
+        settings: &EncoderSettings,
+    ) -> std::io::Result<Box<dyn FinishWrite + 'a>> {
+        if settings.dictionary.is_some() {
+            return Err(dictionary_unsupported(CompressionAlgorithm::Brotli));
+        }
+
+        let quality = settings.level_for(CompressionAlgorithm::Brotli) as u32;
+        let capture = ErrorCapture {
+            inner: writer,
+            error: None,
+        };
+        Ok(Box::new(BrotliEncoder(brotli::CompressorWriter::new(
+            capture,
+            crate::BUFFER_SIZE,
+            quality,
+            BROTLI_LGWIN,
+        ))))
+    }
+
+    fn decoder<'a>(
+        &self,
+        reader: &'a mut dyn BufRead,
+        dictionary: Option<&CompressionDictionary>,
+    ) -> std::io::Result<Box<dyn Read + 'a>> {
+        if dictionary.is_some() {
+            return Err(dictionary_unsupported(CompressionAlgorithm::Brotli));
+        }
+        Ok(Box::new(brotli::Decompressor::new(
+            reader,
+            crate::BUFFER_SIZE,
+        )))
+    }
+}
@@ -0,0 +1,272 @@
+//! Digest SHA-256 dei dati originali e manifest per la verifica approfondita.
+//!
+//! Il manifest elenca i digest in formato `sha256sum` (`<hex>  <nome>`), così il
+//! file `.sha256` scritto accanto all'output può essere controllato anche con
+//! `sha256sum -c` dopo la decompressione. In alternativa lo stesso testo viene
+//! incorporato alla fine del file `.zst` in un frame skippable, che i decoder
+//! zstd ignorano.
+
+use crate::codec::SKIPPABLE_MAGIC_START;
+use crate::{Error, Result};
+use sha2::{Digest as _, Sha256};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Magic del frame skippable con il manifest (0x184D2A5E è della seek table zstd)
+pub const MANIFEST_MAGIC: u32 = SKIPPABLE_MAGIC_START + 0xD;
+
+/// Marcatore finale del manifest incorporato, preceduto dalla lunghezza del testo
+const MANIFEST_FOOTER_TAG: [u8; 4] = *b"FCMF";
+
+/// Estensione del file sidecar
+pub const SIDECAR_EXTENSION: &str = "sha256";
+
+/// Dove salvare il digest dei dati originali in compressione
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestMode {
+    /// File `<output>.sha256` accanto all'output
+    Sidecar,
+    /// Frame skippable alla fine del file .zst (solo zstd)
+    Embedded,
+}
+
+/// Valida la modalità di salvataggio del digest
+pub fn parse_digest_mode(s: &str) -> std::result::Result<DigestMode, String> {
+    match s.to_lowercase().as_str() {
+        "sidecar" | "file" => Ok(DigestMode::Sidecar),
+        "embedded" | "incorporato" => Ok(DigestMode::Embedded),
+        _ => Err(format!("Valore '{}' non valido: usa sidecar o embedded", s)),
+    }
+}
+
+/// Origine del digest atteso in una verifica approfondita
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestSource {
+    /// Frame skippable alla fine del file compresso
+    Embedded,
+    /// File `.sha256` accanto al file compresso
+    Sidecar,
+}
+
+/// Digest SHA-256
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Sha256Digest(pub [u8; 32]);
+
+impl Sha256Digest {
+    /// Calcola il digest di un buffer
+    pub fn of(data: &[u8]) -> Self {
+        Self(Sha256::digest(data).into())
+    }
+
+    /// Rappresentazione esadecimale (minuscola)
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Interpreta 64 cifre esadecimali
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        if hex.len() != 64 || !hex.is_ascii() {
+            return None;
+        }
+        let mut bytes = [0u8; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+        }
+        Some(Self(bytes))
+    }
+}
+
+impl std::fmt::Display for Sha256Digest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+/// Calcolo incrementale del digest mentre i dati vengono letti
+#[derive(Default)]
+pub(crate) struct DigestWriter(Sha256);
+
+impl DigestWriter {
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    pub(crate) fn finish(self) -> Sha256Digest {
+        Sha256Digest(self.0.finalize().into())
+    }
+}
+
+/// Reader che aggiorna il digest con i dati letti
+pub(crate) struct DigestReader<'a, R> {
+    pub(crate) inner: R,
+    pub(crate) digest: &'a mut DigestWriter,
+}
+
+impl<R: Read> Read for DigestReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.digest.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Elenco di digest per nome di file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub entries: Vec<(PathBuf, Sha256Digest)>,
+}
+
+impl Manifest {
+    /// Manifest con un solo file
+    pub fn single(name: &Path, digest: Sha256Digest) -> Self {
+        Self {
+            entries: vec![(name.to_path_buf(), digest)],
+        }
+    }
+
+    /// Aggiunge un file al manifest
+    pub fn push(&mut self, name: &Path, digest: Sha256Digest) {
+        self.entries.push((name.to_path_buf(), digest));
+    }
+
+    /// Digest registrato per un percorso
+    pub fn get(&self, name: &Path) -> Option<Sha256Digest> {
+        self.entries
+            .iter()
+            .find(|(path, _)| path == name)
+            .map(|(_, digest)| *digest)
+    }
+
+    /// Testo in formato `sha256sum`
+    pub fn to_text(&self) -> String {
+        self.entries
+            .iter()
+            .map(|(path, digest)| format!("{}  {}\n", digest, path.display()))
+            .collect()
+    }
+
+    /// Interpreta il testo in formato `sha256sum` (anche con `*` per i file binari)
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut entries = Vec::new();
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            let (hex, name) = line.split_once(' ').ok_or_else(|| invalid_manifest(line))?;
+            let digest = Sha256Digest::from_hex(hex).ok_or_else(|| invalid_manifest(line))?;
+            let name = name.trim_start_matches([' ', '*']);
+            entries.push((PathBuf::from(name), digest));
+        }
+        Ok(Self { entries })
+    }
+
+    /// Scrive il manifest come frame skippable; ritorna i bytes scritti
+    pub fn write_embedded<W: Write>(&self, writer: &mut W) -> Result<u64> {
+        let mut payload = self.to_text().into_bytes();
+        let text_len = payload.len() as u32;
+        payload.extend_from_slice(&text_len.to_le_bytes());
+        payload.extend_from_slice(&MANIFEST_FOOTER_TAG);
+
+        writer.write_all(&MANIFEST_MAGIC.to_le_bytes())?;
+        writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+        writer.write_all(&payload)?;
+        Ok(8 + payload.len() as u64)
+    }
+
+    /// Legge il manifest incorporato alla fine di un file, se presente
+    pub fn read_embedded(path: &Path) -> Result<Option<Self>> {
+        let (mut file, mut file_size) = crate::volume::open_input(path)?;
+        // Nel formato seekable il manifest precede la seek table
+        if let Some((_, table_size)) = crate::SeekTable::read_from(&mut file)? {
+            file_size -= table_size;
+        }
+        Ok(Self::read_embedded_from(&mut file, file_size)?.map(|(manifest, _)| manifest))
+    }
+
+    /// Legge il manifest incorporato che termina a `end`; ritorna anche la
+    /// lunghezza del suo frame
+    pub(crate) fn read_embedded_from<R: Read + Seek + ?Sized>(
+        file: &mut R,
+        end: u64,
+    ) -> Result<Option<(Self, u64)>> {
+        if end < 16 {
+            return Ok(None);
+        }
+
+        let mut footer = [0u8; 8];
+        file.seek(SeekFrom::Start(end - 8))?;
+        file.read_exact(&mut footer)?;
+        if footer[4..] != MANIFEST_FOOTER_TAG {
+            return Ok(None);
+        }
+        let text_len = u64::from(u32::from_le_bytes([
+            footer[0], footer[1], footer[2], footer[3],
+        ]));
+        let Some(frame_start) = end.checked_sub(16 + text_len) else {
+            return Ok(None);
+        };
+
+        // L'header del frame deve corrispondere, altrimenti il marcatore è un caso
+        let mut header = [0u8; 8];
+        file.seek(SeekFrom::Start(frame_start))?;
+        file.read_exact(&mut header)?;
+        let magic = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let frame_size = u64::from(u32::from_le_bytes([
+            header[4], header[5], header[6], header[7],
+        ]));
+        if magic != MANIFEST_MAGIC || frame_size != text_len + 8 {
+            return Ok(None);
+        }
+
+        let mut text = vec![0u8; text_len as usize];
+        file.read_exact(&mut text)?;
+        let text = String::from_utf8(text).map_err(|_| invalid_manifest("UTF-8 non valido"))?;
+        Ok(Some((Self::parse(&text)?, end - frame_start)))
+    }
+
+    /// Scrive il manifest nel file sidecar `<output>.sha256` (in modo atomico)
+    pub fn write_sidecar(&self, output_path: &Path) -> Result<PathBuf> {
+        let sidecar = sidecar_path(output_path);
+        let mut file = crate::AtomicFile::create(&sidecar)?;
+        file.write_all(self.to_text().as_bytes())?;
+        file.commit()?;
+        Ok(sidecar)
+    }
+
+    /// Legge il manifest incorporato o, in mancanza, il sidecar di un file compresso
+    pub fn find(compressed_path: &Path) -> Result<Option<(Self, DigestSource)>> {
+        if let Some(manifest) = Self::read_embedded(compressed_path)? {
+            return Ok(Some((manifest, DigestSource::Embedded)));
+        }
+        // Il sidecar di un set di volumi è quello dell'archivio
+        let archive_path = crate::volume::logical_path(compressed_path);
+        Ok(Self::read_sidecar(&archive_path)?.map(|m| (m, DigestSource::Sidecar)))
+    }
+
+    /// Legge il file sidecar di un file compresso, se esiste
+    pub fn read_sidecar(compressed_path: &Path) -> Result<Option<Self>> {
+        match std::fs::read_to_string(sidecar_path(compressed_path)) {
+            Ok(text) => Self::parse(&text).map(Some),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Rimuove il sidecar di un output riscritto senza `--digest sidecar`: quello
+/// di una compressione precedente non corrisponde più al contenuto
+pub(crate) fn remove_stale_sidecar(output_path: &Path) -> Result<()> {
+    match std::fs::remove_file(sidecar_path(output_path)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Percorso del sidecar: `<file>.sha256`
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(SIDECAR_EXTENSION);
+    PathBuf::from(name)
+}
+
+fn invalid_manifest(detail: &str) -> Error {
+    Error::Corrupt(format!("manifest dei digest non valido: {}", detail))
+}
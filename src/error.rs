@@ -0,0 +1,117 @@
+//! Tipo di errore della libreria.
+//!
+//! Le funzioni pubbliche ritornano [`Error`], così chi chiama può distinguere
+//! un file saltato da un errore vero senza interpretare i messaggi. Il codec e i
+//! reader/writer restano su `std::io::Error`: le conversioni in entrambi i sensi
+//! conservano la variante originale.
+
+use crate::limits::LimitExceeded;
+use std::path::PathBuf;
+
+/// Risultato delle operazioni della libreria
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errori delle operazioni di compressione, decompressione e verifica
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Errore di I/O del sistema o dei codec
+    Io(std::io::Error),
+    /// Il file, la directory o l'entry richiesta non esiste
+    NotFound(String),
+    /// L'output esiste già e `force` non è attivo
+    AlreadyExists(PathBuf),
+    /// Compressione saltata perché non conveniente (es. file già compresso)
+    Skipped { reason: String },
+    /// Argomenti o input non validi
+    InvalidInput(String),
+    /// Il contenuto non è in un formato riconosciuto
+    UnsupportedFormat(String),
+    /// Dati compressi danneggiati
+    Corrupt(String),
+    /// Percorso o link che porterebbe fuori dalla directory di destinazione
+    UnsafePath(String),
+    /// Superato un limite di decompressione
+    LimitExceeded(LimitExceeded),
+    /// Operazione interrotta tramite il flag di annullamento
+    Cancelled,
+}
+
+impl Error {
+    /// Categoria `std::io::ErrorKind` equivalente
+    pub fn kind(&self) -> std::io::ErrorKind {
+        use std::io::ErrorKind;
+        match self {
+            Error::Io(e) => e.kind(),
+            Error::NotFound(_) => ErrorKind::NotFound,
+            Error::AlreadyExists(_) => ErrorKind::AlreadyExists,
+            Error::Skipped { .. } | Error::InvalidInput(_) | Error::UnsupportedFormat(_) => {
+                ErrorKind::InvalidInput
+            }
+            Error::Corrupt(_) | Error::UnsafePath(_) => ErrorKind::InvalidData,
+            Error::LimitExceeded(_) => ErrorKind::Other,
+            Error::Cancelled => ErrorKind::Interrupted,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::NotFound(message)
+            | Error::InvalidInput(message)
+            | Error::UnsupportedFormat(message)
+            | Error::UnsafePath(message) => write!(f, "{}", message),
+            Error::AlreadyExists(path) => write!(
+                f,
+                "L'output {:?} esiste già. Usa --force per sovrascrivere.",
+                path
+            ),
+            Error::Skipped { reason } => write!(f, "Skip compressione: {}", reason),
+            Error::Corrupt(message) => write!(f, "File corrotto: {}", message),
+            Error::LimitExceeded(limit) => write!(f, "{}", limit),
+            Error::Cancelled => write!(f, "Operazione annullata"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::LimitExceeded(limit) => Some(limit),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        // Un Error passato attraverso codice std::io torna alla sua variante
+        if err.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            let inner = err.into_inner().expect("payload verificato sopra");
+            return *inner.downcast::<Error>().expect("payload verificato sopra");
+        }
+        match LimitExceeded::from_io_error(&err) {
+            Some(limit) => Error::LimitExceeded(*limit),
+            None => Error::Io(err),
+        }
+    }
+}
+
+impl From<LimitExceeded> for Error {
+    fn from(limit: LimitExceeded) -> Self {
+        Error::LimitExceeded(limit)
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(e) => e,
+            Error::LimitExceeded(limit) => limit.into(),
+            other => std::io::Error::new(other.kind(), other),
+        }
+    }
+}
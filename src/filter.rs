@@ -0,0 +1,128 @@
+//! Filtri include/exclude sui percorsi, basati su glob.
+//!
+//! Un pattern senza `/` viene confrontato con il nome di ogni componente
+//! (come in `.gitignore`), uno con `/` con il percorso relativo completo.
+//! Un pattern che corrisponde a una directory vale anche per tutto il suo contenuto.
+
+use glob::{MatchOptions, Pattern};
+use std::path::{Component, Path};
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Singolo pattern glob compilato
+#[derive(Debug, Clone)]
+struct PathPattern {
+    pattern: Pattern,
+    /// true se il pattern non contiene `/` e va confrontato con il solo nome
+    name_only: bool,
+}
+
+impl PathPattern {
+    fn new(text: &str) -> std::io::Result<Self> {
+        let text = text.trim_start_matches("./").trim_end_matches('/');
+        let pattern = Pattern::new(text).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Pattern '{}' non valido: {}", text, e),
+            )
+        })?;
+
+        Ok(Self {
+            pattern,
+            name_only: !text.contains('/'),
+        })
+    }
+
+    /// Confronta il percorso e tutte le directory che lo contengono
+    fn matches(&self, relative: &str) -> bool {
+        let mut end = relative.len();
+        loop {
+            let prefix = &relative[..end];
+            let candidate = if self.name_only {
+                prefix.rsplit('/').next().unwrap_or(prefix)
+            } else {
+                prefix
+            };
+            if self.pattern.matches_with(candidate, MATCH_OPTIONS) {
+                return true;
+            }
+
+            match prefix.rfind('/') {
+                Some(pos) => end = pos,
+                None => return false,
+            }
+        }
+    }
+}
+
+/// Filtro di percorsi con liste di pattern da includere ed escludere.
+///
+/// Senza pattern di inclusione tutto è incluso; l'esclusione vince sempre.
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    include: Vec<PathPattern>,
+    exclude: Vec<PathPattern>,
+}
+
+impl PathFilter {
+    /// Crea un filtro vuoto che accetta tutti i percorsi
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compila le liste di pattern (es. `etc/**`, `*.log`, `node_modules`)
+    pub fn from_patterns<S: AsRef<str>>(include: &[S], exclude: &[S]) -> std::io::Result<Self> {
+        Ok(Self {
+            include: include
+                .iter()
+                .map(|p| PathPattern::new(p.as_ref()))
+                .collect::<std::io::Result<_>>()?,
+            exclude: exclude
+                .iter()
+                .map(|p| PathPattern::new(p.as_ref()))
+                .collect::<std::io::Result<_>>()?,
+        })
+    }
+
+    /// Ritorna true se il filtro non ha pattern
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Ritorna true se il percorso relativo corrisponde a un pattern di esclusione.
+    ///
+    /// Utile per non scendere affatto in directory escluse (es. `node_modules`).
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        if self.exclude.is_empty() {
+            return false;
+        }
+        let relative = normalize(path);
+        self.exclude.iter().any(|p| p.matches(&relative))
+    }
+
+    /// Ritorna true se il percorso relativo supera il filtro
+    pub fn matches(&self, path: &Path) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+
+        let relative = normalize(path);
+        let included = self.include.is_empty() || self.include.iter().any(|p| p.matches(&relative));
+        included && !self.exclude.iter().any(|p| p.matches(&relative))
+    }
+}
+
+/// Percorso relativo con separatori `/`, senza `./` iniziali né `/` finali
+pub(crate) fn normalize(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
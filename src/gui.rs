@@ -1,10 +1,10 @@
 use eframe::egui;
 use file_compressor::{
-    compress_directory, compress_file, decompress_file, format_ratio, format_size, verify_zst,
-    CompressOptions, DecompressOptions,
+    compress_directory, compress_file, decompress_file, format_ratio, format_size,
+    verify_zst_with_options, CompressOptions, CompressionAlgorithm, DecompressOptions,
 };
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::Arc;
 use std::thread;
@@ -79,6 +79,7 @@ struct Strings {
     files_valid_skipped: &'static str,
     files_valid_corrupt_skipped: &'static str,
     not_zst_file: &'static str,
+    unknown_format: &'static str,
     valid: &'static str,
 }
 
@@ -118,6 +119,7 @@ const STRINGS_IT: Strings = Strings {
     files_valid_skipped: "{} file validi, {} saltati",
     files_valid_corrupt_skipped: "{} validi, {} corrotti, {} saltati",
     not_zst_file: "non è un file .zst",
+    unknown_format: "formato compresso non riconosciuto",
     valid: "valido",
 };
 
@@ -157,6 +159,7 @@ const STRINGS_EN: Strings = Strings {
     files_valid_skipped: "{} valid files, {} skipped",
     files_valid_corrupt_skipped: "{} valid, {} corrupt, {} skipped",
     not_zst_file: "not a .zst file",
+    unknown_format: "unrecognized compressed format",
     valid: "valid",
 };
 
@@ -286,7 +289,7 @@ struct TaskResult {
 
 struct TaskContext {
     progress_tx: Sender<TaskMessage>,
-    cancel_flag: Arc<AtomicU64>,
+    cancel_flag: Arc<AtomicBool>,
 }
 
 struct CompressorApp {
@@ -299,7 +302,7 @@ struct CompressorApp {
     status_message: String,
     is_processing: bool,
     result_receiver: Option<Receiver<TaskMessage>>,
-    cancel_flag: Arc<AtomicU64>,
+    cancel_flag: Arc<AtomicBool>,
     progress: f32,
     show_details: bool,
     last_details: Vec<String>,
@@ -320,7 +323,7 @@ impl Default for CompressorApp {
             status_message: strings.drag_or_select.to_string(),
             is_processing: false,
             result_receiver: None,
-            cancel_flag: Arc::new(AtomicU64::new(0)),
+            cancel_flag: Arc::new(AtomicBool::new(false)),
             progress: 0.0,
             show_details: false,
             last_details: Vec::new(),
@@ -345,7 +348,7 @@ impl CompressorApp {
         self.result_receiver = Some(rx);
         self.is_processing = true;
         self.progress = 0.0;
-        self.cancel_flag.store(0, Ordering::Relaxed);
+        self.cancel_flag.store(false, Ordering::Relaxed);
 
         let files = self.selected_files.clone();
         let level = self.compression_level;
@@ -384,7 +387,7 @@ impl CompressorApp {
 
     fn cancel_operation(&mut self) {
         if self.is_processing {
-            self.cancel_flag.store(1, Ordering::Relaxed);
+            self.cancel_flag.store(true, Ordering::Relaxed);
             self.status_message = "Annullamento in corso...".to_string();
         }
     }
@@ -1095,9 +1098,11 @@ fn compress_files(
     let mut total_original = 0u64;
     let mut total_compressed = 0u64;
 
+    // Il flag interrompe anche la compressione di un singolo file grande
     let mut options = CompressOptions::new(level)
         .with_force(force)
-        .with_parallel(parallel);
+        .with_parallel(parallel)
+        .with_cancel_flag(Arc::clone(&ctx.cancel_flag));
 
     if let Some(dir) = output_dir {
         options = options.with_output_path(dir);
@@ -1106,7 +1111,7 @@ fn compress_files(
     let total_files = files.len();
     for (idx, file) in files.iter().enumerate() {
         // Controlla flag cancellazione
-        if ctx.cancel_flag.load(Ordering::Relaxed) != 0 {
+        if ctx.cancel_flag.load(Ordering::Relaxed) {
             details.push("❌ Operazione annullata dall'utente".to_string());
             break;
         }
@@ -1199,7 +1204,9 @@ fn decompress_files(
     let mut error_count = 0;
     let mut details = Vec::new();
 
-    let mut options = DecompressOptions::new().with_force(force);
+    let mut options = DecompressOptions::new()
+        .with_force(force)
+        .with_cancel_flag(Arc::clone(&ctx.cancel_flag));
 
     if let Some(dir) = output_dir {
         options = options.with_output_path(dir);
@@ -1208,7 +1215,7 @@ fn decompress_files(
     let total_files = files.len();
     for (idx, file) in files.iter().enumerate() {
         // Controlla flag cancellazione
-        if ctx.cancel_flag.load(Ordering::Relaxed) != 0 {
+        if ctx.cancel_flag.load(Ordering::Relaxed) {
             details.push("❌ Operazione annullata dall'utente".to_string());
             break;
         }
@@ -1216,13 +1223,12 @@ fn decompress_files(
         // Invia progress
         let progress = (idx as f32) / (total_files as f32);
         let _ = ctx.progress_tx.send(TaskMessage::Progress(progress));
-        let ext = file.extension().and_then(|e| e.to_str()).unwrap_or("");
-        if ext != "zst" {
+        if !matches!(CompressionAlgorithm::detect(file), Ok(Some(_))) {
             error_count += 1;
             details.push(format!(
                 "⏭️ {:?}: {}",
                 file.file_name().unwrap_or_default(),
-                strings.not_zst_file
+                strings.unknown_format
             ));
             continue;
         }
@@ -1279,10 +1285,12 @@ fn verify_files(files: &[PathBuf], lang: Language, ctx: &TaskContext) -> TaskRes
     let mut skipped_count = 0;
     let mut details = Vec::new();
 
+    let options = DecompressOptions::new().with_cancel_flag(Arc::clone(&ctx.cancel_flag));
+
     let total_files = files.len();
     for (idx, file) in files.iter().enumerate() {
         // Controlla flag cancellazione
-        if ctx.cancel_flag.load(Ordering::Relaxed) != 0 {
+        if ctx.cancel_flag.load(Ordering::Relaxed) {
             details.push("❌ Operazione annullata dall'utente".to_string());
             break;
         }
@@ -1290,8 +1298,10 @@ fn verify_files(files: &[PathBuf], lang: Language, ctx: &TaskContext) -> TaskRes
         // Invia progress
         let progress = (idx as f32) / (total_files as f32);
         let _ = ctx.progress_tx.send(TaskMessage::Progress(progress));
-        let ext = file.extension().and_then(|e| e.to_str()).unwrap_or("");
-        if ext != "zst" {
+        if !matches!(
+            CompressionAlgorithm::detect(file),
+            Ok(Some(CompressionAlgorithm::Zstd))
+        ) {
             skipped_count += 1;
             details.push(format!(
                 "⏭️ {:?}: {}",
@@ -1301,7 +1311,7 @@ fn verify_files(files: &[PathBuf], lang: Language, ctx: &TaskContext) -> TaskRes
             continue;
         }
 
-        match verify_zst(file, None) {
+        match verify_zst_with_options(file, &options) {
             Ok(result) => {
                 valid_count += 1;
                 details.push(format!(
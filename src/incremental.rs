@@ -0,0 +1,446 @@
+//! Backup incrementali di una directory.
+//!
+//! Accanto a un archivio di backup viene scritto lo stato `<archivio>.state`:
+//! per ogni elemento della directory il tipo e, per i file, dimensione, data di
+//! modifica e SHA-256 del contenuto. Un backup incrementale parte dallo stato
+//! dell'archivio precedente e archivia solo i file nuovi o con dimensione o data
+//! cambiate (più le directory nuove e i link nuovi o modificati); gli elementi
+//! spariti vengono elencati come eliminati nel suo stato. Lo stato ricorda anche
+//! l'archivio precedente, quindi da un archivio si risale la catena fino al
+//! backup completo e il ripristino la riapplica in ordine.
+
+use crate::{Error, Manifest, Result, Sha256Digest, WalkEntry, WalkKind};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Estensione del file di stato
+pub const STATE_EXTENSION: &str = "state";
+
+/// Identificatore del formato del file di stato
+const STATE_FORMAT: &str = "file_compressor-backup";
+
+/// Versione del formato del file di stato
+const STATE_VERSION: u64 = 1;
+
+/// Archivi al massimo in una catena (protegge da stati che si riferiscono in cerchio)
+const MAX_CHAIN_LENGTH: usize = 10_000;
+
+/// Tipo di backup di una directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackupMode {
+    /// Archivia tutto e scrive lo stato, base di backup incrementali successivi
+    Full,
+    /// Archivia solo le differenze rispetto all'archivio di backup indicato
+    Incremental(PathBuf),
+}
+
+/// Elemento della directory registrato nello stato
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateEntry {
+    File {
+        size: u64,
+        /// Data di modifica in nanosecondi dal 1970; `None` se non disponibile
+        mtime_ns: Option<u64>,
+        sha256: Sha256Digest,
+    },
+    Directory,
+    Symlink {
+        target: PathBuf,
+    },
+}
+
+/// Stato di una directory dopo un backup
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackupState {
+    /// Archivio precedente della catena (relativo alla directory dell'archivio);
+    /// `None` per un backup completo
+    pub base: Option<PathBuf>,
+    /// Elementi eliminati rispetto all'archivio precedente
+    pub deleted: Vec<PathBuf>,
+    /// Elementi della directory per percorso relativo
+    pub entries: BTreeMap<PathBuf, StateEntry>,
+}
+
+impl BackupState {
+    /// Legge lo stato di un archivio di backup (`<archivio>.state`)
+    pub fn for_archive(archive_path: &Path) -> Result<Self> {
+        let path = state_path(&crate::volume::logical_path(archive_path));
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(Error::NotFound(format!(
+                "Stato del backup {:?} non trovato: {:?} non è un archivio di backup",
+                path, archive_path
+            ))),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Scrive lo stato accanto all'archivio (in modo atomico); ritorna il percorso
+    pub fn write_for_archive(&self, archive_path: &Path) -> Result<PathBuf> {
+        let path = state_path(archive_path);
+        let mut file = crate::AtomicFile::create(&path)?;
+        file.write_all(self.to_json().as_bytes())?;
+        file.commit()?;
+        Ok(path)
+    }
+
+    /// Testo JSON dello stato
+    pub fn to_json(&self) -> String {
+        let entries: Vec<Value> = self
+            .entries
+            .iter()
+            .map(|(path, entry)| {
+                let mut item = Map::new();
+                item.insert("path".into(), path_value(path));
+                match entry {
+                    StateEntry::File {
+                        size,
+                        mtime_ns,
+                        sha256,
+                    } => {
+                        item.insert("type".into(), json!("file"));
+                        item.insert("size".into(), json!(size));
+                        item.insert("mtime_ns".into(), json!(mtime_ns));
+                        item.insert("sha256".into(), json!(sha256.to_hex()));
+                    }
+                    StateEntry::Directory => {
+                        item.insert("type".into(), json!("dir"));
+                    }
+                    StateEntry::Symlink { target } => {
+                        item.insert("type".into(), json!("symlink"));
+                        item.insert("target".into(), path_value(target));
+                    }
+                }
+                Value::Object(item)
+            })
+            .collect();
+        let document = json!({
+            "format": STATE_FORMAT,
+            "version": STATE_VERSION,
+            "base": self.base.as_deref().map(path_value),
+            "deleted": self.deleted.iter().map(|p| path_value(p)).collect::<Vec<_>>(),
+            "entries": entries,
+        });
+        let mut text = serde_json::to_string_pretty(&document).expect("JSON serializzabile");
+        text.push('\n');
+        text
+    }
+
+    /// Interpreta il testo JSON di uno stato
+    pub fn parse(text: &str) -> Result<Self> {
+        let document: Value =
+            serde_json::from_str(text).map_err(|e| invalid_state(&e.to_string()))?;
+        if document["format"] != STATE_FORMAT {
+            return Err(invalid_state("formato sconosciuto"));
+        }
+        if document["version"] != STATE_VERSION {
+            return Err(invalid_state(&format!(
+                "versione {} non supportata",
+                document["version"]
+            )));
+        }
+
+        let base = match &document["base"] {
+            Value::Null => None,
+            value => Some(parse_path(value)?),
+        };
+        let deleted = match &document["deleted"] {
+            Value::Array(items) => items.iter().map(parse_path).collect::<Result<_>>()?,
+            _ => return Err(invalid_state("elenco dei file eliminati mancante")),
+        };
+
+        let Value::Array(items) = &document["entries"] else {
+            return Err(invalid_state("elenco degli elementi mancante"));
+        };
+        let mut entries = BTreeMap::new();
+        for item in items {
+            let path = parse_path(&item["path"])?;
+            let entry = match item["type"].as_str() {
+                Some("file") => StateEntry::File {
+                    size: item["size"]
+                        .as_u64()
+                        .ok_or_else(|| invalid_state("dimensione di un file"))?,
+                    mtime_ns: item["mtime_ns"].as_u64(),
+                    sha256: item["sha256"]
+                        .as_str()
+                        .and_then(Sha256Digest::from_hex)
+                        .ok_or_else(|| invalid_state("SHA-256 di un file"))?,
+                },
+                Some("dir") => StateEntry::Directory,
+                Some("symlink") => StateEntry::Symlink {
+                    target: parse_path(&item["target"])?,
+                },
+                _ => return Err(invalid_state("tipo di un elemento")),
+            };
+            entries.insert(path, entry);
+        }
+
+        Ok(Self {
+            base,
+            deleted,
+            entries,
+        })
+    }
+}
+
+/// Percorso del file di stato: `<archivio>.state`
+pub fn state_path(archive_path: &Path) -> PathBuf {
+    let mut name = archive_path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(STATE_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// Archivi della catena che termina con `archive_path`, dal backup completo a
+/// quello indicato, ciascuno con il proprio stato
+pub fn backup_chain(archive_path: &Path) -> Result<Vec<(PathBuf, BackupState)>> {
+    let mut chain = Vec::new();
+    let mut seen = HashSet::new();
+    let mut current = archive_input(archive_path);
+    loop {
+        let state = BackupState::for_archive(&current)?;
+        let logical = crate::volume::logical_path(&current);
+        let identity = logical.canonicalize().unwrap_or_else(|_| logical.clone());
+        if !seen.insert(identity) || chain.len() == MAX_CHAIN_LENGTH {
+            return Err(invalid_state(&format!(
+                "la catena di backup che porta a {:?} contiene un ciclo",
+                archive_path
+            )));
+        }
+        let base = state
+            .base
+            .as_ref()
+            .map(|base| archive_input(&logical.parent().unwrap_or(Path::new(".")).join(base)));
+        chain.push((current, state));
+        match base {
+            Some(base) => current = base,
+            None => break,
+        }
+    }
+    chain.reverse();
+    Ok(chain)
+}
+
+/// File da aprire per leggere un archivio: il primo volume se l'archivio è
+/// stato diviso in volumi
+fn archive_input(archive_path: &Path) -> PathBuf {
+    let first_volume = crate::volume::volume_path(archive_path, 1);
+    if !archive_path.exists() && first_volume.exists() {
+        first_volume
+    } else {
+        archive_path.to_path_buf()
+    }
+}
+
+/// Controlla che un percorso registrato nello stato resti dentro la directory
+pub(crate) fn checked_state_path(path: &Path) -> Result<&Path> {
+    let is_safe = !path.as_os_str().is_empty()
+        && path.components().all(|c| matches!(c, Component::Normal(_)));
+    if is_safe {
+        Ok(path)
+    } else {
+        Err(Error::UnsafePath(format!(
+            "Percorso non sicuro nello stato del backup: {:?}",
+            path
+        )))
+    }
+}
+
+/// Elemento della directory da confrontare con lo stato precedente
+struct Snapshot {
+    path: PathBuf,
+    kind: WalkKind,
+    size: u64,
+    mtime_ns: Option<u64>,
+    target: Option<PathBuf>,
+}
+
+/// Backup in corso: elementi della directory e stato precedente
+pub(crate) struct BackupPlan {
+    base: Option<PathBuf>,
+    previous: BTreeMap<PathBuf, StateEntry>,
+    current: Vec<Snapshot>,
+}
+
+impl BackupPlan {
+    /// Prepara il backup di `dir_path` in `output_path` e toglie da `entries`
+    /// gli elementi invariati rispetto all'archivio di base
+    pub(crate) fn new(
+        mode: &BackupMode,
+        dir_path: &Path,
+        output_path: &Path,
+        entries: &mut Vec<WalkEntry>,
+    ) -> Result<Self> {
+        let (base, previous) = match mode {
+            BackupMode::Full => (None, BTreeMap::new()),
+            BackupMode::Incremental(base_archive) => {
+                let state = BackupState::for_archive(base_archive)?;
+                let base_archive = crate::volume::logical_path(base_archive);
+                let base_archive = base_archive.canonicalize().unwrap_or(base_archive);
+                let base = relative_base(&base_archive, output_path);
+                // Sovrascrivere l'archivio di base spezzerebbe la catena
+                if base.as_os_str() == output_path.file_name().unwrap_or_default() {
+                    return Err(Error::InvalidInput(format!(
+                        "Il backup incrementale non può sovrascrivere l'archivio di base {:?}",
+                        base_archive
+                    )));
+                }
+                (Some(base), state.entries)
+            }
+        };
+
+        let mut current = Vec::with_capacity(entries.len());
+        let mut changed = Vec::with_capacity(entries.len());
+        for entry in entries.iter() {
+            let snapshot = Snapshot::of(entry, dir_path)?;
+            changed.push(snapshot.changed_since(previous.get(&snapshot.path)));
+            current.push(snapshot);
+        }
+        let mut changed = changed.into_iter();
+        entries.retain(|_| changed.next().unwrap_or(true));
+
+        Ok(Self {
+            base,
+            previous,
+            current,
+        })
+    }
+
+    /// Scrive lo stato accanto all'archivio: i digest dei file archiviati sono
+    /// nel manifest, quelli dei file invariati vengono dallo stato precedente
+    pub(crate) fn finish(self, output_path: &Path, manifest: &Manifest) -> Result<PathBuf> {
+        let digests: HashMap<&Path, Sha256Digest> = manifest
+            .entries
+            .iter()
+            .map(|(path, digest)| (path.as_path(), *digest))
+            .collect();
+
+        let mut entries = BTreeMap::new();
+        for snapshot in self.current {
+            let entry = match snapshot.kind {
+                WalkKind::Directory => StateEntry::Directory,
+                WalkKind::Symlink => StateEntry::Symlink {
+                    target: snapshot.target.unwrap_or_default(),
+                },
+                WalkKind::File => {
+                    let sha256 = match digests.get(snapshot.path.as_path()) {
+                        Some(digest) => *digest,
+                        None => match self.previous.get(&snapshot.path) {
+                            Some(StateEntry::File { sha256, .. }) => *sha256,
+                            _ => {
+                                return Err(Error::InvalidInput(format!(
+                                    "Digest di {:?} mancante nel backup",
+                                    snapshot.path
+                                )))
+                            }
+                        },
+                    };
+                    StateEntry::File {
+                        size: snapshot.size,
+                        mtime_ns: snapshot.mtime_ns,
+                        sha256,
+                    }
+                }
+            };
+            entries.insert(snapshot.path, entry);
+        }
+
+        // Un elemento che ha cambiato tipo (directory diventata file o viceversa)
+        // va rimosso prima di estrarre quello nuovo
+        let deleted = self
+            .previous
+            .iter()
+            .filter(|(path, previous)| {
+                entries.get(*path).is_none_or(|current| {
+                    std::mem::discriminant(*previous) != std::mem::discriminant(current)
+                })
+            })
+            .map(|(path, _)| path.clone())
+            .collect();
+        let state = BackupState {
+            base: self.base,
+            deleted,
+            entries,
+        };
+        state.write_for_archive(output_path)
+    }
+}
+
+impl Snapshot {
+    fn of(entry: &WalkEntry, dir_path: &Path) -> Result<Self> {
+        let path = entry
+            .path
+            .strip_prefix(dir_path)
+            .map_err(|e| Error::InvalidInput(e.to_string()))?
+            .to_path_buf();
+        let (mtime_ns, target) = match entry.kind {
+            WalkKind::File => (modified_ns(&std::fs::metadata(&entry.path)?), None),
+            WalkKind::Symlink => (None, Some(std::fs::read_link(&entry.path)?)),
+            WalkKind::Directory => (None, None),
+        };
+        Ok(Self {
+            path,
+            kind: entry.kind,
+            size: entry.size,
+            mtime_ns,
+            target,
+        })
+    }
+
+    /// Ritorna true se l'elemento va archiviato: nuovo, di tipo diverso o
+    /// modificato (file con dimensione o data diverse, link con altro target)
+    fn changed_since(&self, previous: Option<&StateEntry>) -> bool {
+        match (self.kind, previous) {
+            (WalkKind::File, Some(StateEntry::File { size, mtime_ns, .. })) => {
+                *size != self.size || mtime_ns.is_none() || *mtime_ns != self.mtime_ns
+            }
+            (WalkKind::Directory, Some(StateEntry::Directory)) => false,
+            (WalkKind::Symlink, Some(StateEntry::Symlink { target })) => {
+                Some(target) != self.target.as_ref()
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Data di modifica in nanosecondi dal 1970
+fn modified_ns(metadata: &std::fs::Metadata) -> Option<u64> {
+    let modified = metadata.modified().ok()?;
+    let since_epoch = modified.duration_since(UNIX_EPOCH).ok()?;
+    u64::try_from(since_epoch.as_nanos()).ok()
+}
+
+/// Archivio di base come lo registra lo stato: solo il nome se sta nella stessa
+/// directory del nuovo archivio, altrimenti il percorso completo
+fn relative_base(base_archive: &Path, output_path: &Path) -> PathBuf {
+    let output_dir = output_path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let same_dir = match (base_archive.parent(), output_dir.canonicalize()) {
+        (Some(base_dir), Ok(output_dir)) => base_dir == output_dir,
+        _ => false,
+    };
+    match base_archive.file_name() {
+        Some(name) if same_dir => PathBuf::from(name),
+        _ => base_archive.to_path_buf(),
+    }
+}
+
+fn path_value(path: &Path) -> Value {
+    Value::String(path.to_string_lossy().into_owned())
+}
+
+fn parse_path(value: &Value) -> Result<PathBuf> {
+    value
+        .as_str()
+        .map(PathBuf::from)
+        .ok_or_else(|| invalid_state("percorso non valido"))
+}
+
+fn invalid_state(detail: &str) -> Error {
+    Error::Corrupt(format!("stato del backup non valido: {}", detail))
+}
@@ -0,0 +1,173 @@
+//! Indice dei membri di un archivio tar seekable.
+//!
+//! Un archivio compresso con il formato seekable (vedi [`crate::seekable`])
+//! inizia ogni membro, o gruppo di membri piccoli, in un nuovo frame e termina
+//! con l'indice: per ogni membro il percorso e la posizione del suo header nel
+//! tar decompresso. Con la seek table la posizione porta direttamente al frame
+//! da decomprimere, senza leggere l'archivio dall'inizio. L'indice è un frame
+//! skippable prima del manifest incorporato e della seek table, quindi zstd e
+//! tar continuano a leggere l'archivio come uno stream normale.
+
+use crate::codec::SKIPPABLE_MAGIC_START;
+use crate::digest::Manifest;
+use crate::{filter, Error, Result, SeekTable};
+use std::borrow::Cow;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Magic del frame skippable con l'indice dei membri
+pub const INDEX_MAGIC: u32 = SKIPPABLE_MAGIC_START + 0xB;
+
+/// Marcatore finale dell'indice, preceduto dalla lunghezza delle entry
+const INDEX_FOOTER_TAG: [u8; 4] = *b"FCIX";
+
+/// Membro dell'archivio
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// Percorso nel tar
+    pub path: PathBuf,
+    /// Posizione del primo header del membro nel tar decompresso
+    pub offset: u64,
+}
+
+/// Indice dei membri di un archivio tar seekable, in ordine di archiviazione
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArchiveIndex {
+    pub entries: Vec<IndexEntry>,
+}
+
+impl ArchiveIndex {
+    /// Aggiunge un membro all'indice
+    pub fn push(&mut self, path: &Path, offset: u64) {
+        self.entries.push(IndexEntry {
+            path: path.to_path_buf(),
+            offset,
+        });
+    }
+
+    /// Membro con il percorso indicato (confrontato in forma normalizzata)
+    pub fn get(&self, path: &Path) -> Option<&IndexEntry> {
+        let wanted = filter::normalize(path);
+        self.entries
+            .iter()
+            .find(|entry| filter::normalize(&entry.path) == wanted)
+    }
+
+    /// Scrive l'indice come frame skippable; ritorna i bytes scritti
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<u64> {
+        let mut payload = Vec::new();
+        for entry in &self.entries {
+            let path = path_to_bytes(&entry.path);
+            payload.extend_from_slice(&entry.offset.to_le_bytes());
+            payload.extend_from_slice(&(path.len() as u32).to_le_bytes());
+            payload.extend_from_slice(&path);
+        }
+        let entries_len = payload.len() as u32;
+        payload.extend_from_slice(&entries_len.to_le_bytes());
+        payload.extend_from_slice(&INDEX_FOOTER_TAG);
+
+        writer.write_all(&INDEX_MAGIC.to_le_bytes())?;
+        writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+        writer.write_all(&payload)?;
+        Ok(8 + payload.len() as u64)
+    }
+
+    /// Legge l'indice di un archivio, se presente
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        let (mut file, file_size) = crate::volume::open_input(path)?;
+        Self::read_from(&mut file, file_size)
+    }
+
+    /// Legge l'indice di un archivio lungo `size` bytes: senza seek table
+    /// l'archivio non è seekable e l'indice non viene cercato
+    pub fn read_from<R: Read + Seek + ?Sized>(reader: &mut R, size: u64) -> Result<Option<Self>> {
+        let Some((_, table_size)) = SeekTable::read_from(reader)? else {
+            return Ok(None);
+        };
+        let mut end = size - table_size;
+        if let Some((_, manifest_size)) = Manifest::read_embedded_from(reader, end)? {
+            end -= manifest_size;
+        }
+        if end < 16 {
+            return Ok(None);
+        }
+
+        let mut footer = [0u8; 8];
+        reader.seek(SeekFrom::Start(end - 8))?;
+        reader.read_exact(&mut footer)?;
+        if footer[4..] != INDEX_FOOTER_TAG {
+            return Ok(None);
+        }
+        let entries_len = u64::from(u32::from_le_bytes([
+            footer[0], footer[1], footer[2], footer[3],
+        ]));
+        let Some(frame_start) = end.checked_sub(16 + entries_len) else {
+            return Err(invalid_index("lunghezza oltre l'inizio del file"));
+        };
+
+        let mut header = [0u8; 8];
+        reader.seek(SeekFrom::Start(frame_start))?;
+        reader.read_exact(&mut header)?;
+        let magic = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let frame_size = u64::from(u32::from_le_bytes([
+            header[4], header[5], header[6], header[7],
+        ]));
+        if magic != INDEX_MAGIC || frame_size != entries_len + 8 {
+            return Err(invalid_index("header del frame"));
+        }
+
+        let mut payload = vec![0u8; entries_len as usize];
+        reader.read_exact(&mut payload)?;
+        Self::parse(&payload).map(Some)
+    }
+
+    fn parse(mut payload: &[u8]) -> Result<Self> {
+        let mut index = Self::default();
+        while !payload.is_empty() {
+            if payload.len() < 12 {
+                return Err(invalid_index("entry troncata"));
+            }
+            let (fixed, rest) = payload.split_at(12);
+            let offset = u64::from_le_bytes(fixed[..8].try_into().expect("8 bytes"));
+            let path_len = u32::from_le_bytes(fixed[8..].try_into().expect("4 bytes")) as usize;
+            if rest.len() < path_len {
+                return Err(invalid_index("percorso troncato"));
+            }
+            let (path, rest) = rest.split_at(path_len);
+            index.push(&bytes_to_path(path)?, offset);
+            payload = rest;
+        }
+        Ok(index)
+    }
+}
+
+#[cfg(unix)]
+fn path_to_bytes(path: &Path) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn path_to_bytes(path: &Path) -> Cow<'_, [u8]> {
+    match path.to_string_lossy() {
+        Cow::Borrowed(s) => Cow::Borrowed(s.as_bytes()),
+        Cow::Owned(s) => Cow::Owned(s.into_bytes()),
+    }
+}
+
+#[cfg(unix)]
+fn bytes_to_path(bytes: &[u8]) -> Result<PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(PathBuf::from(std::ffi::OsStr::from_bytes(bytes)))
+}
+
+#[cfg(not(unix))]
+fn bytes_to_path(bytes: &[u8]) -> Result<PathBuf> {
+    std::str::from_utf8(bytes)
+        .map(PathBuf::from)
+        .map_err(|_| invalid_index("percorso non UTF-8"))
+}
+
+fn invalid_index(detail: &str) -> Error {
+    Error::Corrupt(format!("indice dell'archivio non valido: {}", detail))
+}
@@ -2,12 +2,52 @@
 //!
 //! Fornisce funzioni per comprimere e decomprimere file singoli, directory,
 //! e archivi tar.zst con ottimizzazioni intelligenti basate sul contenuto.
-
+//! Altri algoritmi (lz4, gzip, xz, brotli) sono disponibili tramite il modulo [`codec`].
+
+use digest::{DigestReader, DigestWriter};
+use incremental::BackupPlan;
+use limits::{CompressedSize, LimitedReader};
+use prefetch::{PrefetchedFile, ReadAhead};
+use progress::Progress;
+use seekable::{ParallelDecoder, SeekableWriter};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsString;
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Read, Write};
-use std::path::{Path, PathBuf};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use tar::{Archive, Builder};
+use volume::{open_input, ArchiveOutput};
+
+pub mod atomic;
+pub mod codec;
+pub mod digest;
+pub mod error;
+pub mod filter;
+pub mod incremental;
+pub mod index;
+pub mod limits;
+pub mod prefetch;
+pub mod progress;
+pub mod seekable;
+pub mod volume;
+
+pub use atomic::{cleanup_partial_outputs, AtomicFile};
+pub use codec::{
+    parse_algorithm, CompressionAlgorithm, Compressor, EncoderSettings, FinishWrite,
+    ZstdCompressor, ZstdFrameHeader,
+};
+pub use digest::{parse_digest_mode, DigestMode, DigestSource, Manifest, Sha256Digest};
+pub use error::{Error, Result};
+pub use filter::PathFilter;
+pub use incremental::{backup_chain, BackupMode, BackupState, StateEntry};
+pub use index::{ArchiveIndex, IndexEntry};
+pub use limits::{DecompressionLimits, LimitExceeded, LimitKind};
+pub use progress::{ProgressCallback, ProgressEvent, ProgressHandler};
+pub use seekable::{SeekTable, SeekableReader};
+pub use volume::{VolumeReader, VolumeSet};
 
 /// Tipo di file rilevato per ottimizzazioni
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -29,7 +69,7 @@ pub enum FileType {
 }
 
 /// Strategia di compressione zstd
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CompressionStrategy {
     /// Fast - velocità massima
     Fast,
@@ -46,17 +86,21 @@ pub enum CompressionStrategy {
 }
 
 impl CompressionStrategy {
-    /// Converte in valore numerico per strategia zstd
-    /// Note: zstd seleziona automaticamente la strategia in base al livello di compressione
-    /// Questi valori sono per riferimento futuro se implementiamo il supporto per estrattori personalizzati
+    /// Converte in valore numerico per strategia zstd (ZSTD_c_strategy)
     pub fn to_strategy_value(self) -> i32 {
+        self.to_zstd_strategy() as i32
+    }
+
+    /// Converte nella strategia zstd impostata sull'encoder
+    pub fn to_zstd_strategy(self) -> zstd::zstd_safe::Strategy {
+        use zstd::zstd_safe::Strategy;
         match self {
-            CompressionStrategy::Fast => 1,
-            CompressionStrategy::Default => 2, // DFAST
-            CompressionStrategy::Greedy => 3,
-            CompressionStrategy::Lazy => 4,
-            CompressionStrategy::Lazy2 => 5,
-            CompressionStrategy::BtLazy2 => 7, // BTOPT
+            CompressionStrategy::Fast => Strategy::ZSTD_fast,
+            CompressionStrategy::Default => Strategy::ZSTD_dfast,
+            CompressionStrategy::Greedy => Strategy::ZSTD_greedy,
+            CompressionStrategy::Lazy => Strategy::ZSTD_lazy,
+            CompressionStrategy::Lazy2 => Strategy::ZSTD_lazy2,
+            CompressionStrategy::BtLazy2 => Strategy::ZSTD_btlazy2,
         }
     }
 }
@@ -93,6 +137,9 @@ pub fn optimal_buffer_size(file_size: u64) -> usize {
 /// Mantiene compatibilità con codice esistente
 pub const BUFFER_SIZE: usize = BUFFER_SIZE_SMALL;
 
+/// Dimensione di default di un dizionario zstd (110KB, come `zstd --train`)
+pub const DEFAULT_DICT_SIZE: usize = 112_640;
+
 /// Formatta una dimensione in bytes in modo leggibile (KB, MB, GB, TB)
 pub fn format_size(bytes: u64) -> String {
     const KB: u64 = 1024;
@@ -139,6 +186,45 @@ pub fn parse_level(s: &str) -> Result<i32, String> {
     }
 }
 
+/// Valida il nome di una strategia di compressione zstd
+pub fn parse_strategy(s: &str) -> Result<CompressionStrategy, String> {
+    match s.to_lowercase().as_str() {
+        "fast" => Ok(CompressionStrategy::Fast),
+        "default" | "dfast" => Ok(CompressionStrategy::Default),
+        "greedy" => Ok(CompressionStrategy::Greedy),
+        "lazy" => Ok(CompressionStrategy::Lazy),
+        "lazy2" => Ok(CompressionStrategy::Lazy2),
+        "btlazy2" => Ok(CompressionStrategy::BtLazy2),
+        _ => Err(format!(
+            "Strategia '{}' non valida: usa fast, default, greedy, lazy, lazy2 o btlazy2",
+            s
+        )),
+    }
+}
+
+/// Valida una dimensione in bytes con suffisso opzionale (K, M, G, T; base 1024)
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let text = s.trim().to_uppercase();
+    let text = text
+        .strip_suffix("IB")
+        .or_else(|| text.strip_suffix('B'))
+        .unwrap_or(&text);
+    let (number, multiplier) = match text.char_indices().last() {
+        Some((i, 'K')) => (&text[..i], 1u64 << 10),
+        Some((i, 'M')) => (&text[..i], 1 << 20),
+        Some((i, 'G')) => (&text[..i], 1 << 30),
+        Some((i, 'T')) => (&text[..i], 1 << 40),
+        _ => (text, 1),
+    };
+
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("Dimensione '{}' non valida (es. 512K, 100M, 2G)", s))
+}
+
 /// Ritorna il numero di CPU disponibili
 pub fn num_cpus() -> u32 {
     std::thread::available_parallelism()
@@ -146,49 +232,52 @@ pub fn num_cpus() -> u32 {
         .unwrap_or(1)
 }
 
+/// Canonicalizza l'antenato esistente più profondo e riaggiunge i componenti mancanti
+fn canonicalize_existing_prefix(path: &Path) -> Result<PathBuf> {
+    let mut existing = path;
+    let mut missing = Vec::new();
+
+    while existing.symlink_metadata().is_err() {
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => return Ok(path.to_path_buf()),
+        }
+    }
+
+    let mut canonical = existing.canonicalize()?;
+    for name in missing.iter().rev() {
+        canonical.push(name);
+    }
+    Ok(canonical)
+}
+
 /// Valida un path di output contro directory traversal e path assoluti non sicuri
-pub fn validate_output_path(path: &Path, base_dir: Option<&Path>) -> std::io::Result<PathBuf> {
+pub fn validate_output_path(path: &Path, base_dir: Option<&Path>) -> Result<PathBuf> {
     // Verifica componenti del path per directory traversal
     for component in path.components() {
         if let std::path::Component::ParentDir = component {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                format!("Path non sicuro (contiene '..'): {:?}", path),
-            ));
+            return Err(Error::UnsafePath(format!(
+                "Path non sicuro (contiene '..'): {:?}",
+                path
+            )));
         }
     }
 
-    // Canonicalizza il path se esiste già
-    let canonical = if path.exists() {
-        path.canonicalize()?
-    } else {
-        // Se non esiste, canonicalizza il parent e ricostruisci
-        if let Some(parent) = path.parent() {
-            if parent.exists() {
-                let canonical_parent = parent.canonicalize()?;
-                let file_name = path.file_name().ok_or_else(|| {
-                    std::io::Error::new(std::io::ErrorKind::InvalidInput, "Nome file non valido")
-                })?;
-                canonical_parent.join(file_name)
-            } else {
-                path.to_path_buf()
-            }
-        } else {
-            path.to_path_buf()
-        }
-    };
+    // Canonicalizza la parte del path che esiste già: i link simbolici in
+    // qualunque directory antenata vengono risolti prima del controllo
+    let canonical = canonicalize_existing_prefix(path)?;
 
     // Se è specificata una base dir, verifica che il path sia contenuto
     if let Some(base) = base_dir {
         let base_canonical = base.canonicalize()?;
         if !canonical.starts_with(&base_canonical) {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                format!(
-                    "Path non autorizzato fuori dalla directory base: {:?}",
-                    path
-                ),
-            ));
+            return Err(Error::UnsafePath(format!(
+                "Path non autorizzato fuori dalla directory base: {:?}",
+                path
+            )));
         }
     }
 
@@ -267,7 +356,7 @@ pub fn calculate_entropy(data: &[u8]) -> f64 {
 }
 
 /// Legge un sample del file e calcola l'entropia
-pub fn sample_file_entropy(path: &Path) -> std::io::Result<f64> {
+pub fn sample_file_entropy(path: &Path) -> Result<f64> {
     let mut file = File::open(path)?;
     let file_size = file.metadata()?.len();
 
@@ -281,7 +370,7 @@ pub fn sample_file_entropy(path: &Path) -> std::io::Result<f64> {
 
 /// Determina se vale la pena comprimere un file
 /// Ritorna (should_compress, reason)
-pub fn should_compress(path: &Path) -> std::io::Result<(bool, &'static str)> {
+pub fn should_compress(path: &Path) -> Result<(bool, &'static str)> {
     let file_type = detect_file_type(path);
 
     // Skip file già compressi
@@ -329,27 +418,202 @@ pub fn optimal_strategy_for_file(file_type: FileType, level: i32) -> Compression
     }
 }
 
+/// Strategia che zstd usa per un livello e una dimensione dell'input (0 se ignota)
+fn level_default_strategy(level: i32, input_size: u64) -> zstd::zstd_safe::Strategy {
+    // SAFETY: ZSTD_getCParams legge solo le tabelle dei livelli, senza puntatori
+    unsafe { zstd::zstd_safe::zstd_sys::ZSTD_getCParams(level, input_size, 0) }.strategy
+}
+
 /// Costruisce il path di output per la compressione
 pub fn build_output_path(input_path: &Path) -> PathBuf {
+    build_output_path_for(input_path, CompressionAlgorithm::Zstd)
+}
+
+/// Costruisce il path di output per la compressione con un dato algoritmo
+pub fn build_output_path_for(input_path: &Path, algorithm: CompressionAlgorithm) -> PathBuf {
     match input_path.extension() {
         Some(ext) => {
             let mut new_ext: OsString = ext.to_os_string();
-            new_ext.push(".zst");
+            new_ext.push(".");
+            new_ext.push(algorithm.extension());
             input_path.with_extension(new_ext)
         }
-        None => input_path.with_extension("zst"),
+        None => input_path.with_extension(algorithm.extension()),
+    }
+}
+
+/// Ritorna true se il path indica un archivio tar compresso (.tar.zst, .tar.gz, .tgz, ...)
+pub fn is_tar_archive_path(path: &Path) -> bool {
+    let name = match path.file_name().and_then(|s| s.to_str()) {
+        Some(name) => name.to_lowercase(),
+        None => return false,
+    };
+
+    if matches!(
+        path.extension()
+            .and_then(|s| s.to_str())
+            .map(|s| s.to_lowercase())
+            .as_deref(),
+        Some("tgz" | "txz" | "tzst")
+    ) {
+        return true;
+    }
+
+    CompressionAlgorithm::ALL
+        .iter()
+        .any(|algorithm| name.ends_with(&format!(".tar.{}", algorithm.extension())))
+}
+
+/// Offset del magic "ustar" nell'header di un'entry tar
+const TAR_MAGIC_OFFSET: usize = 257;
+
+/// Formato di un file compresso, rilevato dal contenuto e non dall'estensione
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DetectedFormat {
+    pub algorithm: CompressionAlgorithm,
+    /// true se lo stream decompresso è un archivio tar
+    pub is_tar: bool,
+}
+
+/// Rileva algoritmo e presenza di un archivio tar leggendo l'inizio del file.
+///
+/// Il dizionario serve solo per decodificare il primo blocco di file compressi con esso.
+pub fn detect_format(
+    input_path: &Path,
+    dictionary: Option<&CompressionDictionary>,
+) -> Result<DetectedFormat> {
+    let algorithm = CompressionAlgorithm::detect(input_path)?.ok_or_else(unrecognized_format)?;
+
+    let (input_file, _) = open_input(input_path)?;
+    let mut reader = BufReader::new(input_file);
+    let mut decoder = algorithm.compressor()?.decoder(&mut reader, dictionary)?;
+
+    let mut header = [0u8; 512];
+    let n = codec::read_up_to(&mut decoder, &mut header)?;
+    let is_tar =
+        n >= TAR_MAGIC_OFFSET + 5 && &header[TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + 5] == b"ustar";
+
+    Ok(DetectedFormat { algorithm, is_tar })
+}
+
+fn unrecognized_format() -> Error {
+    Error::UnsupportedFormat(
+        "Formato non riconosciuto: il file non è compresso con zstd, lz4, gzip, xz o brotli"
+            .to_string(),
+    )
+}
+
+/// Dizionario zstd per migliorare la compressione di file piccoli e simili
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressionDictionary {
+    data: Vec<u8>,
+}
+
+impl CompressionDictionary {
+    /// Crea un dizionario da bytes già pronti (es. prodotti da `zstd --train`)
+    pub fn from_bytes(data: Vec<u8>) -> Self {
+        Self { data }
+    }
+
+    /// Addestra un dizionario a partire da un insieme di file di esempio
+    pub fn train_from_files(sample_files: &[PathBuf], dict_size: usize) -> Result<Self> {
+        let mut samples = Vec::with_capacity(sample_files.len());
+        for file in sample_files {
+            if !file.is_file() {
+                return Err(Error::NotFound(format!(
+                    "Il file di esempio {:?} non esiste",
+                    file
+                )));
+            }
+            samples.push(std::fs::read(file)?);
+        }
+        Self::train_from_samples(&samples, dict_size)
+    }
+
+    /// Addestra un dizionario a partire da buffer in memoria
+    pub fn train_from_samples<S: AsRef<[u8]>>(samples: &[S], dict_size: usize) -> Result<Self> {
+        if samples.is_empty() {
+            return Err(Error::InvalidInput(
+                "Servono dei file di esempio per addestrare il dizionario".to_string(),
+            ));
+        }
+
+        let data = zstd::dict::from_samples(samples, dict_size).map_err(|e| {
+            Error::InvalidInput(format!(
+                "Addestramento dizionario fallito: {} (servono più esempi o esempi più grandi)",
+                e
+            ))
+        })?;
+
+        Ok(Self { data })
+    }
+
+    /// Carica un dizionario da disco
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Err(Error::NotFound(format!(
+                "Il dizionario {:?} non esiste",
+                path
+            )));
+        }
+
+        let data = std::fs::read(path)?;
+        if data.is_empty() {
+            return Err(Error::InvalidInput(format!(
+                "Il dizionario {:?} è vuoto",
+                path
+            )));
+        }
+
+        Ok(Self { data })
+    }
+
+    /// Salva il dizionario su disco
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() && !parent.exists() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        std::fs::write(path, &self.data)?;
+        Ok(())
+    }
+
+    /// Contenuto grezzo del dizionario
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Dimensione del dizionario in bytes
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Ritorna true se il dizionario è vuoto
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// ID del dizionario scritto nell'header (None per dizionari raw)
+    pub fn id(&self) -> Option<u32> {
+        zstd::zstd_safe::get_dict_id_from_dict(&self.data).map(|id| id.get())
     }
 }
 
 /// Risultato di un'operazione di compressione/decompressione
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CompressionResult {
     pub input_size: u64,
     pub output_size: u64,
 }
 
-/// Callback per aggiornare il progresso
-pub type ProgressCallback = Box<dyn Fn(u64) + Send + Sync>;
+/// Ritorna [`Error::Cancelled`] se l'operazione è stata annullata tramite il flag
+fn check_cancelled(flag: Option<&Arc<AtomicBool>>) -> Result<()> {
+    match flag {
+        Some(flag) if flag.load(Ordering::Relaxed) => Err(Error::Cancelled),
+        _ => Ok(()),
+    }
+}
 
 /// Opzioni per la compressione
 #[derive(Default)]
@@ -359,8 +623,21 @@ pub struct CompressOptions {
     pub parallel: bool,
     pub auto_parallel: bool,
     pub smart_optimize: bool, // Abilita ottimizzazioni intelligenti basate sul contenuto
+    pub algorithm: CompressionAlgorithm,
     pub output_path: Option<PathBuf>,
-    pub progress_callback: Option<ProgressCallback>,
+    pub dictionary: Option<CompressionDictionary>,
+    pub strategy: Option<CompressionStrategy>, // Strategia esplicita, ha priorità su smart_optimize
+    pub filter: PathFilter,                    // File da includere/escludere nelle directory
+    pub respect_ignore_files: bool,            // Salta i file elencati in .gitignore/.ignore
+    pub follow_symlinks: bool, // Archivia il contenuto dei link invece del link stesso
+    pub cancel_flag: Option<Arc<AtomicBool>>, // Se impostato a true l'operazione si interrompe
+    pub content_checksum: bool, // Checksum del contenuto nei frame zstd
+    pub digest: Option<DigestMode>, // Dove salvare lo SHA-256 dei dati originali
+    pub split_size: Option<u64>, // Archivi divisi in volumi di questa dimensione
+    pub seekable_frame_size: Option<u32>, // Formato seekable: frame indipendenti di questa dimensione
+    pub read_ahead: u64, // Memoria per la lettura anticipata dei file delle directory (0 = sequenziale)
+    pub backup: Option<BackupMode>, // Backup di directory con stato per i backup incrementali
+    pub progress_handler: Option<ProgressHandler>,
 }
 
 impl CompressOptions {
@@ -371,8 +648,21 @@ impl CompressOptions {
             parallel: false,
             auto_parallel: true,  // Abilitato di default per prestazioni ottimali
             smart_optimize: true, // Abilitato di default per compressione ottimale
+            algorithm: CompressionAlgorithm::Zstd,
             output_path: None,
-            progress_callback: None,
+            dictionary: None,
+            strategy: None,
+            filter: PathFilter::new(),
+            respect_ignore_files: false,
+            follow_symlinks: false,
+            cancel_flag: None,
+            content_checksum: true, // Il decoder rileva così i dati alterati
+            digest: None,
+            split_size: None,
+            seekable_frame_size: None,
+            read_ahead: prefetch::DEFAULT_READ_AHEAD,
+            backup: None,
+            progress_handler: None,
         }
     }
 
@@ -404,41 +694,357 @@ impl CompressOptions {
         self
     }
 
-    pub fn with_progress<F>(mut self, callback: F) -> Self
+    /// Usa un dizionario zstd per la compressione
+    pub fn with_dictionary(mut self, dictionary: CompressionDictionary) -> Self {
+        self.dictionary = Some(dictionary);
+        self
+    }
+
+    /// Imposta l'algoritmo di compressione (default: zstd)
+    pub fn with_algorithm(mut self, algorithm: CompressionAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Forza una strategia zstd, ignorando quella scelta da smart_optimize
+    pub fn with_strategy(mut self, strategy: CompressionStrategy) -> Self {
+        self.strategy = Some(strategy);
+        self
+    }
+
+    /// Filtra i file archiviati da `compress_directory` con glob include/exclude
+    pub fn with_filter(mut self, filter: PathFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Rispetta .gitignore/.ignore (e salta .git) in `compress_directory`
+    pub fn with_ignore_files(mut self, respect: bool) -> Self {
+        self.respect_ignore_files = respect;
+        self
+    }
+
+    /// Segue i link simbolici nelle directory (default: salvati come link).
+    ///
+    /// I link che creano un ciclo o puntano a un file inesistente restano link.
+    pub fn with_follow_symlinks(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Flag di annullamento condiviso: quando diventa true l'operazione termina
+    /// con [`Error::Cancelled`] senza lasciare output parziali
+    pub fn with_cancel_flag(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.cancel_flag = Some(flag);
+        self
+    }
+
+    /// Abilita/disabilita il checksum del contenuto nei frame zstd (default: attivo)
+    pub fn with_content_checksum(mut self, checksum: bool) -> Self {
+        self.content_checksum = checksum;
+        self
+    }
+
+    /// Salva lo SHA-256 dei dati originali in `compress_file`, per `verifica --deep`
+    pub fn with_digest(mut self, mode: DigestMode) -> Self {
+        self.digest = Some(mode);
+        self
+    }
+
+    /// Divide gli archivi tar in volumi `<archivio>.001`, `.002`, ... di al
+    /// massimo `bytes` ciascuno
+    pub fn with_split_size(mut self, bytes: u64) -> Self {
+        self.split_size = Some(bytes);
+        self
+    }
+
+    /// Memoria con cui i thread di lettura leggono in anticipo i file di una
+    /// directory mentre il tar comprime i precedenti; 0 legge i file uno alla
+    /// volta sul thread del tar. L'archivio prodotto non cambia
+    pub fn with_read_ahead(mut self, bytes: u64) -> Self {
+        self.read_ahead = bytes;
+        self
+    }
+
+    /// Backup di `compress_directory`: scrive lo stato `<archivio>.state` e, in
+    /// modalità incrementale, archivia solo le differenze dall'archivio di base
+    /// (vedi [`incremental`])
+    pub fn with_backup(mut self, mode: BackupMode) -> Self {
+        self.backup = Some(mode);
+        self
+    }
+
+    /// Scrive il formato zstd seekable: frame indipendenti di `frame_size` bytes
+    /// di input e una seek table per l'accesso casuale (vedi [`SeekableReader`])
+    pub fn with_seekable(mut self, frame_size: u32) -> Self {
+        self.seekable_frame_size = Some(frame_size);
+        self
+    }
+
+    /// Riceve gli eventi di progresso dell'operazione
+    pub fn with_progress_events<F>(mut self, handler: F) -> Self
     where
-        F: Fn(u64) + Send + Sync + 'static,
+        F: Fn(&ProgressEvent) + Send + Sync + 'static,
     {
-        self.progress_callback = Some(Box::new(callback));
+        self.progress_handler = Some(Box::new(handler));
         self
     }
 
+    /// Vecchia API: il callback riceve i bytes degli eventi [`ProgressEvent::Bytes`]
+    #[deprecated(note = "usa with_progress_events")]
+    pub fn with_progress<F>(self, callback: F) -> Self
+    where
+        F: Fn(u64) + Send + Sync + 'static,
+    {
+        self.with_progress_events(progress::bytes_only(callback))
+    }
+
     /// Determina se usare il multithreading in base alle opzioni e alla dimensione del file
     #[inline]
     pub fn should_use_parallel(&self, file_size: u64) -> bool {
         self.parallel || (self.auto_parallel && file_size >= AUTO_PARALLEL_THRESHOLD)
     }
+
+    /// Strategia da applicare per un tipo di file: quella esplicita se presente,
+    /// altrimenti quella ottimale se smart_optimize è attivo e non è più debole
+    /// di quella che zstd userebbe per il livello (es. btultra2 al livello 19)
+    pub fn effective_strategy(
+        &self,
+        file_type: FileType,
+        input_size: u64,
+    ) -> Option<CompressionStrategy> {
+        if self.strategy.is_some() {
+            return self.strategy;
+        }
+        if !self.smart_optimize || file_type == FileType::Unknown {
+            return None;
+        }
+        let strategy = optimal_strategy_for_file(file_type, self.level);
+        let level_strategy = level_default_strategy(self.level, input_size);
+        (strategy.to_zstd_strategy() as u32 >= level_strategy as u32).then_some(strategy)
+    }
 }
 
-/// Comprime un singolo file
-pub fn compress_file(
-    input_path: &Path,
+/// Parametri zstd per archivi tar (contenuto di tipo misto)
+fn archive_encoder_parameters(
+    options: &CompressOptions,
+    total_size: u64,
+) -> Vec<zstd::zstd_safe::CParameter> {
+    use zstd::zstd_safe::CParameter;
+
+    let mut params = vec![CParameter::ChecksumFlag(options.content_checksum)];
+
+    // Abilita multithreading automatico
+    if options.should_use_parallel(total_size) {
+        params.push(CParameter::NbWorkers(num_cpus()));
+    }
+
+    // Strategia esplicita (gli archivi contengono file di tipo misto)
+    if let Some(strategy) = options.strategy {
+        params.push(CParameter::Strategy(strategy.to_zstd_strategy()));
+    }
+
+    // Ottimizzazioni per archivi grandi
+    if total_size >= LARGE_FILE_THRESHOLD {
+        params.push(CParameter::WindowLog(24));
+        params.push(CParameter::EnableLongDistanceMatching(true));
+    }
+
+    params
+}
+
+/// Calcola i parametri zstd da applicare all'encoder per un singolo file
+fn file_encoder_parameters(
     options: &CompressOptions,
-) -> std::io::Result<CompressionResult> {
+    file_type: FileType,
+    input_size: u64,
+) -> Vec<zstd::zstd_safe::CParameter> {
+    use zstd::zstd_safe::CParameter;
+
+    let mut params = vec![CParameter::ChecksumFlag(options.content_checksum)];
+
+    // Abilita multithreading automatico per file grandi o se esplicitamente richiesto
+    if options.should_use_parallel(input_size) {
+        params.push(CParameter::NbWorkers(num_cpus()));
+    }
+
+    // Strategia esplicita o ottimale per tipo di file
+    if let Some(strategy) = options.effective_strategy(file_type, input_size) {
+        params.push(CParameter::Strategy(strategy.to_zstd_strategy()));
+    }
+
+    // Ottimizzazioni per file grandi
+    if input_size >= LARGE_FILE_THRESHOLD {
+        // Window log più grande per migliore compressione di pattern distanti
+        let window_log = if options.level >= 15 {
+            27 // 128MB window per livelli alti
+        } else {
+            24 // 16MB window di default
+        };
+        params.push(CParameter::WindowLog(window_log));
+
+        // Long distance matching per file con pattern ripetuti
+        params.push(CParameter::EnableLongDistanceMatching(true));
+
+        // HashLog e ChainLog per file molto grandi
+        if input_size >= 100 * 1024 * 1024 && options.level >= 10 {
+            // 100MB+
+            params.push(CParameter::HashLog(26));
+            params.push(CParameter::ChainLog(27));
+        }
+    }
+
+    params
+}
+
+/// Encoder dell'output: uno stream o i frame del formato seekable
+enum OutputEncoder<'a> {
+    Stream(Box<dyn FinishWrite + 'a>),
+    Seekable(SeekableWriter<&'a mut dyn Write>),
+}
+
+impl<'a> OutputEncoder<'a> {
+    fn new(
+        writer: &'a mut dyn Write,
+        settings: &EncoderSettings,
+        options: &CompressOptions,
+    ) -> Result<Self> {
+        match options.seekable_frame_size {
+            Some(_) if options.algorithm != CompressionAlgorithm::Zstd => {
+                Err(Error::InvalidInput(format!(
+                    "Il formato seekable richiede zstd, non {}",
+                    options.algorithm
+                )))
+            }
+            Some(frame_size) => Ok(OutputEncoder::Seekable(SeekableWriter::new(
+                writer, settings, frame_size,
+            )?)),
+            None => Ok(OutputEncoder::Stream(
+                options.algorithm.compressor()?.encoder(writer, settings)?,
+            )),
+        }
+    }
+
+    /// Inizio di un membro di un archivio tar. Nel formato seekable ritorna la
+    /// sua posizione nel tar e, se il frame corrente è pieno almeno per metà, ne
+    /// inizia uno nuovo: i membri piccoli restano raggruppati nello stesso frame
+    fn begin_member(&mut self) -> std::io::Result<Option<u64>> {
+        match self {
+            OutputEncoder::Stream(_) => Ok(None),
+            OutputEncoder::Seekable(frames) => {
+                frames.end_frame(frames.frame_size() / 2)?;
+                Ok(Some(frames.position()))
+            }
+        }
+    }
+
+    /// Finalizza lo stream; per il formato seekable ritorna la seek table, da
+    /// scrivere dopo gli altri frame skippable
+    fn finish(self) -> std::io::Result<Option<SeekTable>> {
+        match self {
+            OutputEncoder::Stream(encoder) => encoder.finish().map(|_| None),
+            OutputEncoder::Seekable(frames) => frames.finish().map(|(_, table)| Some(table)),
+        }
+    }
+}
+
+impl Write for OutputEncoder<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputEncoder::Stream(encoder) => encoder.write(buf),
+            OutputEncoder::Seekable(frames) => frames.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputEncoder::Stream(encoder) => encoder.flush(),
+            OutputEncoder::Seekable(frames) => frames.flush(),
+        }
+    }
+}
+
+/// Il manifest incorporato è un frame skippable: solo zstd lo ignora in lettura
+fn check_digest_mode(options: &CompressOptions) -> Result<()> {
+    if options.digest == Some(DigestMode::Embedded)
+        && options.algorithm != CompressionAlgorithm::Zstd
+    {
+        return Err(Error::InvalidInput(format!(
+            "Il digest incorporato richiede zstd: con {} usa il sidecar",
+            options.algorithm
+        )));
+    }
+    Ok(())
+}
+
+/// Completa l'output di un archivio scrivendo l'indice dei membri e il manifest
+/// dei file, se richiesti, e infine la seek table; ritorna la dimensione
+/// dell'output (di tutti i volumi)
+fn commit_archive_output(
+    mut writer: BufWriter<ArchiveOutput>,
+    output_path: &Path,
+    manifest: Option<&Manifest>,
+    mode: Option<DigestMode>,
+    seekable: Option<(ArchiveIndex, SeekTable)>,
+) -> Result<u64> {
+    if let Some((index, _)) = &seekable {
+        index.write_to(&mut writer)?;
+    }
+    if let (Some(manifest), Some(DigestMode::Embedded)) = (manifest, mode) {
+        manifest.write_embedded(&mut writer)?;
+    }
+    if let Some((_, table)) = &seekable {
+        table.write_to(&mut writer)?;
+    }
+    let output = writer.into_inner().map_err(|e| e.into_error())?;
+    let files = output.commit(output_path)?;
+    match (manifest, mode) {
+        (Some(manifest), Some(DigestMode::Sidecar)) => {
+            manifest.write_sidecar(output_path)?;
+        }
+        _ => digest::remove_stale_sidecar(output_path)?,
+    }
+
+    let mut output_size = 0;
+    for file in &files {
+        output_size += std::fs::metadata(file)?.len();
+    }
+    Ok(output_size)
+}
+
+/// Primo file che l'output di un archivio andrebbe a scrivere
+fn archive_output_target(output_path: &Path, options: &CompressOptions) -> PathBuf {
+    match options.split_size {
+        Some(_) => volume::volume_path(output_path, 1),
+        None => output_path.to_path_buf(),
+    }
+}
+
+/// Svuota il buffer e porta il file temporaneo sul percorso finale
+fn commit_output(writer: BufWriter<AtomicFile>) -> Result<()> {
+    writer.into_inner().map_err(|e| e.into_error())?.commit()?;
+    Ok(())
+}
+
+/// Comprime un singolo file
+pub fn compress_file(input_path: &Path, options: &CompressOptions) -> Result<CompressionResult> {
     if !input_path.exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("Il file di input {:?} non esiste", input_path),
-        ));
+        return Err(Error::NotFound(format!(
+            "Il file di input {:?} non esiste",
+            input_path
+        )));
     }
 
+    let progress = Progress::new(options.progress_handler.as_ref());
+
     // Smart optimization: controlla se vale la pena comprimere
     if options.smart_optimize {
         match should_compress(input_path) {
             Ok((false, reason)) => {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidInput,
-                    format!("Skip compressione: {}", reason),
-                ));
+                progress.skipped(input_path, reason);
+                return Err(Error::Skipped {
+                    reason: reason.to_string(),
+                });
             }
             Ok((true, _)) => {} // Procedi con compressione
             Err(_) => {}        // In caso di errore nel check, procedi comunque
@@ -452,32 +1058,36 @@ pub fn compress_file(
         FileType::Unknown
     };
 
+    // Errore subito se l'algoritmo non è compilato, prima di creare l'output
+    options.algorithm.compressor()?;
+    check_digest_mode(options)?;
+    if options.split_size.is_some() {
+        return Err(Error::InvalidInput(
+            "La divisione in volumi è disponibile solo per gli archivi tar".to_string(),
+        ));
+    }
+
     // Usa output_path personalizzato se specificato, altrimenti usa il default
     let output_path = match &options.output_path {
         Some(p) => {
             // Se è una directory, aggiungi il nome del file compresso
             if p.is_dir() {
-                let file_name = input_path.file_name().ok_or_else(|| {
-                    std::io::Error::new(std::io::ErrorKind::InvalidInput, "Nome file non valido")
-                })?;
+                let file_name = input_path
+                    .file_name()
+                    .ok_or_else(|| Error::InvalidInput("Nome file non valido".to_string()))?;
                 let mut output_name = file_name.to_os_string();
-                output_name.push(".zst");
+                output_name.push(".");
+                output_name.push(options.algorithm.extension());
                 p.join(output_name)
             } else {
                 p.clone()
             }
         }
-        None => build_output_path(input_path),
+        None => build_output_path_for(input_path, options.algorithm),
     };
 
     if output_path.exists() && !options.force {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::AlreadyExists,
-            format!(
-                "Il file di output {:?} esiste già. Usa --force per sovrascrivere.",
-                output_path
-            ),
-        ));
+        return Err(Error::AlreadyExists(output_path.to_path_buf()));
     }
 
     // Crea le directory padre se non esistono
@@ -493,74 +1103,66 @@ pub fn compress_file(
     let buffer_size = optimal_buffer_size(input_size);
 
     let input_file = File::open(input_path)?;
-    let output_file = File::create(&output_path)?;
+    let output_file = AtomicFile::create(&output_path)?;
 
     let mut reader = BufReader::with_capacity(buffer_size, input_file);
-    let writer = BufWriter::with_capacity(buffer_size, output_file);
+    let mut writer = BufWriter::with_capacity(buffer_size, output_file);
 
-    let mut encoder = zstd::Encoder::new(writer, options.level)?;
+    // Multithreading, strategia per tipo di file e ottimizzazioni per file grandi
+    let settings = EncoderSettings {
+        level: options.level,
+        dictionary: options.dictionary.as_ref(),
+        pledged_size: Some(input_size),
+        zstd_parameters: file_encoder_parameters(options, file_type, input_size),
+    };
+    let mut encoder = OutputEncoder::new(&mut writer, &settings, options)?;
 
-    // Pledged source size: migliora ratio di compressione del 2-5%
-    encoder.set_pledged_src_size(Some(input_size))?;
+    // Buffer per la lettura incrementale con progress
+    let mut buffer = vec![0u8; buffer_size];
+    let mut total_read = 0u64;
+    let mut digest = options.digest.map(|_| DigestWriter::default());
 
-    // Abilita multithreading automatico per file grandi o se esplicitamente richiesto
-    if options.should_use_parallel(input_size) {
-        encoder.set_parameter(zstd::zstd_safe::CParameter::NbWorkers(num_cpus()))?;
+    progress.started(Some(input_size), Some(1));
+    progress.file_started(input_path);
+    loop {
+        check_cancelled(options.cancel_flag.as_ref())?;
+        let bytes_read = reader.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        encoder.write_all(&buffer[..bytes_read])?;
+        if let Some(digest) = digest.as_mut() {
+            digest.update(&buffer[..bytes_read]);
+        }
+        total_read += bytes_read as u64;
+        progress.bytes(total_read, Some(input_size));
     }
 
-    // Smart optimization: seleziona strategia ottimale per tipo di file
-    if options.smart_optimize && file_type != FileType::Unknown {
-        let strategy_value =
-            optimal_strategy_for_file(file_type, options.level).to_strategy_value();
-        // Usa API di alto livello set_zstd_parameter (deprecato) o imposta strategia tramite compression level
-        // Per ora skippiamo la strategia esplicita, zstd la sceglie automaticamente in base al livello
-        // In future potremmo usare dictionary per personalizzare ulteriormente
-        let _ = strategy_value; // Evita warning unused
-    }
+    let seek_table = encoder.finish()?;
 
-    // Ottimizzazioni per file grandi
-    if input_size >= LARGE_FILE_THRESHOLD {
-        // Window log più grande per migliore compressione di pattern distanti
-        let window_log = if options.level >= 15 {
-            27 // 128MB window per livelli alti
-        } else {
-            24 // 16MB window di default
-        };
-        encoder.set_parameter(zstd::zstd_safe::CParameter::WindowLog(window_log))?;
-
-        // Long distance matching per file con pattern ripetuti
-        encoder.set_parameter(zstd::zstd_safe::CParameter::EnableLongDistanceMatching(
-            true,
-        ))?;
-
-        // HashLog e ChainLog per file molto grandi
-        if input_size >= 100 * 1024 * 1024 && options.level >= 10 {
-            // 100MB+
-            encoder.set_parameter(zstd::zstd_safe::CParameter::HashLog(26))?;
-            encoder.set_parameter(zstd::zstd_safe::CParameter::ChainLog(27))?;
-        }
+    // Il nome nel manifest è quello del file originale, come per sha256sum
+    let manifest = digest.map(|digest| {
+        let name = input_path.file_name().map(Path::new).unwrap_or(input_path);
+        Manifest::single(name, digest.finish())
+    });
+    if let (Some(manifest), Some(DigestMode::Embedded)) = (&manifest, options.digest) {
+        manifest.write_embedded(&mut writer)?;
     }
-
-    // Buffer per la lettura incrementale con progress
-    let mut buffer = vec![0u8; buffer_size];
-    let mut total_read = 0u64;
-
-    loop {
-        let bytes_read = reader.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break;
-        }
-        encoder.write_all(&buffer[..bytes_read])?;
-        total_read += bytes_read as u64;
-
-        if let Some(ref callback) = options.progress_callback {
-            callback(total_read);
+    // La seek table deve essere l'ultimo frame del file
+    if let Some(table) = seek_table {
+        table.write_to(&mut writer)?;
+    }
+    commit_output(writer)?;
+    match (&manifest, options.digest) {
+        (Some(manifest), Some(DigestMode::Sidecar)) => {
+            manifest.write_sidecar(&output_path)?;
         }
+        _ => digest::remove_stale_sidecar(&output_path)?,
     }
 
-    encoder.finish()?;
-
     let output_size = std::fs::metadata(&output_path)?.len();
+    progress.file_finished(input_path, input_size, output_size);
+    progress.finished();
 
     Ok(CompressionResult {
         input_size,
@@ -569,40 +1171,131 @@ pub fn compress_file(
 }
 
 /// Comprime un singolo file (versione semplice senza progress)
-pub fn compress_file_simple(input_path: &Path, level: i32, force: bool) -> std::io::Result<()> {
+pub fn compress_file_simple(input_path: &Path, level: i32, force: bool) -> Result<()> {
     let options = CompressOptions::new(level).with_force(force);
     compress_file(input_path, &options)?;
     Ok(())
 }
 
-/// Comprime una directory in un archivio tar.zst
-pub fn compress_directory(
-    dir_path: &Path,
+/// Reader che conta i bytes letti; il contatore resta leggibile mentre il
+/// reader è in prestito al decoder
+struct CountingReader<'a, R> {
+    inner: R,
+    count: &'a Cell<u64>,
+}
+
+impl<R: Read> Read for CountingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.set(self.count.get() + n as u64);
+        Ok(n)
+    }
+}
+
+/// Writer che conta i bytes scritti
+struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Comprime uno stream generico (es. stdin) scrivendo su un altro stream.
+///
+/// La dimensione dell'input non è nota: niente smart optimization per tipo di file
+/// né ottimizzazioni per file grandi; strategia esplicita, dizionario e `parallel`
+/// vengono applicati. Gli eventi `Bytes` riportano i bytes letti, senza totale.
+pub fn compress_stream<R: Read, W: Write>(
+    mut reader: R,
+    writer: W,
     options: &CompressOptions,
-) -> std::io::Result<CompressionResult> {
+) -> Result<CompressionResult> {
+    let mut writer = CountingWriter {
+        inner: BufWriter::with_capacity(BUFFER_SIZE, writer),
+        count: 0,
+    };
+    let settings = EncoderSettings {
+        level: options.level,
+        dictionary: options.dictionary.as_ref(),
+        pledged_size: None,
+        zstd_parameters: file_encoder_parameters(options, FileType::Unknown, 0),
+    };
+    let mut encoder = OutputEncoder::new(&mut writer, &settings, options)?;
+
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut total_read = 0u64;
+
+    let progress = Progress::new(options.progress_handler.as_ref());
+    progress.started(None, None);
+    loop {
+        check_cancelled(options.cancel_flag.as_ref())?;
+        let bytes_read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        encoder.write_all(&buffer[..bytes_read])?;
+        total_read += bytes_read as u64;
+        progress.bytes(total_read, None);
+    }
+
+    if let Some(table) = encoder.finish()? {
+        table.write_to(&mut writer)?;
+    }
+    writer.flush()?;
+    progress.finished();
+
+    Ok(CompressionResult {
+        input_size: total_read,
+        output_size: writer.count,
+    })
+}
+
+/// Comprime una directory in un archivio tar (tar.zst con l'algoritmo di default)
+pub fn compress_directory(dir_path: &Path, options: &CompressOptions) -> Result<CompressionResult> {
     if !dir_path.exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("La directory {:?} non esiste", dir_path),
-        ));
+        return Err(Error::NotFound(format!(
+            "La directory {:?} non esiste",
+            dir_path
+        )));
     }
 
     if !dir_path.is_dir() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            format!("{:?} non è una directory", dir_path),
-        ));
+        return Err(Error::InvalidInput(format!(
+            "{:?} non è una directory",
+            dir_path
+        )));
     }
 
+    // Errore subito se l'algoritmo non è compilato, prima di creare l'output
+    options.algorithm.compressor()?;
+    check_digest_mode(options)?;
+
     let dir_name = dir_path
         .file_name()
         .unwrap_or_else(|| std::ffi::OsStr::new("archivio"));
+    let archive_name = format!(
+        "{}.tar.{}",
+        dir_name.to_string_lossy(),
+        options.algorithm.extension()
+    );
 
     // Usa output_path personalizzato se specificato
     let output_path = match &options.output_path {
         Some(p) => {
             if p.is_dir() {
-                p.join(format!("{}.tar.zst", dir_name.to_string_lossy()))
+                p.join(&archive_name)
             } else {
                 p.clone()
             }
@@ -610,17 +1303,12 @@ pub fn compress_directory(
         None => dir_path
             .parent()
             .unwrap_or(Path::new("."))
-            .join(format!("{}.tar.zst", dir_name.to_string_lossy())),
+            .join(&archive_name),
     };
 
-    if output_path.exists() && !options.force {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::AlreadyExists,
-            format!(
-                "Il file di output {:?} esiste già. Usa --force per sovrascrivere.",
-                output_path
-            ),
-        ));
+    let target = archive_output_target(&output_path, options);
+    if target.exists() && !options.force {
+        return Err(Error::AlreadyExists(target));
     }
 
     // Crea le directory padre se non esistono
@@ -630,39 +1318,70 @@ pub fn compress_directory(
         }
     }
 
-    // Calcola la dimensione totale della directory
-    let total_size = calculate_dir_size(dir_path)?;
+    // Un solo attraversamento: gli stessi elementi danno totale e contenuto dell'archivio
+    let mut entries = collect_dir_entries(dir_path, &WalkOptions::from(options))?;
+    // Nei backup incrementali restano solo gli elementi nuovi o modificati
+    let backup = match &options.backup {
+        Some(mode) => Some(BackupPlan::new(mode, dir_path, &output_path, &mut entries)?),
+        None => None,
+    };
+    let total_size = total_entries_size(&entries);
 
     // Usa buffer ottimale
     let buffer_size = optimal_buffer_size(total_size);
 
-    let output_file = File::create(&output_path)?;
-    let writer = BufWriter::with_capacity(buffer_size, output_file);
-    let mut encoder = zstd::Encoder::new(writer, options.level)?;
-
-    // Abilita multithreading automatico
-    if options.should_use_parallel(total_size) {
-        encoder.set_parameter(zstd::zstd_safe::CParameter::NbWorkers(num_cpus()))?;
-    }
-
-    // Ottimizzazioni per archivi grandi
-    if total_size >= LARGE_FILE_THRESHOLD {
-        encoder.set_parameter(zstd::zstd_safe::CParameter::WindowLog(24))?;
-        encoder.set_parameter(zstd::zstd_safe::CParameter::EnableLongDistanceMatching(
-            true,
-        ))?;
-    }
+    let output_file = ArchiveOutput::create(&output_path, options.split_size, options.algorithm)?;
+    let mut writer = BufWriter::with_capacity(buffer_size, output_file);
+    let settings = EncoderSettings {
+        level: options.level,
+        dictionary: options.dictionary.as_ref(),
+        pledged_size: None,
+        zstd_parameters: archive_encoder_parameters(options, total_size),
+    };
+    let encoder = OutputEncoder::new(&mut writer, &settings, options)?;
 
     let mut tar = Builder::new(encoder);
+    // Lo stato del backup registra i digest calcolati durante l'archiviazione
+    let mut manifest = (options.digest.is_some() || backup.is_some()).then(Manifest::default);
+    let mut index = ArchiveIndex::default();
+
+    // Aggiungi file, directory e link con progress tracking, mentre i thread di
+    // lettura preparano i file successivi
+    let progress = Progress::new(options.progress_handler.as_ref());
+    progress.started(Some(total_size), Some(count_non_dir_entries(&entries)));
+    let read_ahead = ReadAhead::new(&entries, options.read_ahead);
+    std::thread::scope(|scope| {
+        read_ahead.start(scope);
+        let result = append_entries_with_progress(
+            &mut tar,
+            dir_path,
+            &read_ahead,
+            progress,
+            options.cancel_flag.as_ref(),
+            manifest.as_mut(),
+            |encoder: &mut OutputEncoder, name| {
+                if let Some(offset) = encoder.begin_member()? {
+                    index.push(name, offset);
+                }
+                Ok(())
+            },
+        );
+        read_ahead.stop();
+        result
+    })?;
 
-    // Aggiungi tutti i file dalla directory con progress tracking
-    let progress_tracker = ProgressTracker::new(options.progress_callback.as_ref());
-    add_dir_to_tar_with_progress(&mut tar, dir_path, dir_path, &progress_tracker)?;
-
-    let encoder = tar.into_inner()?;
-    encoder.finish()?;
-
-    let output_size = std::fs::metadata(&output_path)?.len();
+    let table = tar.into_inner()?.finish()?;
+    let output_size = commit_archive_output(
+        writer,
+        &output_path,
+        manifest.as_ref(),
+        options.digest,
+        table.map(|table| (index, table)),
+    )?;
+    if let (Some(backup), Some(manifest)) = (backup, &manifest) {
+        backup.finish(&output_path, manifest)?;
+    }
+    progress.finished();
 
     Ok(CompressionResult {
         input_size: total_size,
@@ -671,129 +1390,295 @@ pub fn compress_directory(
 }
 
 /// Calcola la dimensione totale di una directory
-pub fn calculate_dir_size(dir: &Path) -> std::io::Result<u64> {
-    let mut size = 0;
-    for entry in std::fs::read_dir(dir)? {
-        let entry = entry?;
-        let path = entry.path();
-        if path.is_dir() {
-            size += calculate_dir_size(&path)?;
-        } else {
-            size += std::fs::metadata(&path)?.len();
-        }
-    }
-    Ok(size)
+pub fn calculate_dir_size(dir: &Path) -> Result<u64> {
+    let entries = collect_dir_entries(dir, &WalkOptions::default())?;
+    Ok(total_entries_size(&entries))
 }
 
-/// Conta i file in una directory ricorsivamente
-pub fn count_files_in_dir(dir: &Path) -> std::io::Result<u64> {
-    let mut count = 0;
-    for entry in std::fs::read_dir(dir)? {
-        let entry = entry?;
-        let path = entry.path();
-        if path.is_dir() {
-            count += count_files_in_dir(&path)?;
-        } else {
-            count += 1;
-        }
-    }
-    Ok(count)
+/// Calcola la dimensione dei soli file che `compress_directory` archivierebbe
+pub fn calculate_dir_size_with_options(dir: &Path, options: &CompressOptions) -> Result<u64> {
+    let entries = collect_dir_entries(dir, &WalkOptions::from(options))?;
+    Ok(total_entries_size(&entries))
+}
+
+/// Conta i file (e i link) in una directory ricorsivamente
+pub fn count_files_in_dir(dir: &Path) -> Result<u64> {
+    let entries = collect_dir_entries(dir, &WalkOptions::default())?;
+    Ok(count_non_dir_entries(&entries))
 }
 
-/// Tracker per il progresso
-struct ProgressTracker<'a> {
-    callback: Option<&'a ProgressCallback>,
-    processed: std::cell::Cell<u64>,
+/// Conta i soli file (e link) che `compress_directory` archivierebbe
+pub fn count_files_in_dir_with_options(dir: &Path, options: &CompressOptions) -> Result<u64> {
+    let entries = collect_dir_entries(dir, &WalkOptions::from(options))?;
+    Ok(count_non_dir_entries(&entries))
 }
 
-impl<'a> ProgressTracker<'a> {
-    fn new(callback: Option<&'a ProgressCallback>) -> Self {
+/// Regole di attraversamento di una directory
+#[derive(Default)]
+struct WalkOptions {
+    filter: PathFilter,
+    respect_ignore_files: bool,
+    follow_symlinks: bool,
+}
+
+impl From<&CompressOptions> for WalkOptions {
+    fn from(options: &CompressOptions) -> Self {
         Self {
-            callback,
-            processed: std::cell::Cell::new(0),
+            filter: options.filter.clone(),
+            respect_ignore_files: options.respect_ignore_files,
+            follow_symlinks: options.follow_symlinks,
         }
     }
+}
+
+/// Tipo di un elemento da archiviare
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WalkKind {
+    File,
+    Directory,
+    Symlink,
+}
+
+/// Elemento trovato attraversando una directory
+struct WalkEntry {
+    path: PathBuf,
+    kind: WalkKind,
+    size: u64,
+}
+
+/// Elenca ricorsivamente gli elementi da archiviare, in ordine di nome
+/// (ogni directory precede il proprio contenuto, anche se vuota).
+///
+/// Le directory escluse dal filtro non vengono nemmeno visitate; con
+/// `respect_ignore_files` valgono .gitignore, .ignore e .git/info/exclude
+/// (non il gitignore globale dell'utente) e la directory .git viene saltata.
+fn collect_dir_entries(dir: &Path, options: &WalkOptions) -> Result<Vec<WalkEntry>> {
+    let respect_ignore_files = options.respect_ignore_files;
+    let mut walker = ignore::WalkBuilder::new(dir);
+    walker
+        .standard_filters(respect_ignore_files)
+        .hidden(false)
+        .git_global(false)
+        .require_git(false)
+        .follow_links(options.follow_symlinks)
+        .sort_by_file_name(|a, b| a.cmp(b));
+
+    let base = dir.to_path_buf();
+    let prune = options.filter.clone();
+    walker.filter_entry(move |entry| {
+        if entry.depth() == 0 {
+            return true;
+        }
+        if respect_ignore_files && entry.file_name() == ".git" {
+            return false;
+        }
+        let relative = entry.path().strip_prefix(&base).unwrap_or(entry.path());
+        !prune.is_excluded(relative)
+    });
+
+    let mut entries = Vec::new();
+    for result in walker.build() {
+        let (path, file_type) = match result {
+            Ok(entry) if entry.depth() == 0 => continue,
+            Ok(entry) => {
+                let file_type = entry.file_type();
+                (entry.into_path(), file_type)
+            }
+            // Seguendo i link, un ciclo o una destinazione mancante producono un
+            // errore: il link viene allora salvato come tale
+            Err(err) => match walk_error_path(&err).filter(|p| p.is_symlink()) {
+                Some(path) => (path.to_path_buf(), None),
+                None => return Err(walk_error(err).into()),
+            },
+        };
 
-    fn add(&self, bytes: u64) {
-        let new_total = self.processed.get() + bytes;
-        self.processed.set(new_total);
-        if let Some(callback) = self.callback {
-            callback(new_total);
+        let relative = path.strip_prefix(dir).unwrap_or(&path);
+        if !options.filter.matches(relative) {
+            continue;
         }
+
+        let kind = match file_type {
+            Some(t) if t.is_dir() => WalkKind::Directory,
+            Some(t) if t.is_file() => WalkKind::File,
+            _ if path.is_symlink() => WalkKind::Symlink,
+            _ => WalkKind::File,
+        };
+        let size = match kind {
+            WalkKind::File => std::fs::metadata(&path)?.len(),
+            _ => 0,
+        };
+        entries.push(WalkEntry { path, kind, size });
+    }
+    Ok(entries)
+}
+
+/// Percorso a cui si riferisce un errore dell'attraversamento
+fn walk_error_path(err: &ignore::Error) -> Option<&Path> {
+    match err {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::WithDepth { err, .. } => walk_error_path(err),
+        ignore::Error::Loop { child, .. } => Some(child),
+        _ => None,
     }
 }
 
-/// Aggiunge una directory al tar ricorsivamente con progress tracking
-fn add_dir_to_tar_with_progress<W: Write>(
+fn walk_error(err: ignore::Error) -> std::io::Error {
+    let message = err.to_string();
+    err.into_io_error()
+        .unwrap_or_else(|| std::io::Error::other(message))
+}
+
+/// Somma le dimensioni dei file regolari
+fn total_entries_size(entries: &[WalkEntry]) -> u64 {
+    entries.iter().map(|e| e.size).sum()
+}
+
+/// Conta file e link (le directory non contano)
+fn count_non_dir_entries(entries: &[WalkEntry]) -> u64 {
+    entries
+        .iter()
+        .filter(|e| e.kind != WalkKind::Directory)
+        .count() as u64
+}
+
+/// Aggiunge al tar file, directory e link con progress tracking.
+///
+/// Gli eventi di progresso vengono notificati per ogni file o link, non per le directory.
+/// Con un manifest, il digest di ogni file viene calcolato mentre viene archiviato;
+/// `begin_member` riceve l'output del tar e il nome prima di ogni entry. I file
+/// letti in anticipo vengono presi da `read_ahead` nell'ordine dell'elenco.
+fn append_entries_with_progress<W: Write>(
     tar: &mut Builder<W>,
     base_path: &Path,
-    current_path: &Path,
-    progress: &ProgressTracker,
-) -> std::io::Result<()> {
-    for entry in std::fs::read_dir(current_path)? {
-        let entry = entry?;
-        let path = entry.path();
-        let relative_path = path
+    read_ahead: &ReadAhead,
+    progress: Progress,
+    cancel_flag: Option<&Arc<AtomicBool>>,
+    mut manifest: Option<&mut Manifest>,
+    mut begin_member: impl FnMut(&mut W, &Path) -> std::io::Result<()>,
+) -> Result<()> {
+    let entries = read_ahead.entries();
+    let total = total_entries_size(entries);
+    let mut processed = 0u64;
+    for (position, entry) in entries.iter().enumerate() {
+        check_cancelled(cancel_flag)?;
+        let relative_path = entry
+            .path
             .strip_prefix(base_path)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?;
-
-        if path.is_dir() {
-            add_dir_to_tar_with_progress(tar, base_path, &path, progress)?;
-        } else {
-            let file_size = std::fs::metadata(&path)?.len();
-            tar.append_path_with_name(&path, relative_path)?;
-            progress.add(file_size);
+            .map_err(|e| Error::InvalidInput(e.to_string()))?;
+
+        begin_member(tar.get_mut(), relative_path)?;
+        match entry.kind {
+            WalkKind::Directory => tar.append_dir(relative_path, &entry.path)?,
+            WalkKind::Symlink => {
+                let target = std::fs::read_link(&entry.path)?;
+                let mut header = tar::Header::new_gnu();
+                header.set_metadata(&std::fs::symlink_metadata(&entry.path)?);
+                header.set_entry_type(tar::EntryType::Symlink);
+                header.set_size(0);
+                progress.file_started(relative_path);
+                tar.append_link(&mut header, relative_path, target)?;
+                progress.file_finished(relative_path, 0, 0);
+                progress.bytes(processed, Some(total));
+            }
+            WalkKind::File => {
+                progress.file_started(relative_path);
+                let prefetched = read_ahead.take(position)?;
+                append_file(
+                    tar,
+                    &entry.path,
+                    relative_path,
+                    prefetched,
+                    manifest.as_deref_mut(),
+                )?;
+                processed += entry.size;
+                progress.file_finished(relative_path, entry.size, entry.size);
+                progress.bytes(processed, Some(total));
+            }
         }
     }
     Ok(())
 }
 
-/// Aggiunge una directory al tar ricorsivamente (versione semplice)
+/// Aggiunge una directory al tar ricorsivamente (versione semplice).
+///
+/// I link simbolici vengono salvati come link.
 pub fn add_dir_to_tar<W: Write>(
     tar: &mut Builder<W>,
     base_path: &Path,
     current_path: &Path,
-) -> std::io::Result<()> {
-    for entry in std::fs::read_dir(current_path)? {
-        let entry = entry?;
-        let path = entry.path();
-        let relative_path = path
-            .strip_prefix(base_path)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?;
+) -> Result<()> {
+    let entries = collect_dir_entries(current_path, &WalkOptions::default())?;
+    append_entries_with_progress(
+        tar,
+        base_path,
+        &ReadAhead::new(&entries, 0),
+        Progress::none(),
+        None,
+        None,
+        |_, _| Ok(()),
+    )
+}
 
-        if path.is_dir() {
-            add_dir_to_tar(tar, base_path, &path)?;
-        } else {
-            tar.append_path_with_name(&path, relative_path)?;
+/// Aggiunge un file al tar con il nome indicato, registrandone il digest nel manifest.
+///
+/// Un file già letto in anticipo viene archiviato dalla memoria.
+fn append_file<W: Write>(
+    tar: &mut Builder<W>,
+    path: &Path,
+    name: &Path,
+    prefetched: Option<PrefetchedFile>,
+    manifest: Option<&mut Manifest>,
+) -> Result<()> {
+    if let Some(file) = prefetched {
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(&file.metadata);
+        // La dimensione è quella letta, anche se il file è cambiato nel frattempo
+        header.set_size(file.data.len() as u64);
+        if let Some(manifest) = manifest {
+            manifest.push(name, Sha256Digest::of(&file.data));
         }
+        tar.append_data(&mut header, name, file.data.as_slice())?;
+        return Ok(());
     }
+
+    let Some(manifest) = manifest else {
+        tar.append_path_with_name(path, name)?;
+        return Ok(());
+    };
+
+    // Stesso header di append_path_with_name, ma i dati passano dal digest
+    let file = File::open(path)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_metadata(&file.metadata()?);
+    let mut digest = DigestWriter::default();
+    let reader = DigestReader {
+        inner: file,
+        digest: &mut digest,
+    };
+    tar.append_data(&mut header, name, reader)?;
+    manifest.push(name, digest.finish());
     Ok(())
 }
 
-/// Comprime più file in un singolo archivio tar.zst
+/// Comprime più file in un singolo archivio tar compresso
 pub fn compress_multiple_files(
     input_files: &[PathBuf],
     output_path: &Path,
     options: &CompressOptions,
-) -> std::io::Result<CompressionResult> {
+) -> Result<CompressionResult> {
+    options.algorithm.compressor()?;
+    check_digest_mode(options)?;
+
     // Verifica che tutti i file esistano
     for file in input_files {
         if !file.exists() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                format!("Il file {:?} non esiste", file),
-            ));
+            return Err(Error::NotFound(format!("Il file {:?} non esiste", file)));
         }
     }
 
-    if output_path.exists() && !options.force {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::AlreadyExists,
-            format!(
-                "Il file di output {:?} esiste già. Usa --force per sovrascrivere.",
-                output_path
-            ),
-        ));
+    let target = archive_output_target(output_path, options);
+    if target.exists() && !options.force {
+        return Err(Error::AlreadyExists(target));
     }
 
     // Calcola dimensione totale per buffer ottimale
@@ -804,45 +1689,54 @@ pub fn compress_multiple_files(
         .sum();
     let buffer_size = optimal_buffer_size(total_size);
 
-    let output_file = File::create(output_path)?;
-    let writer = BufWriter::with_capacity(buffer_size, output_file);
-    let mut encoder = zstd::Encoder::new(writer, options.level)?;
-
-    // Abilita multithreading automatico per archivi grandi
-    if options.should_use_parallel(total_size) {
-        encoder.set_parameter(zstd::zstd_safe::CParameter::NbWorkers(num_cpus()))?;
-    }
-
-    // Ottimizzazioni per archivi grandi
-    if total_size >= LARGE_FILE_THRESHOLD {
-        encoder.set_parameter(zstd::zstd_safe::CParameter::WindowLog(24))?;
-        encoder.set_parameter(zstd::zstd_safe::CParameter::EnableLongDistanceMatching(
-            true,
-        ))?;
-    }
+    let output_file = ArchiveOutput::create(output_path, options.split_size, options.algorithm)?;
+    let mut writer = BufWriter::with_capacity(buffer_size, output_file);
+    let settings = EncoderSettings {
+        level: options.level,
+        dictionary: options.dictionary.as_ref(),
+        pledged_size: None,
+        zstd_parameters: archive_encoder_parameters(options, total_size),
+    };
+    let encoder = OutputEncoder::new(&mut writer, &settings, options)?;
 
     let mut tar = Builder::new(encoder);
     let mut total_input_size = 0u64;
-    let mut processed = 0u64;
+    let mut manifest = options.digest.map(|_| Manifest::default());
+    let mut index = ArchiveIndex::default();
 
+    let progress = Progress::new(options.progress_handler.as_ref());
+    progress.started(Some(total_size), Some(input_files.len() as u64));
     for file in input_files {
+        check_cancelled(options.cancel_flag.as_ref())?;
         let file_name = file
             .file_name()
             .unwrap_or_else(|| std::ffi::OsStr::new("file"));
         let file_size = std::fs::metadata(file)?.len();
-        tar.append_path_with_name(file, file_name)?;
-        total_input_size += file_size;
-        processed += file_size;
-
-        if let Some(ref callback) = options.progress_callback {
-            callback(processed);
+        progress.file_started(file);
+        if let Some(offset) = tar.get_mut().begin_member()? {
+            index.push(Path::new(file_name), offset);
         }
+        append_file(
+            &mut tar,
+            file,
+            Path::new(file_name),
+            None,
+            manifest.as_mut(),
+        )?;
+        total_input_size += file_size;
+        progress.file_finished(file, file_size, file_size);
+        progress.bytes(total_input_size, Some(total_size));
     }
 
-    let encoder = tar.into_inner()?;
-    encoder.finish()?;
-
-    let output_size = std::fs::metadata(output_path)?.len();
+    let table = tar.into_inner()?.finish()?;
+    let output_size = commit_archive_output(
+        writer,
+        output_path,
+        manifest.as_ref(),
+        options.digest,
+        table.map(|table| (index, table)),
+    )?;
+    progress.finished();
 
     Ok(CompressionResult {
         input_size: total_input_size,
@@ -850,12 +1744,75 @@ pub fn compress_multiple_files(
     })
 }
 
+/// Metadati ripristinati durante l'estrazione di un archivio tar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MetadataRestore {
+    /// Nessuno: permessi di default (umask) e data di modifica corrente
+    None,
+    /// Permessi rwx e data di modifica (default)
+    #[default]
+    Basic,
+    /// Anche setuid/setgid/sticky e proprietario/gruppo (richiede privilegi di root)
+    All,
+}
+
+/// Valida il livello di ripristino dei metadati
+pub fn parse_metadata_restore(s: &str) -> Result<MetadataRestore, String> {
+    match s.to_lowercase().as_str() {
+        "none" | "nessuno" => Ok(MetadataRestore::None),
+        "basic" | "base" => Ok(MetadataRestore::Basic),
+        "all" | "tutto" => Ok(MetadataRestore::All),
+        _ => Err(format!("Valore '{}' non valido: usa none, basic o all", s)),
+    }
+}
+
+/// Comportamento con entry non sicure (percorsi assoluti, `..`, link che escono
+/// dalla directory di destinazione)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnsafeEntryPolicy {
+    /// Interrompe l'estrazione con un errore (default)
+    #[default]
+    Reject,
+    /// Salta l'entry e prosegue con le successive
+    Skip,
+    /// Rimuove `/` iniziali e componenti `..` dal percorso; i link non sicuri vengono saltati
+    Sanitize,
+}
+
+/// Valida la politica per le entry non sicure
+pub fn parse_unsafe_entry_policy(s: &str) -> Result<UnsafeEntryPolicy, String> {
+    match s.to_lowercase().as_str() {
+        "reject" | "rifiuta" => Ok(UnsafeEntryPolicy::Reject),
+        "skip" | "salta" => Ok(UnsafeEntryPolicy::Skip),
+        "sanitize" | "sanifica" => Ok(UnsafeEntryPolicy::Sanitize),
+        _ => Err(format!(
+            "Valore '{}' non valido: usa reject, skip o sanitize",
+            s
+        )),
+    }
+}
+
 /// Opzioni per la decompressione
 #[derive(Default)]
 pub struct DecompressOptions {
     pub force: bool,
     pub output_path: Option<PathBuf>,
-    pub progress_callback: Option<ProgressCallback>,
+    pub dictionary: Option<CompressionDictionary>,
+    /// Entry da estrarre dagli archivi tar (default: tutte)
+    pub filter: PathFilter,
+    /// Permessi, date e proprietari da ripristinare sulle entry estratte
+    pub restore_metadata: MetadataRestore,
+    /// Cosa fare con entry che scriverebbero fuori dalla directory di output
+    pub unsafe_entries: UnsafeEntryPolicy,
+    /// Limiti contro le decompression bomb (default: nessuno)
+    pub limits: DecompressionLimits,
+    /// Se impostato a true l'operazione si interrompe
+    pub cancel_flag: Option<Arc<AtomicBool>>,
+    /// La verifica ricalcola lo SHA-256 e lo confronta con il manifest
+    pub deep_verify: bool,
+    /// Decomprime in parallelo i frame dei file zstd seekable
+    pub parallel: bool,
+    pub progress_handler: Option<ProgressHandler>,
 }
 
 impl DecompressOptions {
@@ -874,58 +1831,125 @@ impl DecompressOptions {
         self
     }
 
-    pub fn with_progress<F>(mut self, callback: F) -> Self
+    /// Usa il dizionario zstd con cui i file sono stati compressi
+    pub fn with_dictionary(mut self, dictionary: CompressionDictionary) -> Self {
+        self.dictionary = Some(dictionary);
+        self
+    }
+
+    /// Estrae solo le entry che superano il filtro include/exclude
+    pub fn with_filter(mut self, filter: PathFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Sceglie quali metadati (permessi, data, proprietario) ripristinare
+    pub fn with_restore_metadata(mut self, restore: MetadataRestore) -> Self {
+        self.restore_metadata = restore;
+        self
+    }
+
+    /// Sceglie come gestire entry con percorsi o link non sicuri
+    pub fn with_unsafe_entries(mut self, policy: UnsafeEntryPolicy) -> Self {
+        self.unsafe_entries = policy;
+        self
+    }
+
+    /// Imposta i limiti su bytes prodotti, rapporto di espansione ed entry
+    pub fn with_limits(mut self, limits: DecompressionLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Flag di annullamento condiviso: quando diventa true l'operazione termina
+    /// con [`Error::Cancelled`] senza lasciare output parziali
+    pub fn with_cancel_flag(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.cancel_flag = Some(flag);
+        self
+    }
+
+    /// Verifica approfondita: ricalcola lo SHA-256 dei dati decompressi e lo
+    /// confronta con il manifest incorporato o con il sidecar `.sha256`
+    pub fn with_deep_verify(mut self, deep: bool) -> Self {
+        self.deep_verify = deep;
+        self
+    }
+
+    /// Decomprime i frame dei file zstd seekable in parallelo sul pool di rayon;
+    /// gli altri file vengono decompressi come sempre
+    pub fn with_parallel(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    /// Riceve gli eventi di progresso dell'operazione
+    pub fn with_progress_events<F>(mut self, handler: F) -> Self
     where
-        F: Fn(u64) + Send + Sync + 'static,
+        F: Fn(&ProgressEvent) + Send + Sync + 'static,
     {
-        self.progress_callback = Some(Box::new(callback));
+        self.progress_handler = Some(Box::new(handler));
         self
     }
+
+    /// Vecchia API: il callback riceve i bytes per i file singoli e il numero
+    /// di file estratti per gli archivi
+    #[deprecated(note = "usa with_progress_events")]
+    pub fn with_progress<F>(self, callback: F) -> Self
+    where
+        F: Fn(u64) + Send + Sync + 'static,
+    {
+        self.with_progress_events(progress::bytes_or_entries(callback))
+    }
+}
+
+/// Algoritmo con cui decomprimere un file, rilevato dal contenuto
+fn decompression_algorithm(input_path: &Path) -> Result<CompressionAlgorithm> {
+    CompressionAlgorithm::detect(input_path)?.ok_or_else(unrecognized_format)
 }
 
-/// Decomprime un file .zst o .tar.zst
+/// Decomprime un file compresso o un archivio tar compresso.
+///
+/// Formato e presenza del tar sono rilevati dal contenuto, quindi anche file
+/// rinominati o con estensione .tzst vengono gestiti correttamente.
 pub fn decompress_file(
     input_path: &Path,
     options: &DecompressOptions,
-) -> std::io::Result<CompressionResult> {
+) -> Result<CompressionResult> {
     if !input_path.exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("Il file di input {:?} non esiste", input_path),
-        ));
-    }
-
-    let extension = input_path
-        .extension()
-        .and_then(std::ffi::OsStr::to_str)
-        .unwrap_or("");
-
-    if extension != "zst" {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "Il file di input deve avere estensione .zst",
-        ));
+        return Err(Error::NotFound(format!(
+            "Il file di input {:?} non esiste",
+            input_path
+        )));
     }
 
-    let is_tar = input_path.to_string_lossy().ends_with(".tar.zst");
-
-    if is_tar {
+    if detect_format(input_path, options.dictionary.as_ref())?.is_tar {
         decompress_tar_zst(input_path, options)
     } else {
         decompress_single_file(input_path, options)
     }
 }
 
-/// Decomprime un singolo file .zst
+/// Decomprime un singolo file .zst (l'algoritmo è rilevato dal contenuto)
 pub fn decompress_single_file(
     input_path: &Path,
     options: &DecompressOptions,
-) -> std::io::Result<CompressionResult> {
-    // Calcola il nome del file decompresso
-    let default_output = input_path.with_extension("");
-    let default_file_name = default_output.file_name().ok_or_else(|| {
-        std::io::Error::new(std::io::ErrorKind::InvalidInput, "Nome file non valido")
-    })?;
+) -> Result<CompressionResult> {
+    let algorithm = decompression_algorithm(input_path)?;
+    algorithm.compressor()?;
+
+    // Calcola il nome del file decompresso: senza un'estensione nota di
+    // compressione (file rinominato) si aggiunge .out per non toccare l'input
+    let input_name = volume::logical_path(input_path);
+    let default_output = if CompressionAlgorithm::from_path(&input_name).is_some() {
+        input_name.with_extension("")
+    } else {
+        let mut name = input_name.as_os_str().to_os_string();
+        name.push(".out");
+        PathBuf::from(name)
+    };
+    let default_file_name = default_output
+        .file_name()
+        .ok_or_else(|| Error::InvalidInput("Nome file non valido".to_string()))?;
 
     // Usa output_path personalizzato se specificato
     let output_path = match &options.output_path {
@@ -940,13 +1964,7 @@ pub fn decompress_single_file(
     };
 
     if output_path.exists() && !options.force {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::AlreadyExists,
-            format!(
-                "Il file di output {:?} esiste già. Usa --force per sovrascrivere.",
-                output_path
-            ),
-        ));
+        return Err(Error::AlreadyExists(output_path.to_path_buf()));
     }
 
     // Crea le directory padre se non esistono
@@ -956,24 +1974,41 @@ pub fn decompress_single_file(
         }
     }
 
-    let input_size = std::fs::metadata(input_path)?.len();
+    let (input_file, input_size) = open_input(input_path)?;
 
     // Usa buffer ottimale
     let buffer_size = optimal_buffer_size(input_size);
 
-    let input_file = File::open(input_path)?;
-    let output_file = File::create(&output_path)?;
+    let content_size = output_size_hint(algorithm, input_path)?;
+
+    // Il progresso si misura sui bytes compressi letti, non su una stima
+    let compressed = Cell::new(0);
+    let input_file = CountingReader {
+        inner: input_file,
+        count: &compressed,
+    };
+    let output_file = AtomicFile::create(&output_path)?;
 
-    let reader = BufReader::with_capacity(buffer_size, input_file);
+    let mut reader = BufReader::with_capacity(buffer_size, input_file);
     let mut writer = BufWriter::with_capacity(buffer_size, output_file);
 
-    let mut decoder = zstd::Decoder::new(reader)?;
+    let decoder = input_decoder(algorithm, &mut reader, input_path, options)?;
+    let mut decoder = LimitedReader::new(
+        decoder,
+        &options.limits,
+        CompressedSize::Known(input_size),
+        true,
+    );
 
     let mut buffer = vec![0u8; buffer_size];
     let mut total_written = 0u64;
     let mut last_progress_update = 0u64;
 
+    let progress = Progress::new(options.progress_handler.as_ref());
+    progress.started(Some(input_size), Some(1));
+    progress.file_started(input_path);
     loop {
+        check_cancelled(options.cancel_flag.as_ref())?;
         let bytes_read = decoder.read(&mut buffer)?;
         if bytes_read == 0 {
             break;
@@ -982,24 +2017,23 @@ pub fn decompress_single_file(
         total_written += bytes_read as u64;
 
         // Aggiorna progress ogni 1MB per evitare troppe chiamate
-        if let Some(ref callback) = options.progress_callback {
-            if total_written - last_progress_update >= 1024 * 1024 {
-                // Stima del progresso basata sul ratio tipico di compressione
-                let estimated_progress = (total_written as f64 / 3.0).min(input_size as f64) as u64;
-                callback(estimated_progress);
-                last_progress_update = total_written;
-            }
+        if total_written - last_progress_update >= 1024 * 1024 {
+            progress.bytes(compressed.get(), Some(input_size));
+            progress.output(total_written, known_total(content_size, total_written));
+            last_progress_update = total_written;
         }
     }
 
-    writer.flush()?;
+    drop(decoder);
+    commit_output(writer)?;
 
     // Notifica completamento
-    if let Some(ref callback) = options.progress_callback {
-        callback(input_size);
-    }
+    progress.bytes(compressed.get(), Some(input_size));
+    progress.output(total_written, known_total(content_size, total_written));
 
     let output_size = std::fs::metadata(&output_path)?.len();
+    progress.file_finished(input_path, input_size, output_size);
+    progress.finished();
 
     Ok(CompressionResult {
         input_size,
@@ -1007,12 +2041,74 @@ pub fn decompress_single_file(
     })
 }
 
-/// Decomprime un archivio tar.zst
+/// Decoder dei dati compressi: con `options.parallel` i frame di un file zstd
+/// seekable vengono decompressi in parallelo, altrimenti lo stream è sequenziale
+fn input_decoder<'a>(
+    algorithm: CompressionAlgorithm,
+    reader: &'a mut dyn BufRead,
+    input_path: &Path,
+    options: &'a DecompressOptions,
+) -> Result<Box<dyn Read + 'a>> {
+    if options.parallel && algorithm == CompressionAlgorithm::Zstd {
+        if let Some(table) = SeekTable::read(input_path)? {
+            table.check_limits(&options.limits)?;
+            let dictionary = options.dictionary.as_ref();
+            return Ok(Box::new(ParallelDecoder::new(reader, table, dictionary)));
+        }
+    }
+    Ok(algorithm
+        .compressor()?
+        .decoder(reader, options.dictionary.as_ref())?)
+}
+
+/// Dimensione decompressa dichiarata nell'header, se il formato la prevede
+fn output_size_hint(algorithm: CompressionAlgorithm, input_path: &Path) -> Result<Option<u64>> {
+    match algorithm {
+        // Nel formato seekable la tabella riporta il totale di tutti i frame
+        CompressionAlgorithm::Zstd => match SeekTable::read(input_path)? {
+            Some(table) => Ok(Some(table.decompressed_size())),
+            None => Ok(codec::zstd_content_size(input_path)?),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Totale decompresso da riportare: la dimensione dell'header vale finché non
+/// viene superata (file con più frame, di cui è noto solo il primo)
+fn known_total(content_size: Option<u64>, done: u64) -> Option<u64> {
+    content_size.filter(|&size| done <= size)
+}
+
+/// Decomprime un archivio tar.zst (o tar.gz, tar.lz4, ... rilevato dal contenuto)
 pub fn decompress_tar_zst(
     input_path: &Path,
     options: &DecompressOptions,
-) -> std::io::Result<CompressionResult> {
-    let file_stem = input_path
+) -> Result<CompressionResult> {
+    let algorithm = decompression_algorithm(input_path)?;
+    algorithm.compressor()?;
+
+    let output_dir = tar_output_dir(input_path, options)?;
+    if output_dir.exists() && !options.force {
+        return Err(Error::AlreadyExists(output_dir.to_path_buf()));
+    }
+
+    let created_output_dir = !output_dir.exists();
+    std::fs::create_dir_all(&output_dir)?;
+
+    unpack_archive(input_path, &output_dir, options).inspect_err(|_| {
+        // Un'estrazione fallita o annullata non lascia una directory a metà,
+        // a meno che non esistesse già (--force)
+        if created_output_dir {
+            let _ = std::fs::remove_dir_all(&output_dir);
+        }
+    })
+}
+
+/// Directory in cui estrarre un archivio tar: quella indicata nelle opzioni o,
+/// in mancanza, una con il nome dell'archivio senza estensioni
+fn tar_output_dir(input_path: &Path, options: &DecompressOptions) -> Result<PathBuf> {
+    let input_name = volume::logical_path(input_path);
+    let file_stem = input_name
         .file_stem()
         .and_then(|s| Path::new(s).file_stem())
         .unwrap_or_else(|| std::ffi::OsStr::new("output"));
@@ -1021,206 +2117,1213 @@ pub fn decompress_tar_zst(
     let output_dir = match &options.output_path {
         Some(p) => {
             if p.exists() && !p.is_dir() {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidInput,
-                    "Per archivi tar.zst, l'output deve essere una directory",
+                return Err(Error::InvalidInput(
+                    "Per archivi tar.zst, l'output deve essere una directory".to_string(),
                 ));
             }
             p.clone()
         }
-        None => input_path
-            .parent()
-            .unwrap_or(Path::new("."))
-            .join(file_stem),
+        None => {
+            let dir = input_name
+                .parent()
+                .unwrap_or(Path::new("."))
+                .join(file_stem);
+            // Archivio rinominato senza estensione: evita di usare il file stesso
+            if dir == input_name {
+                dir.with_extension("d")
+            } else {
+                dir
+            }
+        }
     };
+    Ok(output_dir)
+}
+
+/// Risultato del ripristino di una catena di backup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RestoreResult {
+    /// Archivi applicati, dal backup completo a quello indicato
+    pub archives: Vec<PathBuf>,
+    /// Bytes compressi letti da tutti gli archivi
+    pub input_size: u64,
+    /// Bytes estratti da tutti gli archivi
+    pub output_size: u64,
+    /// Elementi rimossi perché eliminati in un backup successivo
+    pub deleted: u64,
+}
+
+/// Ripristina un backup incrementale fino all'archivio indicato.
+///
+/// Risale la catena con gli stati `<archivio>.state` (vedi [`incremental`]),
+/// estrae il backup completo e poi, in ordine, ogni backup incrementale fino a
+/// `archive_path`, rimuovendo prima gli elementi che ciascuno registra come
+/// eliminati. La directory di output è quella di [`decompress_tar_zst`].
+pub fn restore_backup(archive_path: &Path, options: &DecompressOptions) -> Result<RestoreResult> {
+    let chain = backup_chain(archive_path)?;
+    for (archive, _) in &chain {
+        decompression_algorithm(archive)?.compressor()?;
+    }
 
+    let output_dir = tar_output_dir(archive_path, options)?;
     if output_dir.exists() && !options.force {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::AlreadyExists,
-            format!(
-                "La directory di output {:?} esiste già. Usa --force per sovrascrivere.",
-                output_dir
-            ),
-        ));
+        return Err(Error::AlreadyExists(output_dir.to_path_buf()));
     }
 
+    let created_output_dir = !output_dir.exists();
     std::fs::create_dir_all(&output_dir)?;
 
-    let input_size = std::fs::metadata(input_path)?.len();
+    replay_backup_chain(&chain, &output_dir, options).inspect_err(|_| {
+        if created_output_dir {
+            let _ = std::fs::remove_dir_all(&output_dir);
+        }
+    })
+}
+
+fn replay_backup_chain(
+    chain: &[(PathBuf, BackupState)],
+    output_dir: &Path,
+    options: &DecompressOptions,
+) -> Result<RestoreResult> {
+    let output_root = output_dir.canonicalize()?;
+    let mut result = RestoreResult {
+        archives: Vec::with_capacity(chain.len()),
+        input_size: 0,
+        output_size: 0,
+        deleted: 0,
+    };
+    for (archive, state) in chain {
+        for path in &state.deleted {
+            check_cancelled(options.cancel_flag.as_ref())?;
+            if remove_deleted_entry(&output_root, path, options)? {
+                result.deleted += 1;
+            }
+        }
+        let extracted = unpack_archive(archive, &output_root, options)?;
+        result.input_size += extracted.input_size;
+        result.output_size += extracted.output_size;
+        result.archives.push(archive.clone());
+    }
+    Ok(result)
+}
+
+/// Rimuove dalla directory ripristinata un elemento eliminato in un backup
+/// incrementale; ritorna false se non c'era o se il filtro lo esclude
+fn remove_deleted_entry(
+    output_root: &Path,
+    path: &Path,
+    options: &DecompressOptions,
+) -> Result<bool> {
+    let path = incremental::checked_state_path(path)?;
+    if !options.filter.matches(path) {
+        return Ok(false);
+    }
+
+    // Come in estrazione, un link nella directory non porta a rimuovere fuori
+    let dest_path = output_root.join(path);
+    validate_output_path(dest_path.parent().unwrap_or(output_root), Some(output_root))?;
+    let removed = match dest_path.symlink_metadata() {
+        Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(&dest_path),
+        Ok(_) => std::fs::remove_file(&dest_path),
+        Err(e) => Err(e),
+    };
+    match removed {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Estrae un archivio tar compresso nella directory `output_dir`, che esiste già
+fn unpack_archive(
+    input_path: &Path,
+    output_dir: &Path,
+    options: &DecompressOptions,
+) -> Result<CompressionResult> {
+    let algorithm = decompression_algorithm(input_path)?;
+    let (input_file, input_size) = open_input(input_path)?;
 
     // Usa buffer ottimale
     let buffer_size = optimal_buffer_size(input_size);
 
-    let input_file = File::open(input_path)?;
-    let reader = BufReader::with_capacity(buffer_size, input_file);
-    let decoder = zstd::Decoder::new(reader)?;
-    let mut archive = Archive::new(decoder);
+    let compressed = Cell::new(0);
+    let input_file = CountingReader {
+        inner: input_file,
+        count: &compressed,
+    };
+    let mut reader = BufReader::with_capacity(buffer_size, input_file);
+    let decoder = input_decoder(algorithm, &mut reader, input_path, options)?;
+    let mut archive = Archive::new(LimitedReader::new(
+        decoder,
+        &options.limits,
+        CompressedSize::Known(input_size),
+        false,
+    ));
+    archive.set_preserve_permissions(options.restore_metadata == MetadataRestore::All);
+    archive.set_preserve_ownerships(options.restore_metadata == MetadataRestore::All);
+    archive.set_preserve_mtime(options.restore_metadata != MetadataRestore::None);
+
+    let input = InputProgress {
+        read: &compressed,
+        total: input_size,
+    };
+    let output_size = unpack_entries(archive, output_dir, options, input)?;
+    Progress::new(options.progress_handler.as_ref()).finished();
+    Ok(CompressionResult {
+        input_size,
+        output_size,
+    })
+}
 
-    let mut file_count = 0u64;
+/// Bytes compressi letti finora su un input di dimensione nota
+#[derive(Clone, Copy)]
+struct InputProgress<'a> {
+    read: &'a Cell<u64>,
+    total: u64,
+}
+
+/// Estrae le entry dell'archivio in `output_dir`; ritorna i bytes estratti
+fn unpack_entries<R: Read>(
+    mut archive: Archive<R>,
+    output_dir: &Path,
+    options: &DecompressOptions,
+    input: InputProgress,
+) -> Result<u64> {
     let mut total_extracted = 0u64;
+    let mut entries_seen = 0u64;
+
+    let progress = Progress::new(options.progress_handler.as_ref());
+    progress.started(Some(input.total), None);
+
+    // Confronto con il percorso canonico: i link già estratti vengono risolti
+    let output_root = output_dir.canonicalize()?;
+    let policy = options.unsafe_entries;
 
     for entry in archive.entries()? {
+        check_cancelled(options.cancel_flag.as_ref())?;
         let mut entry = entry?;
-        let path = entry.path()?;
-        let dest_path = output_dir.join(&path);
+        entries_seen += 1;
+        options.limits.check_entries(entries_seen)?;
+
+        let raw_path = entry.path()?.into_owned();
+        let path = match checked_entry_path(&raw_path, policy)? {
+            Some(path) => path,
+            None => {
+                // La radice "./" degli archivi creati con tar non è un'entry saltata
+                if raw_path.components().any(|c| c != Component::CurDir) {
+                    progress.skipped(&raw_path, "percorso non sicuro");
+                }
+                continue;
+            }
+        };
 
-        if let Some(parent) = dest_path.parent() {
-            std::fs::create_dir_all(parent)?;
+        // Le entry escluse vengono saltate senza scriverle: il lettore tar
+        // ne scarta i dati passando all'entry successiva
+        if !options.filter.matches(&path) {
+            continue;
+        }
+
+        // La dimensione dichiarata nell'header è quella che tar leggerà al massimo
+        options.limits.check_entry_size(entry.size())?;
+
+        let entry_type = entry.header().entry_type();
+        let mut hard_link_target = None;
+        if entry_type.is_symlink() || entry_type.is_hard_link() {
+            let target = entry.link_name()?.unwrap_or_default().into_owned();
+            let safe = if entry_type.is_symlink() {
+                symlink_stays_inside(&output_root.join(&path), &target, &output_root)?
+            } else {
+                // I target degli hard link sono percorsi dell'archivio
+                hard_link_target = checked_entry_path(&target, UnsafeEntryPolicy::Skip)?;
+                hard_link_target.is_some()
+            };
+            if !safe {
+                let message = format!(
+                    "Link non sicuro nell'archivio: {:?} -> {:?}",
+                    raw_path, target
+                );
+                reject_unsafe_entry(policy, &message)?;
+                progress.skipped(&raw_path, &message);
+                continue;
+            }
+        }
+
+        let dest_path = output_root.join(&path);
+        let parent = dest_path.parent().unwrap_or(&output_root);
+
+        // Un link estratto in precedenza potrebbe portare la destinazione fuori
+        if let Err(e) = validate_output_path(parent, Some(&output_root)) {
+            reject_unsafe_entry(policy, &e.to_string())?;
+            progress.skipped(&raw_path, &e.to_string());
+            continue;
+        }
+        std::fs::create_dir_all(parent)?;
+
+        // Non si scrive mai attraverso un link esistente: viene sostituito
+        if dest_path
+            .symlink_metadata()
+            .is_ok_and(|m| m.file_type().is_symlink())
+        {
+            std::fs::remove_file(&dest_path)?;
+        }
+
+        if let Some(target) = hard_link_target {
+            let source = match validate_output_path(&output_root.join(&target), Some(&output_root))
+            {
+                Ok(source) => source,
+                Err(e) => {
+                    reject_unsafe_entry(policy, &e.to_string())?;
+                    progress.skipped(&raw_path, &e.to_string());
+                    continue;
+                }
+            };
+            if dest_path.exists() {
+                std::fs::remove_file(&dest_path)?;
+            }
+            progress.file_started(&path);
+            std::fs::hard_link(source, &dest_path)?;
+            progress.file_finished(&path, 0, 0);
+            continue;
         }
 
         let entry_size = entry.size();
-        entry.unpack(&dest_path)?;
-        file_count += 1;
+        // Come in compressione, le directory non generano eventi per file
+        let is_dir = entry_type.is_dir();
+        if !is_dir {
+            progress.file_started(&path);
+        }
+        match (options.restore_metadata, entry.header().entry_type()) {
+            // tar applica sempre i permessi dell'header: senza metadati il file
+            // viene creato normalmente e riceve quelli di default
+            (MetadataRestore::None, tar::EntryType::Regular | tar::EntryType::Continuous) => {
+                let mut output = File::create(&dest_path)?;
+                std::io::copy(&mut entry, &mut output)?;
+            }
+            (MetadataRestore::None, tar::EntryType::Directory) => {
+                std::fs::create_dir_all(&dest_path)?;
+            }
+            _ => {
+                entry.unpack(&dest_path)?;
+            }
+        }
         total_extracted += entry_size;
+        if !is_dir {
+            progress.file_finished(&path, entry_size, entry_size);
+        }
+        progress.bytes(input.read.get(), Some(input.total));
+        progress.output(total_extracted, None);
+    }
+
+    Ok(total_extracted)
+}
+
+/// Percorso relativo sicuro di un'entry secondo la politica scelta.
+///
+/// Ritorna `None` se l'entry va saltata (anche la radice `./` degli archivi creati con tar).
+fn checked_entry_path(path: &Path, policy: UnsafeEntryPolicy) -> Result<Option<PathBuf>> {
+    let is_safe = path
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+
+    if !is_safe {
+        reject_unsafe_entry(
+            policy,
+            &format!("Percorso non sicuro nell'archivio: {:?}", path),
+        )?;
+        if policy == UnsafeEntryPolicy::Skip {
+            return Ok(None);
+        }
+    }
+
+    // Restano solo i componenti normali: '/', prefissi e '..' vengono rimossi
+    let relative: PathBuf = path
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect();
+    Ok((!relative.as_os_str().is_empty()).then_some(relative))
+}
+
+/// Ritorna true se il target di un link simbolico, risolto dalla directory che
+/// lo conterrà sul disco, resta dentro `output_root` (canonica).
+///
+/// I link già estratti lungo il percorso vengono risolti: una catena di link
+/// innocui presi uno alla volta (`a/b -> ..`, poi `a/b/c -> ..`) non può
+/// portare fuori dalla directory di estrazione.
+fn symlink_stays_inside(dest_path: &Path, target: &Path, output_root: &Path) -> Result<bool> {
+    let parent = dest_path.parent().unwrap_or(output_root);
+    let mut resolved = canonicalize_existing_prefix(parent)?;
+    if !resolved.starts_with(output_root) {
+        return Ok(false);
+    }
+    for component in target.components() {
+        match component {
+            Component::Normal(name) => {
+                resolved.push(name);
+                if resolved
+                    .symlink_metadata()
+                    .is_ok_and(|m| m.file_type().is_symlink())
+                {
+                    // Un link che non si risolve non viene considerato sicuro
+                    match resolved.canonicalize() {
+                        Ok(canonical) => resolved = canonical,
+                        Err(_) => return Ok(false),
+                    }
+                }
+            }
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::RootDir | Component::Prefix(_) => return Ok(false),
+        }
+        if !resolved.starts_with(output_root) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Con `Reject` trasforma l'entry non sicura in errore, altrimenti non fa nulla
+fn reject_unsafe_entry(policy: UnsafeEntryPolicy, message: &str) -> Result<()> {
+    match policy {
+        UnsafeEntryPolicy::Reject => Err(Error::UnsafePath(message.to_string())),
+        UnsafeEntryPolicy::Skip | UnsafeEntryPolicy::Sanitize => Ok(()),
+    }
+}
+
+/// Tipo di un'entry in un archivio tar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveEntryType {
+    File,
+    Directory,
+    Symlink,
+    HardLink,
+    Other,
+}
+
+impl ArchiveEntryType {
+    /// Nome breve del tipo (usato nella tabella e nel JSON di `list`)
+    pub fn name(self) -> &'static str {
+        match self {
+            ArchiveEntryType::File => "file",
+            ArchiveEntryType::Directory => "dir",
+            ArchiveEntryType::Symlink => "symlink",
+            ArchiveEntryType::HardLink => "hardlink",
+            ArchiveEntryType::Other => "other",
+        }
+    }
+}
+
+impl From<tar::EntryType> for ArchiveEntryType {
+    fn from(entry_type: tar::EntryType) -> Self {
+        match entry_type {
+            tar::EntryType::Regular | tar::EntryType::Continuous => ArchiveEntryType::File,
+            tar::EntryType::Directory => ArchiveEntryType::Directory,
+            tar::EntryType::Symlink => ArchiveEntryType::Symlink,
+            tar::EntryType::Link => ArchiveEntryType::HardLink,
+            _ => ArchiveEntryType::Other,
+        }
+    }
+}
+
+/// Entry di un archivio tar compresso, letta dall'header senza estrarre il contenuto
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Permessi Unix (es. 0o644)
+    pub mode: u32,
+    /// Data di modifica in secondi dall'epoch Unix
+    pub mtime: u64,
+    pub entry_type: ArchiveEntryType,
+    /// Destinazione di symlink e hardlink
+    pub link_target: Option<PathBuf>,
+}
+
+/// Elenca il contenuto di un archivio tar compresso senza scrivere su disco.
+///
+/// Il filtro delle opzioni limita le entry restituite; ogni entry elencata
+/// genera un evento [`ProgressEvent::FileFinished`].
+pub fn list_archive(input_path: &Path, options: &DecompressOptions) -> Result<Vec<ArchiveEntry>> {
+    if !input_path.exists() {
+        return Err(Error::NotFound(format!(
+            "Il file di input {:?} non esiste",
+            input_path
+        )));
+    }
+
+    let format = detect_format(input_path, options.dictionary.as_ref())?;
+    if !format.is_tar {
+        return Err(Error::InvalidInput(format!(
+            "Il file {:?} non contiene un archivio tar",
+            input_path
+        )));
+    }
+
+    let mut entries = Vec::new();
+    let mut entries_seen = 0u64;
+    let progress = Progress::new(options.progress_handler.as_ref());
+    progress.started(None, None);
+
+    // Con l'indice vengono decompressi solo i frame con gli header elencati
+    if let Some(index) = ArchiveIndex::read(input_path)? {
+        let mut reader = indexed_reader(input_path, options)?;
+        for member in &index.entries {
+            check_cancelled(options.cancel_flag.as_ref())?;
+            entries_seen += 1;
+            options.limits.check_entries(entries_seen)?;
+            if !options.filter.matches(&member.path) {
+                continue;
+            }
+            let listed = read_indexed_member(&mut reader, member, |entry| archive_entry(entry))?;
+            progress.file_finished(&listed.path, listed.size, listed.size);
+            entries.push(listed);
+        }
+        progress.finished();
+        return Ok(entries);
+    }
+
+    let compressor = format.algorithm.compressor()?;
+    let (input_file, input_size) = open_input(input_path)?;
+    let mut reader = BufReader::with_capacity(optimal_buffer_size(input_size), input_file);
+    let decoder = compressor.decoder(&mut reader, options.dictionary.as_ref())?;
+    let mut archive = Archive::new(LimitedReader::new(
+        decoder,
+        &options.limits,
+        CompressedSize::Known(input_size),
+        false,
+    ));
+
+    for entry in archive.entries()? {
+        check_cancelled(options.cancel_flag.as_ref())?;
+        let entry = entry?;
+        entries_seen += 1;
+        options.limits.check_entries(entries_seen)?;
+        if !options.filter.matches(&entry.path()?) {
+            continue;
+        }
+
+        let listed = archive_entry(&entry)?;
+        progress.file_finished(&listed.path, listed.size, listed.size);
+        entries.push(listed);
+    }
+
+    progress.finished();
+    Ok(entries)
+}
+
+/// Dati di un'entry letti dal suo header
+fn archive_entry<R: Read>(entry: &tar::Entry<R>) -> Result<ArchiveEntry> {
+    let header = entry.header();
+    Ok(ArchiveEntry {
+        path: entry.path()?.into_owned(),
+        size: entry.size(),
+        mode: header.mode()? & 0o7777,
+        mtime: header.mtime()?,
+        entry_type: header.entry_type().into(),
+        link_target: entry.link_name()?.map(|p| p.into_owned()),
+    })
+}
+
+/// Lettore ad accesso casuale di un archivio indicizzato, con gli stessi limiti
+/// di decompressione della lettura sequenziale
+fn indexed_reader<'a>(
+    input_path: &Path,
+    options: &'a DecompressOptions,
+) -> Result<LimitedReader<'a, SeekableReader<volume::InputReader>>> {
+    let (input_file, input_size) = open_input(input_path)?;
+    let reader = SeekableReader::new(input_file, options.dictionary.as_ref())?;
+    reader.table().check_limits(&options.limits)?;
+    Ok(LimitedReader::new(
+        reader,
+        &options.limits,
+        CompressedSize::Known(input_size),
+        false,
+    ))
+}
+
+/// Legge l'entry di un membro dell'indice, decomprimendo solo dalla sua posizione
+fn read_indexed_member<R: Read + Seek, T>(
+    reader: &mut R,
+    member: &IndexEntry,
+    read: impl FnOnce(&mut tar::Entry<&mut R>) -> Result<T>,
+) -> Result<T> {
+    reader.seek(SeekFrom::Start(member.offset))?;
+    let mut archive = Archive::new(reader);
+    let mismatch = || {
+        Error::Corrupt(format!(
+            "l'indice dell'archivio non corrisponde all'entry {:?}",
+            member.path
+        ))
+    };
+    let mut entry = archive.entries()?.next().ok_or_else(mismatch)??;
+    if entry.path()? != member.path {
+        return Err(mismatch());
+    }
+    read(&mut entry)
+}
+
+/// Scrive su `writer` il contenuto di un singolo file di un archivio tar compresso.
+///
+/// La lettura si ferma appena il membro è stato trovato; ritorna i bytes scritti.
+pub fn extract_entry<W: Write + ?Sized>(
+    input_path: &Path,
+    member: &Path,
+    writer: &mut W,
+    options: &DecompressOptions,
+) -> Result<u64> {
+    if !input_path.exists() {
+        return Err(Error::NotFound(format!(
+            "Il file di input {:?} non esiste",
+            input_path
+        )));
+    }
+
+    // Con l'indice la decompressione inizia direttamente dal membro
+    if let Some(index) = ArchiveIndex::read(input_path)? {
+        let indexed = index.get(member).ok_or_else(|| missing_entry(member))?;
+        let mut reader = indexed_reader(input_path, options)?;
+        return read_indexed_member(&mut reader, indexed, |entry| {
+            copy_entry(entry, member, writer, options)
+        });
+    }
+
+    let compressor = decompression_algorithm(input_path)?.compressor()?;
+    let (input_file, input_size) = open_input(input_path)?;
+    let mut reader = BufReader::with_capacity(optimal_buffer_size(input_size), input_file);
+    let decoder = compressor.decoder(&mut reader, options.dictionary.as_ref())?;
+    let mut archive = Archive::new(LimitedReader::new(
+        decoder,
+        &options.limits,
+        CompressedSize::Known(input_size),
+        false,
+    ));
+
+    let wanted = filter::normalize(member);
+    let mut entries_seen = 0u64;
+
+    for entry in archive.entries()? {
+        check_cancelled(options.cancel_flag.as_ref())?;
+        let mut entry = entry?;
+        entries_seen += 1;
+        options.limits.check_entries(entries_seen)?;
+        if filter::normalize(&entry.path()?) != wanted {
+            continue;
+        }
+        return copy_entry(&mut entry, member, writer, options);
+    }
+
+    Err(missing_entry(member))
+}
+
+/// Copia il contenuto di un'entry, che deve essere un file regolare
+fn copy_entry<R: Read, W: Write + ?Sized>(
+    entry: &mut tar::Entry<R>,
+    member: &Path,
+    writer: &mut W,
+    options: &DecompressOptions,
+) -> Result<u64> {
+    let entry_type = ArchiveEntryType::from(entry.header().entry_type());
+    if entry_type != ArchiveEntryType::File {
+        return Err(Error::InvalidInput(format!(
+            "L'entry {:?} non è un file regolare ({})",
+            member,
+            entry_type.name()
+        )));
+    }
+
+    options.limits.check_entry_size(entry.size())?;
+    Ok(std::io::copy(entry, writer)?)
+}
+
+fn missing_entry(member: &Path) -> Error {
+    Error::NotFound(format!("L'entry {:?} non è presente nell'archivio", member))
+}
+
+/// Decomprime uno stream generico (es. stdin) scrivendo su un altro stream.
+///
+/// L'algoritmo è rilevato dal magic number (brotli, che non ne ha, non è supportato);
+/// un archivio tar viene scritto così com'è, senza estrarlo. Gli eventi `Bytes`
+/// e `Output` riportano i bytes letti e scritti, senza totale.
+pub fn decompress_stream<R: Read, W: Write>(
+    reader: R,
+    mut writer: W,
+    options: &DecompressOptions,
+) -> Result<CompressionResult> {
+    let compressed = Cell::new(0);
+    let mut reader = CountingReader {
+        inner: reader,
+        count: &compressed,
+    };
+    let (algorithm, prefix) = codec::sniff_stream(&mut reader)?;
+    let compressor = algorithm.ok_or_else(unrecognized_format)?.compressor()?;
+
+    // I bytes usati per il rilevamento vengono rimessi davanti allo stream
+    let mut input =
+        BufReader::with_capacity(BUFFER_SIZE, std::io::Cursor::new(prefix).chain(&mut reader));
+    let decoder = compressor.decoder(&mut input, options.dictionary.as_ref())?;
+    let mut decoder = LimitedReader::new(
+        decoder,
+        &options.limits,
+        CompressedSize::Counted(&compressed),
+        true,
+    );
+
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut total_written = 0u64;
+    let mut last_progress_update = 0u64;
+
+    let progress = Progress::new(options.progress_handler.as_ref());
+    progress.started(None, None);
+    loop {
+        check_cancelled(options.cancel_flag.as_ref())?;
+        let bytes_read = decoder.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        writer.write_all(&buffer[..bytes_read])?;
+        total_written += bytes_read as u64;
 
-        if let Some(ref callback) = options.progress_callback {
-            callback(file_count);
+        if total_written - last_progress_update >= 1024 * 1024 {
+            progress.bytes(compressed.get(), None);
+            progress.output(total_written, None);
+            last_progress_update = total_written;
         }
     }
 
+    drop(decoder);
+    drop(input);
+    writer.flush()?;
+
+    progress.bytes(compressed.get(), None);
+    progress.output(total_written, None);
+    progress.finished();
+
     Ok(CompressionResult {
-        input_size,
-        output_size: total_extracted,
+        input_size: compressed.get(),
+        output_size: total_written,
     })
 }
 
 /// Decomprime un file (versione semplice)
-pub fn decompress_file_simple(input_path: &Path, force: bool) -> std::io::Result<()> {
+pub fn decompress_file_simple(input_path: &Path, force: bool) -> Result<()> {
     let options = DecompressOptions::new().with_force(force);
     decompress_file(input_path, &options)?;
     Ok(())
 }
 
 /// Verifica l'integrità di un file .zst
+///
+/// Il callback riceve i bytes degli eventi [`ProgressEvent::Bytes`]; per gli
+/// altri eventi usa [`verify_zst_with_options`].
 pub fn verify_zst(
     input_path: &Path,
     progress_callback: Option<&ProgressCallback>,
-) -> std::io::Result<VerifyResult> {
+) -> Result<VerifyResult> {
+    let handler = |event: &ProgressEvent| {
+        if let (Some(callback), ProgressEvent::Bytes { done, .. }) = (progress_callback, event) {
+            callback(*done);
+        }
+    };
+    verify_zst_impl(
+        input_path,
+        None,
+        &DecompressionLimits::default(),
+        None,
+        false,
+        Progress::from_fn(&handler),
+    )
+}
+
+/// Verifica l'integrità di un file .zst usando le opzioni di decompressione
+/// (dizionario, limiti, annullamento, verifica approfondita ed eventi di progresso)
+pub fn verify_zst_with_options(
+    input_path: &Path,
+    options: &DecompressOptions,
+) -> Result<VerifyResult> {
+    verify_zst_impl(
+        input_path,
+        options.dictionary.as_ref(),
+        &options.limits,
+        options.cancel_flag.as_ref(),
+        options.deep_verify,
+        Progress::new(options.progress_handler.as_ref()),
+    )
+}
+
+fn verify_zst_impl(
+    input_path: &Path,
+    dictionary: Option<&CompressionDictionary>,
+    limits: &DecompressionLimits,
+    cancel_flag: Option<&Arc<AtomicBool>>,
+    deep: bool,
+    progress: Progress,
+) -> Result<VerifyResult> {
     if !input_path.exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("Il file {:?} non esiste", input_path),
-        ));
+        return Err(Error::NotFound(format!(
+            "Il file {:?} non esiste",
+            input_path
+        )));
     }
 
-    let extension = input_path
-        .extension()
-        .and_then(std::ffi::OsStr::to_str)
-        .unwrap_or("");
-
-    if extension != "zst" {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "Il file deve avere estensione .zst",
+    if CompressionAlgorithm::detect(input_path)? != Some(CompressionAlgorithm::Zstd) {
+        return Err(Error::UnsupportedFormat(
+            "Il file non è in formato zstd".to_string(),
         ));
     }
 
-    let input_size = std::fs::metadata(input_path)?.len();
-    let input_file = File::open(input_path)?;
-    let reader = BufReader::with_capacity(BUFFER_SIZE, input_file);
+    let (input_file, input_size) = open_input(input_path)?;
+    let frame_header = ZstdFrameHeader::read(input_path)?;
+    let content_size = frame_header.and_then(|h| h.content_size);
+    let compressed = Cell::new(0);
+    let input_file = CountingReader {
+        inner: input_file,
+        count: &compressed,
+    };
+    let mut reader = BufReader::with_capacity(BUFFER_SIZE, input_file);
 
-    let mut decoder = match zstd::Decoder::new(reader) {
-        Ok(d) => d,
+    let mut decoder = match ZstdCompressor.decoder(&mut reader, dictionary) {
+        Ok(d) => LimitedReader::new(d, limits, CompressedSize::Known(input_size), true),
         Err(e) => {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                format!("File corrotto: impossibile inizializzare il decoder: {}", e),
-            ));
+            return Err(Error::Corrupt(format!(
+                "impossibile inizializzare il decoder: {}",
+                e
+            )));
         }
     };
 
     let mut buffer = vec![0u8; BUFFER_SIZE];
     let mut total_decompressed = 0u64;
     let mut last_progress_update = 0u64;
+    let mut digest = deep.then(DigestWriter::default);
 
+    progress.started(Some(input_size), Some(1));
     loop {
+        check_cancelled(cancel_flag)?;
         match decoder.read(&mut buffer) {
             Ok(0) => break,
             Ok(n) => {
                 total_decompressed += n as u64;
+                if let Some(digest) = digest.as_mut() {
+                    digest.update(&buffer[..n]);
+                }
 
-                if let Some(callback) = progress_callback {
-                    if total_decompressed - last_progress_update >= 1024 * 1024 {
-                        let progress =
-                            (total_decompressed as f64 / 3.0).min(input_size as f64) as u64;
-                        callback(progress);
-                        last_progress_update = total_decompressed;
-                    }
+                if total_decompressed - last_progress_update >= 1024 * 1024 {
+                    progress.bytes(compressed.get(), Some(input_size));
+                    progress.output(
+                        total_decompressed,
+                        known_total(content_size, total_decompressed),
+                    );
+                    last_progress_update = total_decompressed;
                 }
             }
+            // Un limite superato non indica un file corrotto
+            Err(e) if LimitExceeded::from_io_error(&e).is_some() => return Err(e.into()),
             Err(e) => {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    format!("File corrotto: errore durante la decompressione: {}", e),
-                ));
+                return Err(Error::Corrupt(format!(
+                    "errore durante la decompressione: {}",
+                    e
+                )));
             }
         }
     }
 
     // Notifica completamento
-    if let Some(callback) = progress_callback {
-        callback(input_size);
-    }
+    progress.bytes(compressed.get(), Some(input_size));
+    progress.output(
+        total_decompressed,
+        known_total(content_size, total_decompressed),
+    );
+    let digest = digest.map(DigestWriter::finish);
+    let digest_source = match digest {
+        Some(actual) => compare_digest(input_path, actual)?,
+        None => None,
+    };
+    progress.finished();
 
     Ok(VerifyResult {
         compressed_size: input_size,
         decompressed_size: total_decompressed,
+        content_checksum: frame_header.is_some_and(|h| h.checksum),
+        digest,
+        digest_source,
     })
 }
 
+/// Confronta il digest ricalcolato con quello del manifest incorporato o del
+/// sidecar; ritorna da dove viene il digest atteso, se ce n'è uno
+fn compare_digest(input_path: &Path, actual: Sha256Digest) -> Result<Option<DigestSource>> {
+    let Some((manifest, source)) = Manifest::find(input_path)? else {
+        return Ok(None);
+    };
+
+    // Manifest di un solo file: il nome originale può differire da quello atteso
+    let original_name = volume::logical_path(input_path).with_extension("");
+    let expected = match manifest.entries.as_slice() {
+        [(_, digest)] => Some(*digest),
+        _ => original_name
+            .file_name()
+            .and_then(|name| manifest.get(Path::new(name))),
+    };
+    match expected {
+        Some(expected) if expected != actual => Err(Error::Corrupt(format!(
+            "lo SHA-256 dei dati non corrisponde al manifest ({} atteso, {} calcolato)",
+            expected, actual
+        ))),
+        Some(_) => Ok(Some(source)),
+        None => Ok(None),
+    }
+}
+
+/// Scrive su `writer` i bytes decompressi da `offset` per `length` bytes (fino
+/// alla fine se `None`) di un file zstd seekable, decomprimendo solo i frame
+/// necessari; ritorna i bytes scritti
+pub fn extract_range<W: Write + ?Sized>(
+    input_path: &Path,
+    offset: u64,
+    length: Option<u64>,
+    writer: &mut W,
+    options: &DecompressOptions,
+) -> Result<u64> {
+    let mut reader = SeekableReader::open(input_path, options.dictionary.as_ref())?;
+    reader.table().check_limits(&options.limits)?;
+    let total = reader.len();
+    if offset > total {
+        return Err(Error::InvalidInput(format!(
+            "L'offset {} è oltre la fine dei dati ({} bytes)",
+            offset, total
+        )));
+    }
+    let length = length.map_or(total - offset, |len| len.min(total - offset));
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut reader = LimitedReader::new(
+        reader.take(length),
+        &options.limits,
+        CompressedSize::Known(std::fs::metadata(input_path)?.len()),
+        true,
+    );
+
+    let progress = Progress::new(options.progress_handler.as_ref());
+    progress.started(Some(length), Some(1));
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut written = 0u64;
+    loop {
+        check_cancelled(options.cancel_flag.as_ref())?;
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        writer.write_all(&buffer[..n])?;
+        written += n as u64;
+        progress.output(written, Some(length));
+    }
+    writer.flush()?;
+    progress.finished();
+    Ok(written)
+}
+
 /// Risultato della verifica
 #[derive(Debug, Clone)]
 pub struct VerifyResult {
     pub compressed_size: u64,
     pub decompressed_size: u64,
+    /// Il frame contiene il checksum del contenuto (verificato durante la lettura)
+    pub content_checksum: bool,
+    /// SHA-256 ricalcolato dei dati decompressi (solo verifica approfondita)
+    pub digest: Option<Sha256Digest>,
+    /// Da dove viene il digest atteso con cui `digest` coincide; `None` se non
+    /// è stato trovato un manifest
+    pub digest_source: Option<DigestSource>,
 }
 
 /// Verifica l'integrità di un file .zst (versione semplice)
-pub fn verify_zst_simple(input_path: &Path) -> std::io::Result<()> {
+pub fn verify_zst_simple(input_path: &Path) -> Result<()> {
     verify_zst(input_path, None)?;
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
+/// Risultato della verifica di un archivio tar compresso
+#[derive(Debug, Clone)]
+pub struct ArchiveVerifyResult {
+    pub compressed_size: u64,
+    pub decompressed_size: u64,
+    /// Entry nell'archivio, directory comprese
+    pub entries: u64,
+    /// Bytes dei dati delle entry (header e padding esclusi)
+    pub entry_bytes: u64,
+    /// File confrontati con il manifest
+    pub files_checked: u64,
+    /// Da dove viene il manifest dei file; `None` se non è stato trovato
+    pub digest_source: Option<DigestSource>,
+    /// L'archivio ha l'indice dei membri (controllato entry per entry)
+    pub indexed: bool,
+}
 
-    fn create_temp_file(name: &str, content: &[u8]) -> PathBuf {
-        let path = std::env::temp_dir().join(name);
-        let mut file = File::create(&path).unwrap();
-        file.write_all(content).unwrap();
-        path
+/// Verifica un archivio tar compresso entry per entry.
+///
+/// Oltre allo stream compresso controlla checksum e dimensione dichiarata di ogni
+/// header tar e la presenza della fine dell'archivio. Se esiste un manifest dei
+/// file (incorporato o `.sha256`) confronta lo SHA-256 di ogni file elencato;
+/// se l'archivio è indicizzato, ogni membro deve essere alla posizione dell'indice.
+pub fn verify_archive(
+    input_path: &Path,
+    options: &DecompressOptions,
+) -> Result<ArchiveVerifyResult> {
+    if !input_path.exists() {
+        return Err(Error::NotFound(format!(
+            "Il file {:?} non esiste",
+            input_path
+        )));
     }
 
-    fn cleanup_files(paths: &[&Path]) {
-        for path in paths {
-            let _ = fs::remove_file(path);
-        }
+    let format = detect_format(input_path, options.dictionary.as_ref())?;
+    if !format.is_tar {
+        return Err(Error::InvalidInput(format!(
+            "Il file {:?} non contiene un archivio tar",
+            input_path
+        )));
     }
 
-    #[test]
-    fn test_format_size_kb() {
-        assert_eq!(format_size(512), "0.50 KB");
-        assert_eq!(format_size(1024), "1.00 KB");
-        assert_eq!(format_size(512 * 1024), "512.00 KB");
-    }
+    let manifest = Manifest::find(input_path)?;
+    let index = ArchiveIndex::read(input_path)?;
+    let compressor = format.algorithm.compressor()?;
+    let (input_file, input_size) = open_input(input_path)?;
+    let compressed = Cell::new(0);
+    let input_file = CountingReader {
+        inner: input_file,
+        count: &compressed,
+    };
+    let mut reader = BufReader::with_capacity(optimal_buffer_size(input_size), input_file);
+    let decoder = compressor.decoder(&mut reader, options.dictionary.as_ref())?;
+    let decompressed = Cell::new(0);
+    let mut archive = Archive::new(CountingReader {
+        inner: LimitedReader::new(
+            decoder,
+            &options.limits,
+            CompressedSize::Known(input_size),
+            false,
+        ),
+        count: &decompressed,
+    });
+
+    let progress = Progress::new(options.progress_handler.as_ref());
+    progress.started(Some(input_size), None);
 
-    #[test]
-    fn test_format_size_mb() {
-        assert_eq!(format_size(1_048_576), "1.00 MB");
-        assert_eq!(format_size(5 * 1_048_576), "5.00 MB");
-        assert_eq!(format_size(1_572_864), "1.50 MB");
-    }
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut entries = 0u64;
+    let mut entry_bytes = 0u64;
+    let mut files_checked = 0u64;
+    let mut checked_paths = HashSet::new();
+    // Digest attesi per percorso (vale il primo, come in Manifest::get), cercati
+    // per ogni entry anche negli archivi con moltissimi file
+    let expected_digests = manifest.as_ref().map(|(manifest, _)| {
+        let mut digests = HashMap::with_capacity(manifest.entries.len());
+        for (path, digest) in &manifest.entries {
+            digests.entry(path.as_path()).or_insert(*digest);
+        }
+        digests
+    });
+    // Fine dei dati dell'ultima entry, allineata ai blocchi da 512 bytes
+    let mut data_end = 0u64;
+
+    for entry in archive.entries().map_err(corrupt_stream)? {
+        check_cancelled(options.cancel_flag.as_ref())?;
+        let mut entry = entry.map_err(|e| corrupt_entry(entries + 1, e))?;
+        entries += 1;
+        options.limits.check_entries(entries)?;
+        let path = entry
+            .path()
+            .map_err(|e| corrupt_entry(entries, e))?
+            .into_owned();
+        // Il membro inizia dove finiscono i dati del precedente
+        if let Some(index) = &index {
+            match index.entries.get(entries as usize - 1) {
+                Some(member) if member.offset == data_end && member.path == path => {}
+                _ => {
+                    return Err(Error::Corrupt(format!(
+                        "l'indice dell'archivio non corrisponde all'entry {:?}",
+                        path
+                    )))
+                }
+            }
+        }
+        let is_dir = entry.header().entry_type().is_dir();
+        let expected_digest = match (&expected_digests, entry.header().entry_type().is_file()) {
+            (Some(digests), true) => digests.get(path.as_path()).copied(),
+            _ => None,
+        };
 
-    #[test]
-    fn test_format_size_gb() {
-        assert_eq!(format_size(1_073_741_824), "1.00 GB");
-        assert_eq!(format_size(5 * 1_073_741_824), "5.00 GB");
-    }
+        if !is_dir {
+            progress.file_started(&path);
+        }
+        let mut digest = expected_digest.map(|_| DigestWriter::default());
+        let mut read = 0u64;
+        loop {
+            let n = match entry.read(&mut buffer) {
+                Ok(n) => n,
+                Err(e) if LimitExceeded::from_io_error(&e).is_some() => return Err(e.into()),
+                Err(e) => return Err(corrupt_stream(e)),
+            };
+            if n == 0 {
+                break;
+            }
+            if let Some(digest) = digest.as_mut() {
+                digest.update(&buffer[..n]);
+            }
+            read += n as u64;
+        }
+        if read != entry.size() {
+            return Err(Error::Corrupt(format!(
+                "l'entry {:?} è troncata: {} bytes su {} dichiarati",
+                path,
+                read,
+                entry.size()
+            )));
+        }
 
-    #[test]
-    fn test_format_size_tb() {
+        if let (Some(expected), Some(digest)) = (expected_digest, digest) {
+            let actual = digest.finish();
+            if actual != expected {
+                return Err(Error::Corrupt(format!(
+                    "lo SHA-256 di {:?} non corrisponde al manifest ({} atteso, {} calcolato)",
+                    path, expected, actual
+                )));
+            }
+            files_checked += 1;
+            checked_paths.insert(path.clone());
+        }
+
+        entry_bytes += read;
+        data_end = entry.raw_file_position() + read.div_ceil(512) * 512;
+        if !is_dir {
+            progress.file_finished(&path, read, read);
+        }
+        progress.bytes(compressed.get(), Some(input_size));
+        progress.output(decompressed.get(), None);
+    }
+
+    // Dopo l'ultima entry devono esserci solo blocchi vuoti, almeno i due finali
+    let mut trailer = archive.into_inner();
+    loop {
+        let n = match trailer.read(&mut buffer) {
+            Ok(n) => n,
+            Err(e) if LimitExceeded::from_io_error(&e).is_some() => return Err(e.into()),
+            Err(e) => return Err(corrupt_stream(e)),
+        };
+        if n == 0 {
+            break;
+        }
+        if buffer[..n].iter().any(|&b| b != 0) {
+            return Err(Error::Corrupt(
+                "dati inattesi dopo la fine dell'archivio tar".to_string(),
+            ));
+        }
+    }
+    if decompressed.get() < data_end + 1024 {
+        return Err(Error::Corrupt(
+            "l'archivio tar è troncato: manca il blocco finale".to_string(),
+        ));
+    }
+
+    if let Some(index) = &index {
+        if index.entries.len() as u64 != entries {
+            return Err(Error::Corrupt(format!(
+                "l'indice elenca {} membri, l'archivio ne contiene {}",
+                index.entries.len(),
+                entries
+            )));
+        }
+    }
+
+    // Ogni file del manifest deve essere nell'archivio
+    if let Some((manifest, _)) = &manifest {
+        if let Some((missing, _)) = manifest
+            .entries
+            .iter()
+            .find(|(path, _)| !checked_paths.contains(path))
+        {
+            return Err(Error::Corrupt(format!(
+                "il file {:?} del manifest non è nell'archivio",
+                missing
+            )));
+        }
+    }
+
+    progress.bytes(compressed.get(), Some(input_size));
+    progress.output(decompressed.get(), Some(decompressed.get()));
+    progress.finished();
+
+    Ok(ArchiveVerifyResult {
+        compressed_size: input_size,
+        decompressed_size: decompressed.get(),
+        entries,
+        entry_bytes,
+        files_checked,
+        digest_source: manifest.map(|(_, source)| source),
+        indexed: index.is_some(),
+    })
+}
+
+fn corrupt_stream(e: std::io::Error) -> Error {
+    Error::Corrupt(format!("errore durante la decompressione: {}", e))
+}
+
+fn corrupt_entry(index: u64, e: std::io::Error) -> Error {
+    Error::Corrupt(format!("header dell'entry {} non valido: {}", index, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn create_temp_file(name: &str, content: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let mut file = File::create(&path).unwrap();
+        file.write_all(content).unwrap();
+        path
+    }
+
+    fn cleanup_files(paths: &[&Path]) {
+        for path in paths {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Bytes pseudo-casuali (xorshift), poco comprimibili e ripetibili
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_format_size_kb() {
+        assert_eq!(format_size(512), "0.50 KB");
+        assert_eq!(format_size(1024), "1.00 KB");
+        assert_eq!(format_size(512 * 1024), "512.00 KB");
+    }
+
+    #[test]
+    fn test_format_size_mb() {
+        assert_eq!(format_size(1_048_576), "1.00 MB");
+        assert_eq!(format_size(5 * 1_048_576), "5.00 MB");
+        assert_eq!(format_size(1_572_864), "1.50 MB");
+    }
+
+    #[test]
+    fn test_format_size_gb() {
+        assert_eq!(format_size(1_073_741_824), "1.00 GB");
+        assert_eq!(format_size(5 * 1_073_741_824), "5.00 GB");
+    }
+
+    #[test]
+    fn test_format_size_tb() {
         assert_eq!(format_size(1_099_511_627_776), "1.00 TB");
     }
 
@@ -1646,6 +3749,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn test_compress_with_progress_callback() {
         use std::sync::atomic::{AtomicU64, Ordering};
         use std::sync::Arc;
@@ -1672,6 +3776,61 @@ mod tests {
         cleanup_files(&[&input_path, &compressed_path]);
     }
 
+    #[test]
+    #[allow(deprecated)]
+    fn test_decompress_with_progress_callback_counts_entries() {
+        use std::sync::Mutex;
+
+        let test_dir = std::env::temp_dir().join("test_legacy_progress_dir");
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(test_dir.join("sub")).unwrap();
+        fs::write(test_dir.join("a.txt"), b"primo ".repeat(500)).unwrap();
+        fs::write(test_dir.join("sub").join("b.txt"), b"secondo ".repeat(500)).unwrap();
+        let archive_path = std::env::temp_dir().join("test_legacy_progress.tar.zst");
+        compress_directory(
+            &test_dir,
+            &CompressOptions::new(3)
+                .with_force(true)
+                .with_output_path(&archive_path),
+        )
+        .unwrap();
+
+        // Archivio: come prima degli eventi, il numero di file estratti
+        let values = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&values);
+        let output_dir = std::env::temp_dir().join("test_legacy_progress_out");
+        let _ = fs::remove_dir_all(&output_dir);
+        decompress_file(
+            &archive_path,
+            &DecompressOptions::new()
+                .with_output_path(&output_dir)
+                .with_progress(move |n| seen.lock().unwrap().push(n)),
+        )
+        .unwrap();
+        assert_eq!(*values.lock().unwrap(), vec![1, 2]);
+
+        // File singolo: i bytes compressi letti
+        let content = b"file singolo ".repeat(1000);
+        let input_path = create_temp_file("test_legacy_progress.txt", &content);
+        compress_file(&input_path, &CompressOptions::new(3).with_force(true)).unwrap();
+        let compressed_path = build_output_path(&input_path);
+        let compressed_size = fs::metadata(&compressed_path).unwrap().len();
+        let values = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&values);
+        decompress_file(
+            &compressed_path,
+            &DecompressOptions::new()
+                .with_force(true)
+                .with_progress(move |n| seen.lock().unwrap().push(n)),
+        )
+        .unwrap();
+        assert_eq!(values.lock().unwrap().last(), Some(&compressed_size));
+
+        let _ = fs::remove_dir_all(&test_dir);
+        let _ = fs::remove_dir_all(&output_dir);
+        cleanup_files(&[&archive_path, &input_path, &compressed_path]);
+    }
+
     #[test]
     fn test_detect_file_type() {
         use std::path::PathBuf;
@@ -1815,4 +3974,2236 @@ mod tests {
             "Smart optimize dovrebbe essere disabilitabile"
         );
     }
+
+    fn dictionary_samples() -> Vec<Vec<u8>> {
+        (0..200)
+            .map(|i| {
+                format!(
+                    "{{\"id\": {}, \"user\": \"utente_{}\", \"status\": \"active\", \"role\": \"admin\", \"tags\": [\"alpha\", \"beta\"], \"score\": {}}}\n",
+                    i,
+                    i % 17,
+                    i * 7 % 100
+                )
+                .into_bytes()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_dictionary_train_save_load() {
+        let samples = dictionary_samples();
+        let dict = CompressionDictionary::train_from_samples(&samples, 1024).unwrap();
+        assert!(!dict.is_empty());
+        assert!(dict.len() <= 1024);
+        assert!(dict.id().is_some());
+
+        let dict_path = std::env::temp_dir().join("test_dict_save_load.dict");
+        dict.save(&dict_path).unwrap();
+        let loaded = CompressionDictionary::load(&dict_path).unwrap();
+        assert_eq!(dict, loaded);
+
+        let _ = fs::remove_file(&dict_path);
+    }
+
+    #[test]
+    fn test_dictionary_train_no_samples() {
+        let samples: Vec<Vec<u8>> = Vec::new();
+        let result = CompressionDictionary::train_from_samples(&samples, 1024);
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_compress_decompress_with_dictionary() {
+        let samples = dictionary_samples();
+        let dict = CompressionDictionary::train_from_samples(&samples, 1024).unwrap();
+
+        let original_content = samples[42].clone();
+        let input_path = create_temp_file("test_dict_roundtrip.json", &original_content);
+        let compressed_path = input_path.with_extension("json.zst");
+
+        let options = CompressOptions::new(3)
+            .with_force(true)
+            .with_dictionary(dict.clone());
+        compress_file(&input_path, &options).unwrap();
+
+        // Senza dizionario la verifica deve fallire
+        assert!(verify_zst(&compressed_path, None).is_err());
+
+        let verify_options = DecompressOptions::new().with_dictionary(dict.clone());
+        let verified = verify_zst_with_options(&compressed_path, &verify_options).unwrap();
+        assert_eq!(verified.decompressed_size, original_content.len() as u64);
+
+        fs::remove_file(&input_path).unwrap();
+        let decompress_options = DecompressOptions::new()
+            .with_force(true)
+            .with_dictionary(dict);
+        decompress_single_file(&compressed_path, &decompress_options).unwrap();
+        assert_eq!(fs::read(&input_path).unwrap(), original_content);
+
+        cleanup_files(&[&input_path, &compressed_path]);
+    }
+
+    #[test]
+    fn test_parse_strategy() {
+        assert_eq!(parse_strategy("fast").unwrap(), CompressionStrategy::Fast);
+        assert_eq!(
+            parse_strategy("GREEDY").unwrap(),
+            CompressionStrategy::Greedy
+        );
+        assert_eq!(
+            parse_strategy("btlazy2").unwrap(),
+            CompressionStrategy::BtLazy2
+        );
+        assert!(parse_strategy("ultra").is_err());
+    }
+
+    #[test]
+    fn test_encoder_parameters_differ_by_file_type() {
+        use zstd::zstd_safe::{CParameter, Strategy};
+
+        let options = CompressOptions::new(3).with_auto_parallel(false);
+        let text_params = file_encoder_parameters(&options, FileType::Text, 1024);
+        let binary_params = file_encoder_parameters(&options, FileType::Binary, 1024);
+
+        assert!(text_params.contains(&CParameter::Strategy(Strategy::ZSTD_greedy)));
+        assert!(binary_params.contains(&CParameter::Strategy(Strategy::ZSTD_lazy)));
+        assert_ne!(text_params, binary_params);
+
+        // Tipo sconosciuto: zstd sceglie la strategia in base al livello
+        let unknown_params = file_encoder_parameters(&options, FileType::Unknown, 1024);
+        assert!(!unknown_params
+            .iter()
+            .any(|p| matches!(p, CParameter::Strategy(_))));
+
+        // Al livello 19 zstd usa btultra2: la strategia per tipo sarebbe più debole
+        let options = CompressOptions::new(19).with_auto_parallel(false);
+        for file_type in [FileType::Text, FileType::Binary, FileType::Database] {
+            assert_eq!(options.effective_strategy(file_type, 300 * 1024), None);
+        }
+    }
+
+    #[test]
+    fn test_smart_optimize_never_worse_at_high_level() {
+        let log: Vec<u8> = (0..4000u32)
+            .flat_map(|i| {
+                format!(
+                    "2024-05-{:02} 12:{:02}:{:02} INFO richiesta {} servita in {} ms\n",
+                    i % 28 + 1,
+                    i % 60,
+                    i * 7 % 60,
+                    i * 31 % 997,
+                    i % 250
+                )
+                .into_bytes()
+            })
+            .collect();
+        let db: Vec<u8> = (0..3000u64)
+            .flat_map(|i| {
+                let mut record = (i * 2_654_435_761 % 100_000).to_le_bytes().to_vec();
+                record.extend(noise(8, i + 1).into_iter().map(|b| b % 4));
+                record.extend(format!("utente{:05}", i % 700).into_bytes());
+                record
+            })
+            .collect();
+
+        for (name, content) in [("test_smart_high.log", log), ("test_smart_high.db", db)] {
+            let input_path = create_temp_file(name, &content);
+            let compressed_path = build_output_path(&input_path);
+            let mut sizes = Vec::new();
+            for smart in [true, false] {
+                let options = CompressOptions::new(19)
+                    .with_force(true)
+                    .with_auto_parallel(false)
+                    .with_smart_optimize(smart);
+                sizes.push(compress_file(&input_path, &options).unwrap().output_size);
+            }
+            assert!(sizes[0] <= sizes[1], "{}: {:?}", name, sizes);
+            cleanup_files(&[&input_path, &compressed_path]);
+        }
+    }
+
+    #[test]
+    fn test_encoder_parameters_explicit_strategy() {
+        use zstd::zstd_safe::{CParameter, Strategy};
+
+        // La strategia esplicita vince su smart_optimize e vale anche se disabilitato
+        let options = CompressOptions::new(3)
+            .with_auto_parallel(false)
+            .with_smart_optimize(false)
+            .with_strategy(CompressionStrategy::Fast);
+        let params = file_encoder_parameters(&options, FileType::Text, 1024);
+        assert_eq!(
+            params,
+            vec![
+                CParameter::ChecksumFlag(true),
+                CParameter::Strategy(Strategy::ZSTD_fast)
+            ]
+        );
+
+        // Senza smart_optimize e senza override nessuna strategia viene forzata
+        let options = CompressOptions::new(3)
+            .with_auto_parallel(false)
+            .with_smart_optimize(false)
+            .with_content_checksum(false);
+        assert_eq!(
+            file_encoder_parameters(&options, FileType::Text, 1024),
+            vec![CParameter::ChecksumFlag(false)]
+        );
+    }
+
+    #[test]
+    fn test_compress_with_explicit_strategy_roundtrip() {
+        let original_content = b"Strategy roundtrip content, strategy roundtrip.\n".repeat(200);
+        let input_path = create_temp_file("test_strategy_roundtrip.txt", &original_content);
+        let compressed_path = input_path.with_extension("txt.zst");
+
+        let options = CompressOptions::new(5)
+            .with_force(true)
+            .with_strategy(CompressionStrategy::BtLazy2);
+        compress_file(&input_path, &options).unwrap();
+
+        let verified = verify_zst(&compressed_path, None).unwrap();
+        assert_eq!(verified.decompressed_size, original_content.len() as u64);
+
+        cleanup_files(&[&input_path, &compressed_path]);
+    }
+
+    #[test]
+    fn test_build_output_path_for_algorithm() {
+        let path = Path::new("/some/file.txt");
+        assert_eq!(
+            build_output_path_for(path, CompressionAlgorithm::Gzip),
+            PathBuf::from("/some/file.txt.gz")
+        );
+        assert_eq!(
+            build_output_path_for(Path::new("/some/file"), CompressionAlgorithm::Lz4),
+            PathBuf::from("/some/file.lz4")
+        );
+    }
+
+    #[test]
+    fn test_is_tar_archive_path() {
+        assert!(is_tar_archive_path(Path::new("backup.tar.zst")));
+        assert!(is_tar_archive_path(Path::new("backup.tar.gz")));
+        assert!(is_tar_archive_path(Path::new("backup.TGZ")));
+        assert!(is_tar_archive_path(Path::new("backup.tar.lz4")));
+        assert!(!is_tar_archive_path(Path::new("file.txt.zst")));
+        assert!(!is_tar_archive_path(Path::new("file.gz")));
+    }
+
+    #[test]
+    fn test_algorithm_from_path_and_parse() {
+        assert_eq!(
+            CompressionAlgorithm::from_path(Path::new("a.zst")),
+            Some(CompressionAlgorithm::Zstd)
+        );
+        assert_eq!(
+            CompressionAlgorithm::from_path(Path::new("a.tgz")),
+            Some(CompressionAlgorithm::Gzip)
+        );
+        assert_eq!(CompressionAlgorithm::from_path(Path::new("a.txt")), None);
+
+        assert_eq!(parse_algorithm("ZSTD").unwrap(), CompressionAlgorithm::Zstd);
+        assert!(parse_algorithm("bzip2").is_err());
+        for algorithm in CompressionAlgorithm::ALL {
+            assert_eq!(
+                parse_algorithm(algorithm.name()).is_ok(),
+                algorithm.is_available()
+            );
+        }
+    }
+
+    #[test]
+    fn test_compress_decompress_roundtrip_all_algorithms() {
+        let original_content = b"Contenuto di prova per tutti gli algoritmi.\n".repeat(500);
+
+        for algorithm in CompressionAlgorithm::ALL {
+            if !algorithm.is_available() {
+                continue;
+            }
+
+            let input_path = create_temp_file(
+                &format!("test_algo_roundtrip_{}.txt", algorithm.name()),
+                &original_content,
+            );
+            let compressed_path = build_output_path_for(&input_path, algorithm);
+
+            let options = CompressOptions::new(19)
+                .with_force(true)
+                .with_algorithm(algorithm);
+            let result = compress_file(&input_path, &options).unwrap();
+            assert!(compressed_path.exists(), "{} output mancante", algorithm);
+            assert!(result.output_size < result.input_size);
+
+            fs::remove_file(&input_path).unwrap();
+            decompress_file_simple(&compressed_path, true).unwrap();
+            assert_eq!(
+                fs::read(&input_path).unwrap(),
+                original_content,
+                "roundtrip {} fallito",
+                algorithm
+            );
+
+            cleanup_files(&[&input_path, &compressed_path]);
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_compress_directory_tar_gz_readable_by_flate2() {
+        let test_dir = std::env::temp_dir().join("test_tar_gz_dir");
+        let _ = fs::create_dir_all(&test_dir);
+        fs::write(test_dir.join("legacy.txt"), b"legacy consumer content").unwrap();
+
+        let options = CompressOptions::new(6)
+            .with_force(true)
+            .with_algorithm(CompressionAlgorithm::Gzip);
+        compress_directory(&test_dir, &options).unwrap();
+
+        let archive_path = std::env::temp_dir().join("test_tar_gz_dir.tar.gz");
+        assert!(archive_path.exists());
+
+        // Un lettore gzip+tar standard deve poter leggere l'archivio
+        let file = File::open(&archive_path).unwrap();
+        let mut archive = Archive::new(flate2::read::GzDecoder::new(file));
+        let names: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, vec!["legacy.txt".to_string()]);
+
+        // E decompress_file deve riconoscere .tar.gz come archivio
+        let output_dir = std::env::temp_dir().join("test_tar_gz_dir_out");
+        let decompress_options = DecompressOptions::new()
+            .with_force(true)
+            .with_output_path(&output_dir);
+        decompress_file(&archive_path, &decompress_options).unwrap();
+        assert_eq!(
+            fs::read(output_dir.join("legacy.txt")).unwrap(),
+            b"legacy consumer content"
+        );
+
+        let _ = fs::remove_dir_all(&test_dir);
+        let _ = fs::remove_dir_all(&output_dir);
+        let _ = fs::remove_file(&archive_path);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_dictionary_rejected_for_non_zstd() {
+        let dict = CompressionDictionary::train_from_samples(&dictionary_samples(), 1024).unwrap();
+        let input_path = create_temp_file("test_dict_gzip.json", b"{\"id\": 1}");
+
+        let options = CompressOptions::new(3)
+            .with_force(true)
+            .with_algorithm(CompressionAlgorithm::Gzip)
+            .with_dictionary(dict);
+        let result = compress_file(&input_path, &options);
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+
+        let compressed_path = input_path.with_extension("json.gz");
+        cleanup_files(&[&input_path, &compressed_path]);
+    }
+
+    #[test]
+    fn test_sniff_magic_and_skippable_frames() {
+        let compressed = zstd::encode_all(&b"payload"[..], 3).unwrap();
+        assert_eq!(
+            CompressionAlgorithm::from_magic(&compressed),
+            Some(CompressionAlgorithm::Zstd)
+        );
+        assert_eq!(
+            CompressionAlgorithm::from_magic(&[0x1F, 0x8B, 0x08]),
+            Some(CompressionAlgorithm::Gzip)
+        );
+        assert_eq!(CompressionAlgorithm::from_magic(b"plain text"), None);
+
+        // Frame skippable seguito da un frame zstd
+        let mut stream = codec::SKIPPABLE_MAGIC_START.to_le_bytes().to_vec();
+        stream.extend_from_slice(&3u32.to_le_bytes());
+        stream.extend_from_slice(b"abc");
+        let skippable_only = stream.clone();
+        stream.extend_from_slice(&compressed);
+        assert_eq!(
+            CompressionAlgorithm::sniff(&mut &stream[..]).unwrap(),
+            Some(CompressionAlgorithm::Zstd)
+        );
+        assert_eq!(zstd::decode_all(&stream[..]).unwrap(), b"payload");
+
+        assert_eq!(
+            CompressionAlgorithm::sniff(&mut &skippable_only[..]).unwrap(),
+            Some(CompressionAlgorithm::Zstd)
+        );
+        // Frame skippable troncato
+        assert_eq!(
+            CompressionAlgorithm::sniff(&mut &skippable_only[..10]).unwrap(),
+            None
+        );
+        assert_eq!(CompressionAlgorithm::sniff(&mut &b""[..]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_decompress_and_verify_renamed_zst() {
+        let original_content = b"Renamed zstd file content.\n".repeat(100);
+        let input_path = create_temp_file("test_renamed_src.txt", &original_content);
+        compress_file_simple(&input_path, 3, true).unwrap();
+
+        let compressed_path = input_path.with_extension("txt.zst");
+        let renamed_path = std::env::temp_dir().join("test_renamed_data.bin");
+        fs::rename(&compressed_path, &renamed_path).unwrap();
+
+        let format = detect_format(&renamed_path, None).unwrap();
+        assert_eq!(format.algorithm, CompressionAlgorithm::Zstd);
+        assert!(!format.is_tar);
+
+        let verify = verify_zst(&renamed_path, None).unwrap();
+        assert_eq!(verify.decompressed_size, original_content.len() as u64);
+
+        // Senza un'estensione nota l'output non deve sovrascrivere l'input
+        decompress_file_simple(&renamed_path, true).unwrap();
+        let output_path = std::env::temp_dir().join("test_renamed_data.bin.out");
+        assert_eq!(fs::read(&output_path).unwrap(), original_content);
+        assert!(renamed_path.exists());
+
+        cleanup_files(&[&input_path, &renamed_path, &output_path]);
+    }
+
+    #[test]
+    fn test_decompress_tzst_archive() {
+        let test_dir = std::env::temp_dir().join("test_tzst_dir");
+        let _ = fs::create_dir_all(&test_dir);
+        fs::write(test_dir.join("a.txt"), b"tzst archive content").unwrap();
+
+        let options = CompressOptions::new(3).with_force(true);
+        compress_directory(&test_dir, &options).unwrap();
+
+        let archive_path = std::env::temp_dir().join("test_tzst_dir.tar.zst");
+        let tzst_path = std::env::temp_dir().join("test_tzst_archive.tzst");
+        fs::rename(&archive_path, &tzst_path).unwrap();
+
+        let format = detect_format(&tzst_path, None).unwrap();
+        assert_eq!(format.algorithm, CompressionAlgorithm::Zstd);
+        assert!(format.is_tar);
+
+        let output_dir = std::env::temp_dir().join("test_tzst_out");
+        let decompress_options = DecompressOptions::new()
+            .with_force(true)
+            .with_output_path(&output_dir);
+        decompress_file(&tzst_path, &decompress_options).unwrap();
+        assert_eq!(
+            fs::read(output_dir.join("a.txt")).unwrap(),
+            b"tzst archive content"
+        );
+
+        let _ = fs::remove_dir_all(&test_dir);
+        let _ = fs::remove_dir_all(&output_dir);
+        let _ = fs::remove_file(&tzst_path);
+    }
+
+    #[test]
+    fn test_list_archive() {
+        let test_dir = std::env::temp_dir().join("test_list_dir");
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(test_dir.join("sub")).unwrap();
+        fs::write(test_dir.join("a.txt"), b"12345").unwrap();
+        fs::write(test_dir.join("sub").join("b.txt"), b"1234567890").unwrap();
+
+        let options = CompressOptions::new(3).with_force(true);
+        compress_directory(&test_dir, &options).unwrap();
+        let archive_path = std::env::temp_dir().join("test_list_dir.tar.zst");
+
+        let mut entries = list_archive(&archive_path, &DecompressOptions::new()).unwrap();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        let files: Vec<(PathBuf, u64)> = entries
+            .iter()
+            .filter(|e| e.entry_type == ArchiveEntryType::File)
+            .map(|e| (e.path.clone(), e.size))
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                (PathBuf::from("a.txt"), 5),
+                (PathBuf::from("sub/b.txt"), 10)
+            ]
+        );
+        assert!(entries.iter().all(|e| e.mtime > 0 && e.mode != 0));
+
+        // Un file compresso singolo non è un archivio
+        let single = create_temp_file("test_list_single.txt", b"not an archive");
+        compress_file_simple(&single, 3, true).unwrap();
+        let single_zst = single.with_extension("txt.zst");
+        let result = list_archive(&single_zst, &DecompressOptions::new());
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+
+        let _ = fs::remove_dir_all(&test_dir);
+        cleanup_files(&[&archive_path, &single, &single_zst]);
+    }
+
+    #[test]
+    fn test_path_filter_patterns() {
+        let filter = PathFilter::from_patterns(&["etc/**"], &[]).unwrap();
+        assert!(filter.matches(Path::new("etc/app.toml")));
+        assert!(filter.matches(Path::new("./etc/nested/deep.conf")));
+        assert!(!filter.matches(Path::new("var/log/syslog")));
+        assert!(!filter.matches(Path::new("etcetera/file")));
+
+        // Pattern senza '/' confrontati con ogni componente, directory incluse
+        let filter = PathFilter::from_patterns(&[] as &[&str], &["*.log", "node_modules"]).unwrap();
+        assert!(!filter.matches(Path::new("logs/app.log")));
+        assert!(!filter.matches(Path::new("web/node_modules/x/index.js")));
+        assert!(filter.matches(Path::new("src/main.rs")));
+
+        // Una directory inclusa porta con sé il suo contenuto; l'esclusione vince
+        let filter = PathFilter::from_patterns(&["config"], &["config/secret.toml"]).unwrap();
+        assert!(filter.matches(Path::new("config/app.toml")));
+        assert!(!filter.matches(Path::new("config/secret.toml")));
+
+        assert!(PathFilter::new().matches(Path::new("anything")));
+        assert!(PathFilter::from_patterns(&["[unclosed"], &[]).is_err());
+    }
+
+    #[test]
+    fn test_decompress_tar_with_include_filter() {
+        let test_dir = std::env::temp_dir().join("test_filter_dir");
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(test_dir.join("etc")).unwrap();
+        fs::create_dir_all(test_dir.join("data")).unwrap();
+        fs::write(test_dir.join("etc").join("app.toml"), b"key = 1").unwrap();
+        fs::write(test_dir.join("data").join("big.bin"), vec![7u8; 10_000]).unwrap();
+
+        let options = CompressOptions::new(3).with_force(true);
+        compress_directory(&test_dir, &options).unwrap();
+        let archive_path = std::env::temp_dir().join("test_filter_dir.tar.zst");
+
+        let output_dir = std::env::temp_dir().join("test_filter_out");
+        let _ = fs::remove_dir_all(&output_dir);
+        let filter = PathFilter::from_patterns(&["etc/**"], &[]).unwrap();
+        let decompress_options = DecompressOptions::new()
+            .with_output_path(&output_dir)
+            .with_filter(filter);
+        let result = decompress_tar_zst(&archive_path, &decompress_options).unwrap();
+
+        assert_eq!(result.output_size, 7);
+        assert_eq!(
+            fs::read(output_dir.join("etc").join("app.toml")).unwrap(),
+            b"key = 1"
+        );
+        assert!(!output_dir.join("data").exists());
+
+        let _ = fs::remove_dir_all(&test_dir);
+        let _ = fs::remove_dir_all(&output_dir);
+        let _ = fs::remove_file(&archive_path);
+    }
+
+    #[test]
+    fn test_extract_entry_to_writer() {
+        let test_dir = std::env::temp_dir().join("test_cat_dir");
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(test_dir.join("config")).unwrap();
+        fs::write(test_dir.join("config").join("app.toml"), b"port = 8080\n").unwrap();
+        fs::write(test_dir.join("other.txt"), b"other").unwrap();
+
+        let options = CompressOptions::new(3).with_force(true);
+        compress_directory(&test_dir, &options).unwrap();
+        let archive_path = std::env::temp_dir().join("test_cat_dir.tar.zst");
+
+        let mut output = Vec::new();
+        let written = extract_entry(
+            &archive_path,
+            Path::new("./config/app.toml"),
+            &mut output,
+            &DecompressOptions::new(),
+        )
+        .unwrap();
+        assert_eq!(written, 12);
+        assert_eq!(output, b"port = 8080\n");
+
+        let missing = extract_entry(
+            &archive_path,
+            Path::new("config/missing.toml"),
+            &mut Vec::new(),
+            &DecompressOptions::new(),
+        );
+        assert_eq!(missing.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+
+        let _ = fs::remove_dir_all(&test_dir);
+        let _ = fs::remove_file(&archive_path);
+    }
+
+    #[test]
+    fn test_compress_decompress_stream_roundtrip() {
+        let original_content = b"Streaming da stdin a stdout.\n".repeat(1000);
+
+        for algorithm in CompressionAlgorithm::ALL {
+            // Brotli non ha magic number: non rilevabile da uno stream
+            if !algorithm.is_available() || algorithm == CompressionAlgorithm::Brotli {
+                continue;
+            }
+
+            let options = CompressOptions::new(3).with_algorithm(algorithm);
+            let mut compressed = Vec::new();
+            let result = compress_stream(&original_content[..], &mut compressed, &options).unwrap();
+            assert_eq!(result.input_size, original_content.len() as u64);
+            assert_eq!(result.output_size, compressed.len() as u64);
+            assert!(compressed.len() < original_content.len());
+
+            let mut decompressed = Vec::new();
+            let result = decompress_stream(
+                &compressed[..],
+                &mut decompressed,
+                &DecompressOptions::new(),
+            )
+            .unwrap();
+            assert_eq!(
+                decompressed, original_content,
+                "stream {} fallito",
+                algorithm
+            );
+            assert_eq!(result.input_size, compressed.len() as u64);
+        }
+
+        let result = decompress_stream(&b"plain text"[..], Vec::new(), &DecompressOptions::new());
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_compress_directory_with_filters_and_gitignore() {
+        let test_dir = std::env::temp_dir().join("test_ignore_dir");
+        let _ = fs::remove_dir_all(&test_dir);
+        for sub in ["src", "target", "node_modules", ".git"] {
+            fs::create_dir_all(test_dir.join(sub)).unwrap();
+        }
+        fs::write(test_dir.join(".gitignore"), b"target/\n*.tmp\n").unwrap();
+        fs::write(test_dir.join("src").join("main.rs"), b"fn main() {}").unwrap();
+        fs::write(test_dir.join("scratch.tmp"), b"temp").unwrap();
+        fs::write(test_dir.join("target").join("app.bin"), vec![1u8; 4096]).unwrap();
+        fs::write(test_dir.join("node_modules").join("x.js"), b"x").unwrap();
+        fs::write(test_dir.join(".git").join("HEAD"), b"ref").unwrap();
+
+        let filter = PathFilter::from_patterns(&[] as &[&str], &["node_modules"]).unwrap();
+        let options = CompressOptions::new(3)
+            .with_force(true)
+            .with_filter(filter)
+            .with_ignore_files(true);
+
+        // I totali per il progresso devono coincidere con il contenuto dell'archivio
+        assert_eq!(
+            count_files_in_dir_with_options(&test_dir, &options).unwrap(),
+            2
+        );
+        let expected_size = calculate_dir_size_with_options(&test_dir, &options).unwrap();
+        assert_eq!(expected_size, 14 + 12);
+        assert_eq!(count_files_in_dir(&test_dir).unwrap(), 6);
+
+        let result = compress_directory(&test_dir, &options).unwrap();
+        assert_eq!(result.input_size, expected_size);
+
+        let archive_path = std::env::temp_dir().join("test_ignore_dir.tar.zst");
+        let names: Vec<PathBuf> = list_archive(&archive_path, &DecompressOptions::new())
+            .unwrap()
+            .into_iter()
+            .map(|e| e.path)
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                PathBuf::from(".gitignore"),
+                PathBuf::from("src"),
+                PathBuf::from("src/main.rs")
+            ]
+        );
+
+        let _ = fs::remove_dir_all(&test_dir);
+        let _ = fs::remove_file(&archive_path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_compress_directory_stores_symlinks_and_empty_dirs() {
+        use std::os::unix::fs::symlink;
+
+        let test_dir = std::env::temp_dir().join("test_symlink_dir");
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(test_dir.join("empty")).unwrap();
+        fs::create_dir_all(test_dir.join("sub")).unwrap();
+        fs::write(test_dir.join("a.txt"), b"contenuto").unwrap();
+        symlink("a.txt", test_dir.join("link.txt")).unwrap();
+        // Link a un antenato: seguendolo si ricorrerebbe all'infinito
+        symlink("..", test_dir.join("sub").join("loop")).unwrap();
+
+        let options = CompressOptions::new(3).with_force(true);
+        assert_eq!(
+            count_files_in_dir_with_options(&test_dir, &options).unwrap(),
+            3
+        );
+        compress_directory(&test_dir, &options).unwrap();
+        let archive_path = std::env::temp_dir().join("test_symlink_dir.tar.zst");
+
+        let entries = list_archive(&archive_path, &DecompressOptions::new()).unwrap();
+        let find = |name: &str| entries.iter().find(|e| e.path == Path::new(name)).unwrap();
+        assert_eq!(find("empty").entry_type, ArchiveEntryType::Directory);
+        assert_eq!(find("link.txt").entry_type, ArchiveEntryType::Symlink);
+        assert_eq!(
+            find("link.txt").link_target.as_deref(),
+            Some(Path::new("a.txt"))
+        );
+        assert_eq!(find("sub/loop").entry_type, ArchiveEntryType::Symlink);
+
+        let output_dir = std::env::temp_dir().join("test_symlink_out");
+        let _ = fs::remove_dir_all(&output_dir);
+        decompress_tar_zst(
+            &archive_path,
+            &DecompressOptions::new().with_output_path(&output_dir),
+        )
+        .unwrap();
+        assert!(output_dir.join("empty").is_dir());
+        assert_eq!(
+            fs::read_link(output_dir.join("link.txt")).unwrap(),
+            Path::new("a.txt")
+        );
+
+        let _ = fs::remove_dir_all(&test_dir);
+        let _ = fs::remove_dir_all(&output_dir);
+        let _ = fs::remove_file(&archive_path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_compress_directory_follow_symlinks_detects_cycles() {
+        use std::os::unix::fs::symlink;
+
+        let test_dir = std::env::temp_dir().join("test_follow_dir");
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(test_dir.join("sub")).unwrap();
+        fs::write(test_dir.join("a.txt"), b"contenuto").unwrap();
+        symlink("a.txt", test_dir.join("link.txt")).unwrap();
+        symlink("..", test_dir.join("sub").join("loop")).unwrap();
+        symlink("missing.txt", test_dir.join("broken")).unwrap();
+
+        let options = CompressOptions::new(3)
+            .with_force(true)
+            .with_follow_symlinks(true);
+        compress_directory(&test_dir, &options).unwrap();
+        let archive_path = std::env::temp_dir().join("test_follow_dir.tar.zst");
+
+        let entries = list_archive(&archive_path, &DecompressOptions::new()).unwrap();
+        let find = |name: &str| entries.iter().find(|e| e.path == Path::new(name)).unwrap();
+        // Il link a file viene risolto, ciclo e link rotto restano link
+        assert_eq!(find("link.txt").entry_type, ArchiveEntryType::File);
+        assert_eq!(find("link.txt").size, 9);
+        assert_eq!(find("sub/loop").entry_type, ArchiveEntryType::Symlink);
+        assert_eq!(find("broken").entry_type, ArchiveEntryType::Symlink);
+
+        let _ = fs::remove_dir_all(&test_dir);
+        let _ = fs::remove_file(&archive_path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_decompress_restore_metadata() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let test_dir = std::env::temp_dir().join("test_metadata_dir");
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(&test_dir).unwrap();
+        let script = test_dir.join("run.sh");
+        fs::write(&script, b"#!/bin/sh\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o750)).unwrap();
+        let mtime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+        File::options()
+            .write(true)
+            .open(&script)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+
+        let options = CompressOptions::new(3).with_force(true);
+        compress_directory(&test_dir, &options).unwrap();
+        let archive_path = std::env::temp_dir().join("test_metadata_dir.tar.zst");
+
+        let output_dir = std::env::temp_dir().join("test_metadata_out");
+        let _ = fs::remove_dir_all(&output_dir);
+        decompress_tar_zst(
+            &archive_path,
+            &DecompressOptions::new().with_output_path(&output_dir),
+        )
+        .unwrap();
+        let restored = fs::metadata(output_dir.join("run.sh")).unwrap();
+        assert_eq!(restored.permissions().mode() & 0o777, 0o750);
+        assert_eq!(restored.modified().unwrap(), mtime);
+
+        let _ = fs::remove_dir_all(&output_dir);
+        decompress_tar_zst(
+            &archive_path,
+            &DecompressOptions::new()
+                .with_output_path(&output_dir)
+                .with_restore_metadata(MetadataRestore::None),
+        )
+        .unwrap();
+        let plain = fs::metadata(output_dir.join("run.sh")).unwrap();
+        assert_ne!(plain.modified().unwrap(), mtime);
+        assert_eq!(plain.permissions().mode() & 0o111, 0);
+
+        assert_eq!(parse_metadata_restore("ALL"), Ok(MetadataRestore::All));
+        assert!(parse_metadata_restore("some").is_err());
+
+        let _ = fs::remove_dir_all(&test_dir);
+        let _ = fs::remove_dir_all(&output_dir);
+        let _ = fs::remove_file(&archive_path);
+    }
+
+    /// Scrive un tar.zst con header grezzi, senza i controlli di `tar::Builder`
+    fn write_raw_tar_zst(path: &Path, entries: &[(&str, tar::EntryType, &str, &[u8])]) {
+        let encoder = zstd::Encoder::new(File::create(path).unwrap(), 3).unwrap();
+        let mut builder = tar::Builder::new(encoder);
+        for (name, entry_type, link, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.as_old_mut().linkname[..link.len()].copy_from_slice(link.as_bytes());
+            header.set_entry_type(*entry_type);
+            header.set_mode(0o644);
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            builder.append(&header, *data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn test_decompress_unsafe_paths_policy() {
+        let base = std::env::temp_dir().join("test_tarslip_paths");
+        let _ = fs::remove_dir_all(&base);
+        fs::create_dir_all(&base).unwrap();
+        let archive_path = base.join("evil.tar.zst");
+        let output_dir = base.join("out");
+        let absolute = base.join("abs_escape.txt");
+        let absolute_name = absolute.to_string_lossy().into_owned();
+
+        write_raw_tar_zst(
+            &archive_path,
+            &[
+                ("good.txt", tar::EntryType::Regular, "", b"good"),
+                ("../dotdot_escape.txt", tar::EntryType::Regular, "", b"evil"),
+                (&absolute_name, tar::EntryType::Regular, "", b"evil"),
+            ],
+        );
+
+        // Reject (default): errore e nessun file fuori dalla destinazione
+        let options = DecompressOptions::new().with_output_path(&output_dir);
+        let err = decompress_tar_zst(&archive_path, &options).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(!base.join("dotdot_escape.txt").exists());
+        assert!(!absolute.exists());
+
+        // Skip: estratte solo le entry sicure
+        let options = DecompressOptions::new()
+            .with_output_path(&output_dir)
+            .with_force(true)
+            .with_unsafe_entries(UnsafeEntryPolicy::Skip);
+        decompress_tar_zst(&archive_path, &options).unwrap();
+        assert_eq!(fs::read(output_dir.join("good.txt")).unwrap(), b"good");
+        assert!(!base.join("dotdot_escape.txt").exists());
+        assert!(!absolute.exists());
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 1);
+
+        // Sanitize: i percorsi vengono riportati dentro la destinazione
+        let options = DecompressOptions::new()
+            .with_output_path(&output_dir)
+            .with_force(true)
+            .with_unsafe_entries(UnsafeEntryPolicy::Sanitize);
+        decompress_tar_zst(&archive_path, &options).unwrap();
+        assert_eq!(
+            fs::read(output_dir.join("dotdot_escape.txt")).unwrap(),
+            b"evil"
+        );
+        let relative: PathBuf = absolute
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect();
+        assert!(output_dir.join(relative).exists());
+        assert!(!base.join("dotdot_escape.txt").exists());
+        assert!(!absolute.exists());
+
+        assert_eq!(
+            parse_unsafe_entry_policy("salta").unwrap(),
+            UnsafeEntryPolicy::Skip
+        );
+        assert!(parse_unsafe_entry_policy("ignora").is_err());
+
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_decompress_unsafe_links_policy() {
+        let base = std::env::temp_dir().join("test_tarslip_links");
+        let _ = fs::remove_dir_all(&base);
+        fs::create_dir_all(&base).unwrap();
+        let archive_path = base.join("links.tar.zst");
+        let output_dir = base.join("out");
+
+        // Link che escono dalla destinazione e un file scritto attraverso il link
+        write_raw_tar_zst(
+            &archive_path,
+            &[
+                ("data/file.txt", tar::EntryType::Regular, "", b"dati"),
+                ("data/ok", tar::EntryType::Symlink, "file.txt", b""),
+                ("data/hard", tar::EntryType::Link, "data/file.txt", b""),
+                ("escape", tar::EntryType::Symlink, "..", b""),
+                ("escape/via_link.txt", tar::EntryType::Regular, "", b"evil"),
+                ("abs", tar::EntryType::Symlink, "/etc", b""),
+                ("hard_escape", tar::EntryType::Link, "../outside.txt", b""),
+            ],
+        );
+
+        let options = DecompressOptions::new().with_output_path(&output_dir);
+        let err = decompress_tar_zst(&archive_path, &options).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(!base.join("via_link.txt").exists());
+
+        let options = DecompressOptions::new()
+            .with_output_path(&output_dir)
+            .with_force(true)
+            .with_unsafe_entries(UnsafeEntryPolicy::Skip);
+        decompress_tar_zst(&archive_path, &options).unwrap();
+        assert_eq!(fs::read(output_dir.join("data/ok")).unwrap(), b"dati");
+        assert_eq!(fs::read(output_dir.join("data/hard")).unwrap(), b"dati");
+        assert!(fs::symlink_metadata(output_dir.join("escape"))
+            .unwrap()
+            .is_dir());
+        assert!(!output_dir.join("abs").exists());
+        assert!(!output_dir.join("hard_escape").exists());
+        assert!(!base.join("via_link.txt").exists());
+
+        // Un link già presente nella destinazione non viene attraversato
+        fs::remove_dir_all(output_dir.join("escape")).unwrap();
+        std::os::unix::fs::symlink(&base, output_dir.join("escape")).unwrap();
+        let options = DecompressOptions::new()
+            .with_output_path(&output_dir)
+            .with_force(true);
+        write_raw_tar_zst(
+            &archive_path,
+            &[("escape/via_link.txt", tar::EntryType::Regular, "", b"evil")],
+        );
+        assert!(decompress_tar_zst(&archive_path, &options).is_err());
+        assert!(!base.join("via_link.txt").exists());
+
+        // Link in catena: ognuno resta dentro se preso da solo, ma il secondo
+        // viene creato attraverso il primo e porterebbe fuori
+        let _ = fs::remove_dir_all(&output_dir);
+        write_raw_tar_zst(
+            &archive_path,
+            &[
+                ("a/file.txt", tar::EntryType::Regular, "", b"dati"),
+                ("a/b", tar::EntryType::Symlink, "..", b""),
+                ("a/b/c", tar::EntryType::Symlink, "..", b""),
+                ("a/b/c/chained.txt", tar::EntryType::Regular, "", b"evil"),
+            ],
+        );
+        let options = DecompressOptions::new().with_output_path(&output_dir);
+        let err = decompress_tar_zst(&archive_path, &options).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(!base.join("chained.txt").exists());
+
+        let options = options.with_unsafe_entries(UnsafeEntryPolicy::Skip);
+        decompress_tar_zst(&archive_path, &options).unwrap();
+        assert!(!base.join("chained.txt").exists());
+        assert!(!fs::symlink_metadata(output_dir.join("c"))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read(output_dir.join("c/chained.txt")).unwrap(), b"evil");
+
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    fn limit_kind(err: &Error) -> Option<LimitKind> {
+        match err {
+            Error::LimitExceeded(limit) => Some(limit.kind),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_decompression_limits_single_file() {
+        let test_file = std::env::temp_dir().join("test_limits_bomb.bin");
+        fs::write(&test_file, vec![0u8; 4 * 1024 * 1024]).unwrap();
+        let options = CompressOptions::new(3)
+            .with_force(true)
+            .with_auto_parallel(false);
+        compress_file(&test_file, &options).unwrap();
+        let compressed = build_output_path(&test_file);
+        let output = std::env::temp_dir().join("test_limits_bomb.out");
+
+        let cases = [
+            (
+                DecompressionLimits::new().with_max_output_bytes(64 * 1024),
+                LimitKind::OutputBytes,
+            ),
+            (
+                DecompressionLimits::new().with_max_ratio(10),
+                LimitKind::Ratio,
+            ),
+            (
+                DecompressionLimits::new()
+                    .with_max_output_bytes(1024 * 1024)
+                    .with_max_entry_size(1000),
+                LimitKind::EntrySize,
+            ),
+        ];
+        for (limits, kind) in cases {
+            let options = DecompressOptions::new()
+                .with_force(true)
+                .with_output_path(&output)
+                .with_limits(limits);
+            let err = decompress_single_file(&compressed, &options).unwrap_err();
+            assert_eq!(limit_kind(&err), Some(kind));
+            assert!(!output.exists());
+
+            let err = verify_zst_with_options(&compressed, &options).unwrap_err();
+            assert_eq!(limit_kind(&err), Some(kind));
+
+            let input = File::open(&compressed).unwrap();
+            let err = decompress_stream(input, std::io::sink(), &options).unwrap_err();
+            assert_eq!(limit_kind(&err), Some(kind));
+        }
+
+        // Limiti sufficienti: nessun errore
+        let options = DecompressOptions::new()
+            .with_force(true)
+            .with_output_path(&output)
+            .with_limits(DecompressionLimits::new().with_max_output_bytes(4 * 1024 * 1024));
+        decompress_single_file(&compressed, &options).unwrap();
+
+        let _ = fs::remove_file(&test_file);
+        let _ = fs::remove_file(&compressed);
+        let _ = fs::remove_file(&output);
+    }
+
+    #[test]
+    fn test_decompression_limits_tar_archive() {
+        let test_dir = std::env::temp_dir().join("test_limits_dir");
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(&test_dir).unwrap();
+        for i in 0..3 {
+            fs::write(test_dir.join(format!("file{}.txt", i)), vec![b'a'; 10_000]).unwrap();
+        }
+        let options = CompressOptions::new(3).with_force(true);
+        compress_directory(&test_dir, &options).unwrap();
+        let archive_path = std::env::temp_dir().join("test_limits_dir.tar.zst");
+        let output_dir = std::env::temp_dir().join("test_limits_out");
+
+        let cases = [
+            (
+                DecompressionLimits::new().with_max_entries(2),
+                LimitKind::Entries,
+            ),
+            (
+                DecompressionLimits::new().with_max_entry_size(5_000),
+                LimitKind::EntrySize,
+            ),
+            (
+                DecompressionLimits::new().with_max_output_bytes(15_000),
+                LimitKind::OutputBytes,
+            ),
+        ];
+        for (limits, kind) in cases {
+            let _ = fs::remove_dir_all(&output_dir);
+            let options = DecompressOptions::new()
+                .with_output_path(&output_dir)
+                .with_limits(limits);
+            let err = decompress_tar_zst(&archive_path, &options).unwrap_err();
+            assert_eq!(limit_kind(&err), Some(kind), "{}", err);
+        }
+
+        let options = DecompressOptions::new()
+            .with_limits(DecompressionLimits::new().with_max_entry_size(5_000));
+        let err = extract_entry(
+            &archive_path,
+            Path::new("file0.txt"),
+            &mut std::io::sink(),
+            &options,
+        )
+        .unwrap_err();
+        assert_eq!(limit_kind(&err), Some(LimitKind::EntrySize));
+
+        assert_eq!(parse_size("512K").unwrap(), 512 * 1024);
+        assert_eq!(parse_size("2GiB").unwrap(), 2 << 30);
+        assert_eq!(parse_size("100").unwrap(), 100);
+        assert!(parse_size("dieci").is_err());
+
+        let _ = fs::remove_dir_all(&test_dir);
+        let _ = fs::remove_dir_all(&output_dir);
+        let _ = fs::remove_file(&archive_path);
+    }
+
+    #[test]
+    fn test_atomic_file_commit_and_discard() {
+        let target = std::env::temp_dir().join("test_atomic_target.txt");
+        fs::write(&target, b"vecchio").unwrap();
+
+        // Senza commit il file esistente resta intatto e il temporaneo sparisce
+        let mut file = AtomicFile::create(&target).unwrap();
+        file.write_all(b"nuovo").unwrap();
+        let temp = file.temp_path().to_path_buf();
+        assert!(temp.exists());
+        drop(file);
+        assert!(!temp.exists());
+        assert_eq!(fs::read(&target).unwrap(), b"vecchio");
+
+        let mut file = AtomicFile::create(&target).unwrap();
+        file.write_all(b"nuovo").unwrap();
+        file.commit().unwrap();
+        assert_eq!(fs::read(&target).unwrap(), b"nuovo");
+
+        let _ = fs::remove_file(&target);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_atomic_overwrite_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let input_path = create_temp_file("test_atomic_mode.txt", &b"segreto ".repeat(500));
+        let compressed_path = build_output_path(&input_path);
+        fs::write(&compressed_path, b"vecchio").unwrap();
+        fs::set_permissions(&compressed_path, fs::Permissions::from_mode(0o600)).unwrap();
+
+        compress_file(&input_path, &CompressOptions::new(3).with_force(true)).unwrap();
+        let mode = fs::metadata(&compressed_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(verify_zst_simple(&compressed_path).is_ok());
+
+        cleanup_files(&[&input_path, &compressed_path]);
+    }
+
+    #[test]
+    fn test_failed_decompress_keeps_existing_output() {
+        let test_dir = std::env::temp_dir().join("test_atomic_decompress");
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(&test_dir).unwrap();
+        let input = test_dir.join("data.txt");
+        fs::write(&input, b"contenuto valido ".repeat(1000)).unwrap();
+        compress_file(&input, &CompressOptions::new(3)).unwrap();
+        let compressed = test_dir.join("data.txt.zst");
+
+        // Archivio troncato: la decompressione fallisce a metà
+        let truncated = test_dir.join("troncato.txt.zst");
+        let bytes = fs::read(&compressed).unwrap();
+        fs::write(&truncated, &bytes[..bytes.len() / 2]).unwrap();
+
+        let options = DecompressOptions::new()
+            .with_force(true)
+            .with_output_path(&input);
+        assert!(decompress_single_file(&truncated, &options).is_err());
+
+        assert_eq!(fs::read(&input).unwrap(), b"contenuto valido ".repeat(1000));
+        let names: Vec<_> = fs::read_dir(&test_dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names.len(), 3, "{:?}", names);
+
+        let _ = fs::remove_dir_all(&test_dir);
+    }
+
+    #[test]
+    fn test_cancel_flag_interrupts_and_cleans_up() {
+        let test_dir = std::env::temp_dir().join("test_cancel_dir");
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(test_dir.join("src")).unwrap();
+        for i in 0..5 {
+            fs::write(test_dir.join("src").join(format!("f{}.txt", i)), b"dati").unwrap();
+        }
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        // Annullamento durante la compressione: il callback alza il flag al primo file
+        let flag = Arc::clone(&cancel);
+        let options = CompressOptions::new(3)
+            .with_force(true)
+            .with_cancel_flag(Arc::clone(&cancel))
+            .with_progress_events(move |_| flag.store(true, Ordering::Relaxed));
+        let err = compress_directory(&test_dir, &options).unwrap_err();
+        assert!(matches!(err, Error::Cancelled), "{}", err);
+        let archive_path = std::env::temp_dir().join("test_cancel_dir.tar.zst");
+        assert!(!archive_path.exists());
+
+        cancel.store(false, Ordering::Relaxed);
+        let options = CompressOptions::new(3).with_force(true);
+        compress_directory(&test_dir, &options).unwrap();
+
+        // Annullamento durante l'estrazione: la directory creata viene rimossa
+        let output_dir = std::env::temp_dir().join("test_cancel_out");
+        let _ = fs::remove_dir_all(&output_dir);
+        let flag = Arc::clone(&cancel);
+        let options = DecompressOptions::new()
+            .with_output_path(&output_dir)
+            .with_cancel_flag(Arc::clone(&cancel))
+            .with_progress_events(move |_| flag.store(true, Ordering::Relaxed));
+        let err = decompress_tar_zst(&archive_path, &options).unwrap_err();
+        assert!(matches!(err, Error::Cancelled), "{}", err);
+        assert!(!output_dir.exists());
+
+        // Flag già alzato: file singoli e verifica si fermano subito
+        let single = test_dir.join("src").join("f0.txt");
+        let options = CompressOptions::new(3).with_cancel_flag(Arc::clone(&cancel));
+        let err = compress_file(&single, &options).unwrap_err();
+        assert!(matches!(err, Error::Cancelled), "{}", err);
+        assert!(!build_output_path(&single).exists());
+
+        let options = DecompressOptions::new().with_cancel_flag(Arc::clone(&cancel));
+        let err = verify_zst_with_options(&archive_path, &options).unwrap_err();
+        assert!(matches!(err, Error::Cancelled), "{}", err);
+
+        let _ = fs::remove_dir_all(&test_dir);
+        let _ = fs::remove_file(&archive_path);
+    }
+
+    #[test]
+    fn test_error_variants() {
+        // File già compresso: saltato, non un errore di I/O
+        let image = create_temp_file("test_error_skip.jpg", b"finta immagine");
+        let err = compress_file(&image, &CompressOptions::new(3)).unwrap_err();
+        assert!(matches!(err, Error::Skipped { .. }), "{}", err);
+
+        let options = CompressOptions::new(3).with_smart_optimize(false);
+        compress_file(&image, &options).unwrap();
+        let err = compress_file(&image, &options).unwrap_err();
+        assert!(matches!(&err, Error::AlreadyExists(p) if *p == build_output_path(&image)));
+
+        // Dati zstd troncati: corrotto
+        let compressed = build_output_path(&image);
+        let data = fs::read(&compressed).unwrap();
+        fs::write(&compressed, &data[..data.len() - 4]).unwrap();
+        let err = verify_zst(&compressed, None).unwrap_err();
+        assert!(matches!(err, Error::Corrupt(_)), "{}", err);
+
+        // Il passaggio attraverso std::io::Error conserva la variante
+        let io_err: std::io::Error = Error::Skipped {
+            reason: "test".to_string(),
+        }
+        .into();
+        assert_eq!(io_err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(matches!(Error::from(io_err), Error::Skipped { reason } if reason == "test"));
+        let io_err: std::io::Error = LimitExceeded::new(LimitKind::Entries, 3).into();
+        assert!(matches!(Error::from(io_err), Error::LimitExceeded(l) if l.limit == 3));
+
+        let _ = fs::remove_file(&image);
+        let _ = fs::remove_file(&compressed);
+    }
+
+    #[test]
+    fn test_progress_events() {
+        use std::sync::Mutex;
+
+        fn recorder() -> (Arc<Mutex<Vec<ProgressEvent>>>, ProgressHandler) {
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let sink = Arc::clone(&events);
+            let handler: ProgressHandler = Box::new(move |e: &ProgressEvent| {
+                sink.lock().unwrap().push(e.clone());
+            });
+            (events, handler)
+        }
+
+        let test_dir = std::env::temp_dir().join("test_progress_events");
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(test_dir.join("sub")).unwrap();
+        fs::write(test_dir.join("a.txt"), b"aaaa").unwrap();
+        fs::write(test_dir.join("sub").join("b.txt"), b"bbbbbb").unwrap();
+
+        // Directory: totali noti in anticipo, un FileStarted/FileFinished per file
+        let (events, handler) = recorder();
+        let options = CompressOptions::new(3)
+            .with_force(true)
+            .with_progress_events(handler);
+        compress_directory(&test_dir, &options).unwrap();
+        let events = events.lock().unwrap().clone();
+        assert_eq!(
+            events.first(),
+            Some(&ProgressEvent::Started {
+                total_bytes: Some(10),
+                total_files: Some(2),
+            })
+        );
+        assert_eq!(events.last(), Some(&ProgressEvent::Finished));
+        let finished: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                ProgressEvent::FileFinished { path, result } => {
+                    Some((path.clone(), result.input_size))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            finished,
+            vec![
+                (PathBuf::from("a.txt"), 4),
+                (PathBuf::from("sub").join("b.txt"), 6),
+            ]
+        );
+        assert!(events.contains(&ProgressEvent::Bytes {
+            done: 10,
+            total: Some(10),
+        }));
+
+        // Estrazione: un FileFinished per ogni entry con il percorso nell'archivio
+        let archive_path = std::env::temp_dir().join("test_progress_events.tar.zst");
+        let output_dir = std::env::temp_dir().join("test_progress_events_out");
+        let _ = fs::remove_dir_all(&output_dir);
+        let (events, handler) = recorder();
+        let options = DecompressOptions::new()
+            .with_output_path(&output_dir)
+            .with_progress_events(handler);
+        decompress_tar_zst(&archive_path, &options).unwrap();
+        let events = events.lock().unwrap().clone();
+        let extracted = events
+            .iter()
+            .filter(|e| matches!(e, ProgressEvent::FileFinished { .. }))
+            .count();
+        assert_eq!(extracted, 2);
+        assert!(events.contains(&ProgressEvent::FileFinished {
+            path: PathBuf::from("sub").join("b.txt"),
+            result: CompressionResult {
+                input_size: 6,
+                output_size: 6,
+            },
+        }));
+        assert_eq!(events.last(), Some(&ProgressEvent::Finished));
+
+        // File saltato da smart_optimize: evento Skipped prima dell'errore
+        let image = test_dir.join("foto.jpg");
+        fs::write(&image, b"finta immagine").unwrap();
+        let (events, handler) = recorder();
+        let options = CompressOptions::new(3).with_progress_events(handler);
+        assert!(compress_file(&image, &options).is_err());
+        assert!(matches!(
+            events.lock().unwrap().as_slice(),
+            [ProgressEvent::Skipped { path, .. }] if *path == image
+        ));
+
+        let _ = fs::remove_dir_all(&test_dir);
+        let _ = fs::remove_dir_all(&output_dir);
+        let _ = fs::remove_file(&archive_path);
+    }
+
+    #[test]
+    fn test_decompression_progress_uses_compressed_bytes() {
+        use std::sync::Mutex;
+
+        // Contenuto poco comprimibile: una stima 3:1 sbaglierebbe di molto
+        let content: Vec<u8> = noise(3 * 1024 * 1024, 0x2545_f491)
+            .into_iter()
+            .map(|b| b % 16)
+            .collect();
+        let input_path = create_temp_file("test_accurate_progress.bin", &content);
+        let options = CompressOptions::new(3)
+            .with_force(true)
+            .with_auto_parallel(false)
+            .with_smart_optimize(false);
+        compress_file(&input_path, &options).unwrap();
+        let compressed_path = build_output_path(&input_path);
+        let compressed_size = fs::metadata(&compressed_path).unwrap().len();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        let options = DecompressOptions::new()
+            .with_force(true)
+            .with_progress_events(move |e| sink.lock().unwrap().push(e.clone()));
+        decompress_single_file(&compressed_path, &options).unwrap();
+
+        let events = events.lock().unwrap().clone();
+        let bytes: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                ProgressEvent::Bytes { done, total } => Some((*done, *total)),
+                _ => None,
+            })
+            .collect();
+        assert!(bytes.len() > 1);
+        assert!(bytes.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert!(bytes
+            .iter()
+            .all(|&(_, total)| total == Some(compressed_size)));
+        assert_eq!(bytes.last().unwrap().0, compressed_size);
+
+        // Il frame scritto da compress_file riporta la dimensione originale
+        let original = content.len() as u64;
+        assert!(events.contains(&ProgressEvent::Output {
+            done: original,
+            total: Some(original),
+        }));
+
+        // Da stream la dimensione non è nell'header: nessun totale
+        let stream_path = std::env::temp_dir().join("test_accurate_progress_stream.zst");
+        let mut stream_output = Vec::new();
+        compress_stream(&content[..], &mut stream_output, &CompressOptions::new(3)).unwrap();
+        fs::write(&stream_path, &stream_output).unwrap();
+        assert_eq!(codec::zstd_content_size(&stream_path).unwrap(), None);
+        assert_eq!(
+            codec::zstd_content_size(&compressed_path).unwrap(),
+            Some(original)
+        );
+
+        cleanup_files(&[&input_path, &compressed_path, &stream_path]);
+    }
+
+    #[test]
+    fn test_digest_sidecar_and_embedded() {
+        let content = b"Dati da verificare con SHA-256. ".repeat(2000);
+        let expected = Sha256Digest::of(&content);
+        let input_path = create_temp_file("test_digest_sidecar.txt", &content);
+        let options = CompressOptions::new(3)
+            .with_force(true)
+            .with_digest(DigestMode::Sidecar);
+        assert!(options.content_checksum);
+        compress_file(&input_path, &options).unwrap();
+        let compressed_path = build_output_path(&input_path);
+        let sidecar = digest::sidecar_path(&compressed_path);
+        let manifest = Manifest::read_sidecar(&compressed_path).unwrap().unwrap();
+        assert_eq!(
+            manifest.get(Path::new("test_digest_sidecar.txt")),
+            Some(expected)
+        );
+
+        let deep = DecompressOptions::new().with_deep_verify(true);
+        let result = verify_zst_with_options(&compressed_path, &deep).unwrap();
+        assert!(result.content_checksum);
+        assert_eq!(result.digest, Some(expected));
+        assert_eq!(result.digest_source, Some(DigestSource::Sidecar));
+
+        // Un sidecar alterato fa fallire la verifica approfondita
+        Manifest::single(Path::new("test_digest_sidecar.txt"), Sha256Digest([0; 32]))
+            .write_sidecar(&compressed_path)
+            .unwrap();
+        assert!(matches!(
+            verify_zst_with_options(&compressed_path, &deep),
+            Err(Error::Corrupt(_))
+        ));
+        // La verifica normale non legge il manifest
+        assert!(verify_zst_simple(&compressed_path).is_ok());
+
+        // Ricomprimendo senza sidecar quello vecchio non deve restare
+        compress_file(&input_path, &CompressOptions::new(3).with_force(true)).unwrap();
+        assert!(!sidecar.exists());
+        assert_eq!(
+            verify_zst_with_options(&compressed_path, &deep)
+                .unwrap()
+                .digest_source,
+            None
+        );
+
+        // Manifest incorporato: il file resta decomprimibile
+        let embedded_path = std::env::temp_dir().join("test_digest_embedded.txt.zst");
+        let options = CompressOptions::new(3)
+            .with_force(true)
+            .with_digest(DigestMode::Embedded)
+            .with_output_path(&embedded_path);
+        compress_file(&input_path, &options).unwrap();
+        let result = verify_zst_with_options(&embedded_path, &deep).unwrap();
+        assert_eq!(result.digest_source, Some(DigestSource::Embedded));
+        let output_path = std::env::temp_dir().join("test_digest_embedded_out.txt");
+        decompress_single_file(
+            &embedded_path,
+            &DecompressOptions::new()
+                .with_force(true)
+                .with_output_path(&output_path),
+        )
+        .unwrap();
+        assert_eq!(fs::read(&output_path).unwrap(), content);
+
+        cleanup_files(&[
+            &input_path,
+            &compressed_path,
+            &sidecar,
+            &embedded_path,
+            &output_path,
+        ]);
+    }
+
+    #[test]
+    fn test_verify_archive_entries_and_manifest() {
+        let test_dir = std::env::temp_dir().join("test_verify_archive_dir");
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(test_dir.join("sub")).unwrap();
+        fs::write(test_dir.join("a.txt"), b"12345").unwrap();
+        fs::write(test_dir.join("sub").join("b.txt"), b"1234567890").unwrap();
+        let archive_path = std::env::temp_dir().join("test_verify_archive.tar.zst");
+
+        let options = CompressOptions::new(3)
+            .with_force(true)
+            .with_digest(DigestMode::Embedded)
+            .with_output_path(&archive_path);
+        compress_directory(&test_dir, &options).unwrap();
+        let result = verify_archive(&archive_path, &DecompressOptions::new()).unwrap();
+        assert_eq!(result.entries, 3);
+        assert_eq!(result.entry_bytes, 15);
+        assert_eq!(result.files_checked, 2);
+        assert_eq!(result.digest_source, Some(DigestSource::Embedded));
+
+        // Sidecar con un digest sbagliato
+        let options = CompressOptions::new(3)
+            .with_force(true)
+            .with_output_path(&archive_path);
+        compress_directory(&test_dir, &options).unwrap();
+        let sidecar = digest::sidecar_path(&archive_path);
+        let mut manifest = Manifest::default();
+        manifest.push(Path::new("a.txt"), Sha256Digest::of(b"12345"));
+        manifest.push(Path::new("sub/b.txt"), Sha256Digest::of(b"altro"));
+        manifest.write_sidecar(&archive_path).unwrap();
+        assert!(matches!(
+            verify_archive(&archive_path, &DecompressOptions::new()),
+            Err(Error::Corrupt(msg)) if msg.contains("sub/b.txt")
+        ));
+        compress_directory(&test_dir, &options).unwrap();
+        assert!(!sidecar.exists());
+        let result = verify_archive(&archive_path, &DecompressOptions::new()).unwrap();
+        assert_eq!((result.files_checked, result.digest_source), (0, None));
+
+        let _ = fs::remove_dir_all(&test_dir);
+        cleanup_files(&[&archive_path]);
+    }
+
+    #[test]
+    fn test_verify_archive_detects_broken_tar() {
+        let mut tar = Builder::new(Vec::new());
+        for (name, data) in [("a.txt", &b"primo file"[..]), ("b.txt", &[7u8; 600][..])] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            tar.append_data(&mut header, name, data).unwrap();
+        }
+        let valid = tar.into_inner().unwrap();
+
+        let write_zst = |name: &str, data: &[u8]| {
+            let path = std::env::temp_dir().join(name);
+            let mut output = Vec::new();
+            compress_stream(data, &mut output, &CompressOptions::new(3)).unwrap();
+            fs::write(&path, output).unwrap();
+            path
+        };
+        let valid_path = write_zst("test_broken_tar_valid.tar.zst", &valid);
+        assert_eq!(
+            verify_archive(&valid_path, &DecompressOptions::new())
+                .unwrap()
+                .entries,
+            2
+        );
+
+        // Header alterato, dati troncati e fine dell'archivio mancante: lo stream
+        // zstd resta valido, la struttura del tar no
+        let mut bad_header = valid.clone();
+        bad_header[1024 + 100] ^= 1;
+        let broken = [
+            write_zst("test_broken_tar_header.tar.zst", &bad_header),
+            write_zst("test_broken_tar_data.tar.zst", &valid[..512 * 3]),
+            write_zst("test_broken_tar_end.tar.zst", &valid[..512 * 5]),
+        ];
+        for path in &broken {
+            assert!(verify_zst_simple(path).is_ok());
+            assert!(
+                matches!(
+                    verify_archive(path, &DecompressOptions::new()),
+                    Err(Error::Corrupt(_))
+                ),
+                "{:?}",
+                path
+            );
+        }
+
+        cleanup_files(&[&valid_path, &broken[0], &broken[1], &broken[2]]);
+    }
+
+    #[test]
+    fn test_split_volumes_roundtrip() {
+        let test_dir = std::env::temp_dir().join("test_split_dir");
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(&test_dir).unwrap();
+        let noise = noise(300 * 1024, 0x9e37_79b9);
+        fs::write(test_dir.join("noise.bin"), &noise).unwrap();
+        fs::write(test_dir.join("a.txt"), b"testo ".repeat(1000)).unwrap();
+
+        // I volumi in una cartella propria: nessun `.00N` resta nella temp
+        let archive_dir = std::env::temp_dir().join("test_split_volumes");
+        let _ = fs::remove_dir_all(&archive_dir);
+        fs::create_dir_all(&archive_dir).unwrap();
+        let archive_path = archive_dir.join("test_split.tar.zst");
+        let options = CompressOptions::new(3)
+            .with_force(true)
+            .with_split_size(64 * 1024)
+            .with_output_path(&archive_path);
+        let result = compress_directory(&test_dir, &options).unwrap();
+        assert!(!archive_path.exists());
+
+        let first = volume::volume_path(&archive_path, 1);
+        let set = VolumeSet::open(&first).unwrap();
+        assert!(set.paths().len() >= 5);
+        let sizes: Vec<u64> = set
+            .paths()
+            .iter()
+            .map(|p| fs::metadata(p).unwrap().len())
+            .collect();
+        assert!(sizes.iter().all(|&size| size <= 64 * 1024));
+        assert_eq!(sizes.iter().sum::<u64>(), result.output_size);
+
+        // I volumi concatenati sono un normale .tar.zst
+        let mut joined = Vec::new();
+        set.reader().unwrap().read_to_end(&mut joined).unwrap();
+        let mut concatenated = Vec::new();
+        for path in set.paths() {
+            concatenated.extend(fs::read(path).unwrap());
+        }
+        assert!(concatenated.starts_with(&joined));
+        let mut tar_data = Vec::new();
+        decompress_stream(&concatenated[..], &mut tar_data, &DecompressOptions::new()).unwrap();
+        assert_eq!(Archive::new(&tar_data[..]).entries().unwrap().count(), 2);
+
+        assert!(verify_zst_simple(&first).is_ok());
+        assert_eq!(
+            verify_archive(&first, &DecompressOptions::new())
+                .unwrap()
+                .entries,
+            2
+        );
+        let output_dir = std::env::temp_dir().join("test_split_out");
+        let _ = fs::remove_dir_all(&output_dir);
+        decompress_file(
+            &first,
+            &DecompressOptions::new().with_output_path(&output_dir),
+        )
+        .unwrap();
+        assert_eq!(fs::read(output_dir.join("noise.bin")).unwrap(), noise);
+
+        // Un set più corto sovrascritto con --force non lascia volumi vecchi
+        fs::remove_file(test_dir.join("noise.bin")).unwrap();
+        compress_directory(&test_dir, &options).unwrap();
+        assert!(!volume::volume_path(&archive_path, 2).exists());
+        assert!(VolumeSet::open(&first).is_ok());
+
+        let _ = fs::remove_dir_all(&test_dir);
+        let _ = fs::remove_dir_all(&output_dir);
+        let _ = fs::remove_dir_all(&archive_dir);
+    }
+
+    #[test]
+    fn test_split_volumes_missing_or_out_of_order() {
+        let files: Vec<PathBuf> = (0..4)
+            .map(|i| {
+                let data = noise(60 * 1024, 0x1234_5678 + i);
+                create_temp_file(&format!("test_split_part{}.bin", i), &data)
+            })
+            .collect();
+        let archive_path = std::env::temp_dir().join("test_split_missing.tar.zst");
+        let options = CompressOptions::new(1)
+            .with_force(true)
+            .with_split_size(64 * 1024);
+        compress_multiple_files(&files, &archive_path, &options).unwrap();
+        let volume = |i| volume::volume_path(&archive_path, i);
+        assert!(volume(4).exists());
+        let first = volume(1);
+
+        // Indicare un volume intermedio è un errore dell'utente
+        assert!(matches!(
+            verify_zst_simple(&volume(2)),
+            Err(Error::InvalidInput(_))
+        ));
+
+        // Volumi scambiati
+        let second = fs::read(volume(2)).unwrap();
+        let third = fs::read(volume(3)).unwrap();
+        fs::write(volume(2), &third).unwrap();
+        fs::write(volume(3), &second).unwrap();
+        assert!(matches!(
+            verify_zst_simple(&first),
+            Err(Error::Corrupt(msg)) if msg.contains("fuori ordine")
+        ));
+        fs::write(volume(2), &second).unwrap();
+        fs::write(volume(3), &third).unwrap();
+        assert!(verify_zst_simple(&first).is_ok());
+
+        // Volume intermedio mancante
+        fs::remove_file(volume(2)).unwrap();
+        assert!(matches!(
+            decompress_file(&first, &DecompressOptions::new()),
+            Err(Error::NotFound(msg)) if msg.contains("test_split_missing.tar.zst.002")
+        ));
+        fs::write(volume(2), &second).unwrap();
+
+        // Ultimo volume mancante
+        let last = (1..).map(volume).take_while(|p| p.exists()).last().unwrap();
+        let last_data = fs::read(&last).unwrap();
+        fs::remove_file(&last).unwrap();
+        assert!(matches!(verify_zst_simple(&first), Err(Error::NotFound(_))));
+        fs::write(&last, last_data).unwrap();
+
+        for path in (1..).map(volume).take_while(|p| p.exists()) {
+            let _ = fs::remove_file(path);
+        }
+        for file in &files {
+            let _ = fs::remove_file(file);
+        }
+    }
+
+    #[test]
+    fn test_split_volumes_require_skippable_frames() {
+        let input_path = create_temp_file("test_split_algorithm.txt", b"dati ");
+        let archive_path = std::env::temp_dir().join("test_split_algorithm.tar");
+        // gzip, xz e brotli non saltano il frame con la tabella dei volumi
+        for algorithm in [
+            CompressionAlgorithm::Gzip,
+            CompressionAlgorithm::Xz,
+            CompressionAlgorithm::Brotli,
+        ] {
+            if !algorithm.is_available() {
+                continue;
+            }
+            let options = CompressOptions::new(3)
+                .with_force(true)
+                .with_algorithm(algorithm)
+                .with_split_size(64 * 1024);
+            assert!(matches!(
+                compress_multiple_files(std::slice::from_ref(&input_path), &archive_path, &options),
+                Err(Error::InvalidInput(msg)) if msg.contains("zstd o lz4")
+            ));
+            assert!(!volume::volume_path(&archive_path, 1).exists());
+        }
+        cleanup_files(&[&input_path]);
+    }
+
+    #[test]
+    fn test_plain_file_with_volume_extension() {
+        // Un `.001` isolato senza tabella dei volumi è un file normale
+        let mut compressed = Vec::new();
+        compress_stream(
+            &b"non sono un volume ".repeat(100)[..],
+            &mut compressed,
+            &CompressOptions::new(3).with_auto_parallel(false),
+        )
+        .unwrap();
+        let plain = create_temp_file("test_plain_volume.zst.001", &compressed);
+        assert!(!volume::is_first_volume(&plain));
+        assert!(verify_zst_simple(&plain).is_ok());
+        cleanup_files(&[&plain]);
+
+        // Oltre il 999° volume il suffisso non avrebbe più tre cifre
+        let base = std::env::temp_dir().join("test_too_many_volumes.bin");
+        let mut writer = volume::VolumeWriter::create(&base, volume::MIN_VOLUME_SIZE).unwrap();
+        let chunk = vec![0u8; volume::MIN_VOLUME_SIZE as usize];
+        for _ in 0..volume::MAX_VOLUMES {
+            writer.write_all(&chunk).unwrap();
+        }
+        let err = writer.write_all(&chunk).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        drop(writer);
+        assert!(!volume::volume_path(&base, 1).exists());
+    }
+
+    #[test]
+    fn test_seekable_roundtrip_and_range() {
+        let content: Vec<u8> = (0..300_000u32)
+            .flat_map(|i| format!("riga {}\n", i).into_bytes())
+            .collect();
+        let input_path = create_temp_file("test_seekable.txt", &content);
+        let compressed_path = std::env::temp_dir().join("test_seekable.txt.zst");
+        let options = CompressOptions::new(3)
+            .with_force(true)
+            .with_auto_parallel(false)
+            .with_digest(DigestMode::Embedded)
+            .with_seekable(256 * 1024)
+            .with_output_path(&compressed_path);
+        compress_file(&input_path, &options).unwrap();
+
+        // Resta un normale file zstd, con il manifest prima della seek table
+        let output_path = std::env::temp_dir().join("test_seekable_out.txt");
+        let decompress = DecompressOptions::new()
+            .with_force(true)
+            .with_output_path(&output_path);
+        decompress_file(&compressed_path, &decompress).unwrap();
+        assert_eq!(fs::read(&output_path).unwrap(), content);
+        let deep = DecompressOptions::new().with_deep_verify(true);
+        let result = verify_zst_with_options(&compressed_path, &deep).unwrap();
+        assert_eq!(result.digest_source, Some(DigestSource::Embedded));
+
+        let table = SeekTable::read(&compressed_path).unwrap().unwrap();
+        let frames = content.len().div_ceil(256 * 1024);
+        assert_eq!(table.frames().len(), frames);
+        assert_eq!(table.decompressed_size(), content.len() as u64);
+
+        // Lettura a cavallo di un confine tra frame
+        let mut reader = SeekableReader::open(&compressed_path, None).unwrap();
+        let start = 256 * 1024 - 10;
+        reader.seek(SeekFrom::Start(start as u64)).unwrap();
+        let mut buf = vec![0u8; 100];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, &content[start..start + 100]);
+        reader.seek(SeekFrom::End(-5)).unwrap();
+        let mut tail = Vec::new();
+        reader.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, &content[content.len() - 5..]);
+
+        let mut range = Vec::new();
+        let written = extract_range(
+            &compressed_path,
+            1_000_000,
+            Some(50_000),
+            &mut range,
+            &DecompressOptions::new(),
+        )
+        .unwrap();
+        assert_eq!(written, 50_000);
+        assert_eq!(range, &content[1_000_000..1_050_000]);
+
+        // Lunghezza oltre la fine: si ferma all'ultimo byte
+        let mut range = Vec::new();
+        let offset = content.len() as u64 - 3;
+        extract_range(
+            &compressed_path,
+            offset,
+            Some(10),
+            &mut range,
+            &DecompressOptions::new(),
+        )
+        .unwrap();
+        assert_eq!(range, &content[content.len() - 3..]);
+        assert!(matches!(
+            extract_range(
+                &compressed_path,
+                content.len() as u64 + 1,
+                None,
+                &mut Vec::new(),
+                &DecompressOptions::new()
+            ),
+            Err(Error::InvalidInput(_))
+        ));
+
+        let _ = fs::remove_file(&input_path);
+        let _ = fs::remove_file(&compressed_path);
+        let _ = fs::remove_file(&output_path);
+    }
+
+    #[test]
+    fn test_seekable_frame_limits_checked_before_decompression() {
+        // Un frame di pochi bytes che nella seek table dichiara 1 GiB
+        let frame = zstd::encode_all(&b"x"[..], 3).unwrap();
+        let mut data = frame.clone();
+        data.extend_from_slice(&seekable::SEEK_TABLE_MAGIC.to_le_bytes());
+        data.extend_from_slice(&17u32.to_le_bytes());
+        data.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        data.extend_from_slice(&seekable::MAX_FRAME_SIZE.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.push(0);
+        data.extend_from_slice(&seekable::SEEKABLE_MAGIC.to_le_bytes());
+        let input_path = create_temp_file("test_seekable_bomb.zst", &data);
+
+        let cases = [
+            (
+                DecompressionLimits::new().with_max_output_bytes(1024 * 1024),
+                LimitKind::OutputBytes,
+            ),
+            (
+                DecompressionLimits::new().with_max_ratio(100),
+                LimitKind::Ratio,
+            ),
+        ];
+        for (limits, kind) in cases {
+            let options = DecompressOptions::new().with_limits(limits);
+            let err =
+                extract_range(&input_path, 0, Some(1), &mut Vec::new(), &options).unwrap_err();
+            assert_eq!(limit_kind(&err), Some(kind));
+        }
+
+        let _ = fs::remove_file(&input_path);
+    }
+
+    #[test]
+    fn test_seekable_rejects_plain_zstd() {
+        let input_path = create_temp_file(
+            "test_seekable_plain.txt",
+            b"non seekable ".repeat(100).as_slice(),
+        );
+        let options = CompressOptions::new(3).with_force(true);
+        compress_file(&input_path, &options).unwrap();
+        let compressed_path = build_output_path(&input_path);
+        assert!(matches!(
+            SeekableReader::open(&compressed_path, None),
+            Err(Error::UnsupportedFormat(_))
+        ));
+
+        // Il formato seekable riguarda solo zstd
+        #[cfg(feature = "gzip")]
+        {
+            let gzip = CompressOptions::new(3)
+                .with_force(true)
+                .with_algorithm(CompressionAlgorithm::Gzip)
+                .with_seekable(seekable::DEFAULT_FRAME_SIZE);
+            assert!(matches!(
+                compress_file(&input_path, &gzip),
+                Err(Error::InvalidInput(_))
+            ));
+        }
+
+        let _ = fs::remove_file(&input_path);
+        let _ = fs::remove_file(&compressed_path);
+    }
+
+    #[test]
+    fn test_indexed_archive_list_and_cat() {
+        let test_dir = std::env::temp_dir().join("test_indexed_dir");
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(test_dir.join("sub")).unwrap();
+        for i in 0..30 {
+            let content = format!("file piccolo {}\n", i).repeat(50 + i);
+            fs::write(test_dir.join(format!("f{:02}.txt", i)), content).unwrap();
+        }
+        let big: Vec<u8> = (0..200_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let long_name = format!("{}.bin", "nome_lungo_".repeat(12));
+        fs::write(test_dir.join("sub").join(&long_name), &big).unwrap();
+
+        let archive_path = std::env::temp_dir().join("test_indexed_dir.tar.zst");
+        let options = CompressOptions::new(3)
+            .with_force(true)
+            .with_auto_parallel(false)
+            .with_digest(DigestMode::Embedded)
+            .with_seekable(64 * 1024)
+            .with_output_path(&archive_path);
+        compress_directory(&test_dir, &options).unwrap();
+
+        // L'indice elenca ogni membro, che inizia entro la prima metà di un frame
+        let index = ArchiveIndex::read(&archive_path).unwrap().unwrap();
+        assert_eq!(index.entries.len(), 32);
+        let table = SeekTable::read(&archive_path).unwrap().unwrap();
+        assert!(table.frames().len() > 10);
+        for member in &index.entries {
+            let frame = &table.frames()[table.frame_at(member.offset).unwrap()];
+            assert!(member.offset - frame.decompressed_offset < 32 * 1024);
+        }
+        let big_path = Path::new("sub").join(&long_name);
+
+        // L'elenco dall'indice coincide con quello della lettura sequenziale
+        let listed = list_archive(&archive_path, &DecompressOptions::new()).unwrap();
+        let plain_path = std::env::temp_dir().join("test_indexed_plain.tar.zst");
+        let plain = CompressOptions::new(3)
+            .with_force(true)
+            .with_output_path(&plain_path);
+        compress_directory(&test_dir, &plain).unwrap();
+        assert!(ArchiveIndex::read(&plain_path).unwrap().is_none());
+        let expected = list_archive(&plain_path, &DecompressOptions::new()).unwrap();
+        assert_eq!(listed, expected);
+
+        let mut output = Vec::new();
+        extract_entry(
+            &archive_path,
+            &big_path,
+            &mut output,
+            &DecompressOptions::new(),
+        )
+        .unwrap();
+        assert_eq!(output, big);
+        let mut output = Vec::new();
+        extract_entry(
+            &archive_path,
+            Path::new("./f17.txt"),
+            &mut output,
+            &DecompressOptions::new(),
+        )
+        .unwrap();
+        assert_eq!(output, "file piccolo 17\n".repeat(67).into_bytes());
+        assert!(matches!(
+            extract_entry(
+                &archive_path,
+                Path::new("manca.txt"),
+                &mut Vec::new(),
+                &DecompressOptions::new()
+            ),
+            Err(Error::NotFound(_))
+        ));
+
+        // I limiti di decompressione valgono anche con l'indice
+        let limited = DecompressOptions::new()
+            .with_limits(DecompressionLimits::new().with_max_output_bytes(64 * 1024));
+        let err = extract_entry(&archive_path, &big_path, &mut Vec::new(), &limited).unwrap_err();
+        assert_eq!(limit_kind(&err), Some(LimitKind::OutputBytes));
+        let limited = DecompressOptions::new()
+            .with_limits(DecompressionLimits::new().with_max_output_bytes(4 * 1024));
+        let err = list_archive(&archive_path, &limited).unwrap_err();
+        assert_eq!(limit_kind(&err), Some(LimitKind::OutputBytes));
+
+        // Resta un tar.zst normale, verificabile anche contro l'indice
+        let result = verify_archive(&archive_path, &DecompressOptions::new()).unwrap();
+        assert!(result.indexed);
+        assert_eq!(result.files_checked, 31);
+        let extract_dir = std::env::temp_dir().join("test_indexed_extract");
+        let _ = fs::remove_dir_all(&extract_dir);
+        let decompress = DecompressOptions::new().with_output_path(&extract_dir);
+        decompress_file(&archive_path, &decompress).unwrap();
+        assert_eq!(
+            fs::read(extract_dir.join("sub").join(&long_name)).unwrap(),
+            big
+        );
+
+        let _ = fs::remove_dir_all(&test_dir);
+        let _ = fs::remove_dir_all(&extract_dir);
+        let _ = fs::remove_file(&archive_path);
+        let _ = fs::remove_file(&plain_path);
+    }
+
+    #[test]
+    fn test_parallel_decompression_of_seekable_frames() {
+        use std::sync::Mutex;
+
+        let content: Vec<u8> = (0..400_000u32)
+            .flat_map(|i| format!("{:08}\n", i * 7).into_bytes())
+            .collect();
+        let input_path = create_temp_file("test_parallel_frames.txt", &content);
+        let compressed_path = std::env::temp_dir().join("test_parallel_frames.txt.zst");
+        let options = CompressOptions::new(3)
+            .with_force(true)
+            .with_digest(DigestMode::Embedded)
+            .with_seekable(128 * 1024)
+            .with_output_path(&compressed_path);
+        compress_file(&input_path, &options).unwrap();
+        let compressed_size = fs::metadata(&compressed_path).unwrap().len();
+        assert!(
+            SeekTable::read(&compressed_path)
+                .unwrap()
+                .unwrap()
+                .frames()
+                .len()
+                > 20
+        );
+
+        let output_path = std::env::temp_dir().join("test_parallel_frames.out");
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        let decompress = DecompressOptions::new()
+            .with_force(true)
+            .with_parallel(true)
+            .with_output_path(&output_path)
+            .with_progress_events(move |e| sink.lock().unwrap().push(e.clone()));
+        decompress_file(&compressed_path, &decompress).unwrap();
+        assert_eq!(fs::read(&output_path).unwrap(), content);
+        // Anche i frame skippable finali contano come letti
+        assert!(events.lock().unwrap().contains(&ProgressEvent::Bytes {
+            done: compressed_size,
+            total: Some(compressed_size),
+        }));
+
+        // I limiti valgono anche in parallelo
+        let limited = DecompressOptions::new()
+            .with_force(true)
+            .with_parallel(true)
+            .with_output_path(&output_path)
+            .with_limits(DecompressionLimits::new().with_max_output_bytes(1024 * 1024));
+        assert!(matches!(
+            decompress_file(&compressed_path, &limited),
+            Err(Error::LimitExceeded(_))
+        ));
+
+        // Un frame danneggiato viene segnalato
+        let mut damaged = fs::read(&compressed_path).unwrap();
+        let table = SeekTable::read(&compressed_path).unwrap().unwrap();
+        let frame = table.frames()[5];
+        damaged[(frame.compressed_offset + u64::from(frame.compressed_size) / 2) as usize] ^= 0xFF;
+        fs::write(&compressed_path, &damaged).unwrap();
+        let parallel = DecompressOptions::new()
+            .with_force(true)
+            .with_parallel(true)
+            .with_output_path(&output_path);
+        assert!(matches!(
+            decompress_file(&compressed_path, &parallel),
+            Err(Error::Corrupt(_))
+        ));
+
+        let _ = fs::remove_file(&input_path);
+        let _ = fs::remove_file(&compressed_path);
+        let _ = fs::remove_file(&output_path);
+    }
+
+    #[test]
+    fn test_read_ahead_keeps_archive_identical() {
+        let test_dir = std::env::temp_dir().join("test_read_ahead_dir");
+        let _ = fs::remove_dir_all(&test_dir);
+        for i in 0..120 {
+            let dir = test_dir.join(format!("d{}", i % 7));
+            fs::create_dir_all(&dir).unwrap();
+            let content = format!("contenuto {} ", i).repeat(i * 40);
+            fs::write(dir.join(format!("f{:03}.txt", i)), content).unwrap();
+        }
+        fs::write(test_dir.join("grande.bin"), vec![7u8; 300_000]).unwrap();
+
+        // Stesso tar con lettura sequenziale, con il default e con un limite
+        // più piccolo di molti file (letti allora dal thread del tar)
+        let archive_path = std::env::temp_dir().join("test_read_ahead.tar.zst");
+        let mut tars = Vec::new();
+        let mut manifests = Vec::new();
+        for read_ahead in [0, prefetch::DEFAULT_READ_AHEAD, 8 * 1024] {
+            let options = CompressOptions::new(3)
+                .with_force(true)
+                .with_auto_parallel(false)
+                .with_digest(DigestMode::Sidecar)
+                .with_read_ahead(read_ahead)
+                .with_output_path(&archive_path);
+            compress_directory(&test_dir, &options).unwrap();
+            let mut tar_bytes = Vec::new();
+            decompress_stream(
+                File::open(&archive_path).unwrap(),
+                &mut tar_bytes,
+                &DecompressOptions::new(),
+            )
+            .unwrap();
+            tars.push(tar_bytes);
+            manifests.push(Manifest::read_sidecar(&archive_path).unwrap().unwrap());
+        }
+        assert!(tars.windows(2).all(|w| w[0] == w[1]));
+        assert!(manifests.windows(2).all(|w| w[0] == w[1]));
+        assert_eq!(manifests[0].entries.len(), 121);
+        assert!(verify_archive(&archive_path, &DecompressOptions::new()).is_ok());
+
+        let _ = fs::remove_dir_all(&test_dir);
+        let _ = fs::remove_file(&archive_path);
+        let _ = fs::remove_file(digest::sidecar_path(&archive_path));
+    }
+
+    #[test]
+    fn test_incremental_backup_chain_and_restore() {
+        let base = std::env::temp_dir().join("test_incremental_backup");
+        let _ = fs::remove_dir_all(&base);
+        let source = base.join("sorgente");
+        fs::create_dir_all(source.join("vecchia")).unwrap();
+        fs::write(source.join("fisso.txt"), "non cambia").unwrap();
+        fs::write(source.join("modificato.txt"), "prima").unwrap();
+        fs::write(source.join("vecchia/eliminato.txt"), "sparirà").unwrap();
+        fs::create_dir_all(source.join("cartella")).unwrap();
+        fs::write(source.join("cartella/dentro.txt"), "nella directory").unwrap();
+        fs::write(source.join("documento"), "era un file").unwrap();
+
+        let full_path = base.join("backup-1.tar.zst");
+        let options = CompressOptions::new(3)
+            .with_auto_parallel(false)
+            .with_backup(BackupMode::Full)
+            .with_output_path(&full_path);
+        compress_directory(&source, &options).unwrap();
+        let full_state = BackupState::for_archive(&full_path).unwrap();
+        assert_eq!(full_state.base, None);
+        assert_eq!(full_state.entries.len(), 7);
+        assert!(matches!(
+            full_state.entries[Path::new("fisso.txt")],
+            StateEntry::File { size: 10, sha256, .. } if sha256 == Sha256Digest::of(b"non cambia")
+        ));
+        assert_eq!(
+            full_state.entries[Path::new("vecchia")],
+            StateEntry::Directory
+        );
+
+        // Dimensione diversa: il file risulta modificato anche con date uguali
+        fs::write(source.join("modificato.txt"), "dopo la modifica").unwrap();
+        fs::write(source.join("nuovo.txt"), "aggiunto").unwrap();
+        fs::remove_dir_all(source.join("vecchia")).unwrap();
+        // Cambi di tipo: una directory diventa un file e un file una directory
+        fs::remove_dir_all(source.join("cartella")).unwrap();
+        fs::write(source.join("cartella"), "ora un file").unwrap();
+        fs::remove_file(source.join("documento")).unwrap();
+        fs::create_dir_all(source.join("documento")).unwrap();
+        fs::write(source.join("documento/figlio.txt"), "ora una directory").unwrap();
+
+        let incremental_path = base.join("backup-2.tar.zst");
+        let options = CompressOptions::new(3)
+            .with_auto_parallel(false)
+            .with_backup(BackupMode::Incremental(full_path.clone()))
+            .with_output_path(&incremental_path);
+        compress_directory(&source, &options).unwrap();
+
+        let mut archived: Vec<PathBuf> = list_archive(&incremental_path, &DecompressOptions::new())
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+        archived.sort();
+        assert_eq!(
+            archived,
+            [
+                PathBuf::from("cartella"),
+                PathBuf::from("documento"),
+                PathBuf::from("documento/figlio.txt"),
+                PathBuf::from("modificato.txt"),
+                PathBuf::from("nuovo.txt")
+            ]
+        );
+        let state = BackupState::for_archive(&incremental_path).unwrap();
+        assert_eq!(state.base, Some(PathBuf::from("backup-1.tar.zst")));
+        assert_eq!(
+            state.deleted,
+            [
+                PathBuf::from("cartella"),
+                PathBuf::from("cartella/dentro.txt"),
+                PathBuf::from("documento"),
+                PathBuf::from("vecchia"),
+                PathBuf::from("vecchia/eliminato.txt")
+            ]
+        );
+        assert_eq!(
+            backup_chain(&incremental_path)
+                .unwrap()
+                .into_iter()
+                .map(|(archive, _)| archive)
+                .collect::<Vec<_>>(),
+            [full_path.clone(), incremental_path.clone()]
+        );
+
+        // Ripristino fino all'ultimo backup: la directory com'è adesso
+        let restored = base.join("ripristino");
+        let result = restore_backup(
+            &incremental_path,
+            &DecompressOptions::new().with_output_path(&restored),
+        )
+        .unwrap();
+        assert_eq!(result.archives.len(), 2);
+        // I file eliminati spariscono insieme alla loro directory
+        assert_eq!(result.deleted, 3);
+        assert_eq!(
+            fs::read_to_string(restored.join("modificato.txt")).unwrap(),
+            "dopo la modifica"
+        );
+        assert_eq!(
+            fs::read_to_string(restored.join("fisso.txt")).unwrap(),
+            "non cambia"
+        );
+        assert!(restored.join("nuovo.txt").exists());
+        assert!(!restored.join("vecchia").exists());
+        assert_eq!(
+            fs::read_to_string(restored.join("cartella")).unwrap(),
+            "ora un file"
+        );
+        assert_eq!(
+            fs::read_to_string(restored.join("documento/figlio.txt")).unwrap(),
+            "ora una directory"
+        );
+
+        // Ripristino fino al backup completo: la directory com'era allora
+        let restored_first = base.join("ripristino-1");
+        restore_backup(
+            &full_path,
+            &DecompressOptions::new().with_output_path(&restored_first),
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(restored_first.join("modificato.txt")).unwrap(),
+            "prima"
+        );
+        assert!(restored_first.join("vecchia/eliminato.txt").exists());
+        assert!(!restored_first.join("nuovo.txt").exists());
+
+        // L'archivio di base non si sovrascrive con un incrementale
+        let options = CompressOptions::new(3)
+            .with_force(true)
+            .with_backup(BackupMode::Incremental(full_path.clone()))
+            .with_output_path(&full_path);
+        assert!(matches!(
+            compress_directory(&source, &options),
+            Err(Error::InvalidInput(_))
+        ));
+
+        let _ = fs::remove_dir_all(&base);
+    }
 }
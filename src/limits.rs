@@ -0,0 +1,243 @@
+//! Limiti contro le "decompression bomb".
+//!
+//! I limiti sono verificati mentre i dati vengono decompressi: il reader si ferma
+//! con un [`LimitExceeded`] prima di consegnare i bytes oltre la soglia, quindi
+//! non si scrive mai più del limite (più al massimo un buffer già scartato).
+
+use std::cell::Cell;
+use std::io::{Read, Seek, SeekFrom};
+
+/// Limiti applicati durante la decompressione (default: nessun limite)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecompressionLimits {
+    /// Bytes decompressi totali (per gli archivi tar include gli header)
+    pub max_output_bytes: Option<u64>,
+    /// Rapporto massimo tra bytes decompressi e compressi (es. 100 = 100:1)
+    pub max_ratio: Option<u64>,
+    /// Numero massimo di entry in un archivio tar
+    pub max_entries: Option<u64>,
+    /// Dimensione massima di un singolo file decompresso o di un'entry tar
+    pub max_entry_size: Option<u64>,
+}
+
+impl DecompressionLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_output_bytes(mut self, bytes: u64) -> Self {
+        self.max_output_bytes = Some(bytes);
+        self
+    }
+
+    pub fn with_max_ratio(mut self, ratio: u64) -> Self {
+        self.max_ratio = Some(ratio);
+        self
+    }
+
+    pub fn with_max_entries(mut self, entries: u64) -> Self {
+        self.max_entries = Some(entries);
+        self
+    }
+
+    pub fn with_max_entry_size(mut self, bytes: u64) -> Self {
+        self.max_entry_size = Some(bytes);
+        self
+    }
+
+    /// Ritorna true se nessun limite è impostato
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Verifica il numero di entry lette finora da un archivio
+    pub(crate) fn check_entries(&self, entries: u64) -> crate::Result<()> {
+        match self.max_entries {
+            Some(max) if entries > max => Err(LimitExceeded::new(LimitKind::Entries, max).into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Verifica la dimensione dichiarata nell'header di un'entry, prima di leggerla
+    pub(crate) fn check_entry_size(&self, size: u64) -> crate::Result<()> {
+        match self.max_entry_size {
+            Some(max) if size > max => Err(LimitExceeded::new(LimitKind::EntrySize, max).into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Verifica le dimensioni dichiarate di un frame prima di allocarne i dati
+    /// decompressi: il frame da solo non deve superare il totale né il rapporto
+    pub(crate) fn check_frame(&self, compressed: u64, decompressed: u64) -> crate::Result<()> {
+        if let Some(max) = self.max_output_bytes.filter(|&max| decompressed > max) {
+            return Err(LimitExceeded::new(LimitKind::OutputBytes, max).into());
+        }
+        match self.max_ratio {
+            Some(ratio) if decompressed > ratio.saturating_mul(compressed.max(1)) => {
+                Err(LimitExceeded::new(LimitKind::Ratio, ratio).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Limite sui bytes prodotti: per un file singolo vale anche `max_entry_size`
+    fn output_cap(&self, single_entry: bool) -> Option<LimitExceeded> {
+        let total = self
+            .max_output_bytes
+            .map(|max| LimitExceeded::new(LimitKind::OutputBytes, max));
+        let entry = self
+            .max_entry_size
+            .filter(|_| single_entry)
+            .map(|max| LimitExceeded::new(LimitKind::EntrySize, max));
+
+        match (total, entry) {
+            (Some(t), Some(e)) => Some(if e.limit < t.limit { e } else { t }),
+            (t, e) => t.or(e),
+        }
+    }
+}
+
+/// Limite che ha interrotto la decompressione
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    OutputBytes,
+    Ratio,
+    Entries,
+    EntrySize,
+}
+
+/// Limite superato, riportato come [`crate::Error::LimitExceeded`].
+///
+/// Dentro i reader viaggia in un `std::io::Error` (kind `Other`): usa
+/// [`LimitExceeded::from_io_error`] per riconoscerlo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitExceeded {
+    pub kind: LimitKind,
+    /// Valore del limite superato (bytes, rapporto o numero di entry)
+    pub limit: u64,
+}
+
+impl LimitExceeded {
+    pub fn new(kind: LimitKind, limit: u64) -> Self {
+        Self { kind, limit }
+    }
+
+    /// Estrae il limite superato da un errore di I/O, anche se incapsulato
+    /// da altri errori (es. quelli di estrazione di tar)
+    pub fn from_io_error(err: &std::io::Error) -> Option<&LimitExceeded> {
+        let mut current = err;
+        loop {
+            let inner = current.get_ref()?;
+            if let Some(limit) = inner.downcast_ref::<LimitExceeded>() {
+                return Some(limit);
+            }
+            current = inner.source()?.downcast_ref::<std::io::Error>()?;
+        }
+    }
+}
+
+impl std::fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            LimitKind::OutputBytes => write!(
+                f,
+                "Limite di decompressione superato: output oltre {}",
+                crate::format_size(self.limit)
+            ),
+            LimitKind::Ratio => write!(
+                f,
+                "Limite di decompressione superato: rapporto di espansione oltre {}:1",
+                self.limit
+            ),
+            LimitKind::Entries => write!(
+                f,
+                "Limite di decompressione superato: più di {} entry nell'archivio",
+                self.limit
+            ),
+            LimitKind::EntrySize => write!(
+                f,
+                "Limite di decompressione superato: file oltre {}",
+                crate::format_size(self.limit)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+impl From<LimitExceeded> for std::io::Error {
+    fn from(err: LimitExceeded) -> Self {
+        std::io::Error::other(err)
+    }
+}
+
+/// Bytes compressi con cui calcolare il rapporto di espansione
+pub(crate) enum CompressedSize<'a> {
+    /// Dimensione del file di input
+    Known(u64),
+    /// Bytes letti finora da uno stream
+    Counted(&'a Cell<u64>),
+}
+
+impl CompressedSize<'_> {
+    fn get(&self) -> u64 {
+        match self {
+            CompressedSize::Known(size) => *size,
+            CompressedSize::Counted(count) => count.get(),
+        }
+    }
+}
+
+/// Reader sui dati decompressi che si ferma al superamento dei limiti
+pub(crate) struct LimitedReader<'a, R> {
+    inner: R,
+    cap: Option<LimitExceeded>,
+    max_ratio: Option<u64>,
+    compressed: CompressedSize<'a>,
+    produced: u64,
+}
+
+impl<'a, R: Read> LimitedReader<'a, R> {
+    /// `single_entry` è true per un file compresso singolo, false per uno stream tar
+    pub(crate) fn new(
+        inner: R,
+        limits: &DecompressionLimits,
+        compressed: CompressedSize<'a>,
+        single_entry: bool,
+    ) -> Self {
+        Self {
+            inner,
+            cap: limits.output_cap(single_entry),
+            max_ratio: limits.max_ratio,
+            compressed,
+            produced: 0,
+        }
+    }
+}
+
+/// Gli spostamenti non azzerano il conteggio: i limiti valgono per tutti i
+/// bytes decompressi, anche letti da posizioni diverse
+impl<R: Read + Seek> Seek for LimitedReader<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl<R: Read> Read for LimitedReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.produced += n as u64;
+
+        if let Some(cap) = self.cap {
+            if self.produced > cap.limit {
+                return Err(cap.into());
+            }
+        }
+        if let Some(ratio) = self.max_ratio {
+            if self.produced > ratio.saturating_mul(self.compressed.get().max(1)) {
+                return Err(LimitExceeded::new(LimitKind::Ratio, ratio).into());
+            }
+        }
+        Ok(n)
+    }
+}
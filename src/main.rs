@@ -1,16 +1,23 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use file_compressor::{
-    compress_directory, compress_file, compress_file_simple, compress_multiple_files,
-    count_files_in_dir, decompress_single_file, decompress_tar_zst, format_ratio, format_size,
-    parse_level, verify_zst, CompressOptions, DecompressOptions, ProgressCallback,
+    backup_chain, cleanup_partial_outputs, compress_directory, compress_file,
+    compress_multiple_files, compress_stream, decompress_single_file, decompress_stream,
+    decompress_tar_zst, detect_format, extract_entry, extract_range, format_ratio, format_size,
+    list_archive, parse_algorithm, parse_digest_mode, parse_level, parse_metadata_restore,
+    parse_size, parse_strategy, parse_unsafe_entry_policy, restore_backup, seekable,
+    verify_archive, verify_zst_with_options, volume, ArchiveEntry, ArchiveEntryType, AtomicFile,
+    BackupMode, CompressOptions, CompressionAlgorithm, CompressionDictionary, CompressionStrategy,
+    DecompressOptions, DecompressionLimits, DigestMode, DigestSource, Error, MetadataRestore,
+    PathFilter, ProgressEvent, Result, UnsafeEntryPolicy, VolumeSet, DEFAULT_DICT_SIZE,
 };
 use glob::glob;
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
+use std::io::{IsTerminal, Write};
 use std::path::{Path, PathBuf};
 use std::process;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
 
 /// Un programma per comprimere e decomprimere file con l'algoritmo Zstandard
 #[derive(Parser, Debug)]
@@ -24,7 +31,7 @@ struct Cli {
 enum Commands {
     /// Comprime un file o una directory
     Compress {
-        /// Il file o la directory da comprimere
+        /// Il file o la directory da comprimere (`-` per stdin)
         #[arg(value_name = "FILE")]
         input_file: PathBuf,
 
@@ -44,13 +51,76 @@ enum Commands {
         #[arg(long)]
         no_smart: bool,
 
-        /// Percorso di destinazione (file o directory)
+        /// Percorso di destinazione (file o directory, `-` per stdout)
         #[arg(short, long, value_name = "PERCORSO")]
         output: Option<PathBuf>,
+
+        /// Dizionario zstd da usare (creato con train-dict)
+        #[arg(long, value_name = "DIZIONARIO")]
+        dict: Option<PathBuf>,
+
+        /// Strategia zstd esplicita (fast, default, greedy, lazy, lazy2, btlazy2)
+        #[arg(long, value_parser = parse_strategy, value_name = "STRATEGIA")]
+        strategy: Option<CompressionStrategy>,
+
+        /// Algoritmo di compressione (zstd, lz4, gzip, xz, brotli)
+        #[arg(short, long, default_value = "zstd", value_parser = parse_algorithm, value_name = "ALGORITMO")]
+        algoritmo: CompressionAlgorithm,
+
+        /// Per le directory: archivia solo i file che corrispondono al glob (ripetibile)
+        #[arg(long, value_name = "GLOB")]
+        include: Vec<String>,
+
+        /// Per le directory: salta file e directory che corrispondono al glob (ripetibile)
+        #[arg(long, value_name = "GLOB")]
+        exclude: Vec<String>,
+
+        /// Per le directory: rispetta .gitignore/.ignore e salta .git
+        #[arg(long)]
+        gitignore: bool,
+
+        /// Per le directory: archivia il contenuto dei link simbolici invece dei link
+        #[arg(long)]
+        follow_symlinks: bool,
+
+        /// Salva lo SHA-256 dei file originali: sidecar (file .sha256) o embedded (nel .zst)
+        #[arg(long, value_parser = parse_digest_mode, value_name = "MODO")]
+        digest: Option<DigestMode>,
+
+        /// Non scrive il checksum del contenuto nei frame zstd
+        #[arg(long)]
+        no_checksum: bool,
+
+        /// Per le directory: divide l'archivio in volumi .001, .002, ... di questa dimensione (es. 4G)
+        #[arg(long, value_parser = parse_size, value_name = "SIZE")]
+        split_size: Option<u64>,
+
+        /// Per le directory: memoria per leggere i file in anticipo su più thread (default 64M, 0 = sequenziale)
+        #[arg(long, value_parser = parse_size, value_name = "SIZE")]
+        read_ahead: Option<u64>,
+
+        /// Formato zstd seekable: frame indipendenti e seek table per l'accesso casuale
+        /// (vedi `range`); per le directory anche l'indice dei membri per `list` e `cat`
+        #[arg(long)]
+        seekable: bool,
+
+        /// Dimensione dei frame del formato seekable (default 1M, implica --seekable)
+        #[arg(long, value_parser = parse_size, value_name = "SIZE")]
+        frame_size: Option<u64>,
+
+        /// Per le directory: backup completo, con lo stato <archivio>.state per i
+        /// backup incrementali successivi
+        #[arg(long, conflicts_with = "incremental")]
+        backup: bool,
+
+        /// Per le directory: archivia solo le differenze dal backup indicato
+        /// (file nuovi o modificati ed eliminazioni) e ne scrive lo stato
+        #[arg(long, value_name = "ARCHIVIO")]
+        incremental: Option<PathBuf>,
     },
-    /// Decomprime un file con estensione .zst o .tar.zst
+    /// Decomprime un file .zst o .tar.zst (anche .gz, .lz4, .xz, .br)
     Decompress {
-        /// Il file .zst da decomprimere
+        /// Il file da decomprimere (`-` per stdin)
         #[arg(value_name = "FILE")]
         input_file: PathBuf,
 
@@ -58,9 +128,36 @@ enum Commands {
         #[arg(short, long)]
         force: bool,
 
-        /// Percorso di destinazione (file o directory)
+        /// Percorso di destinazione (file o directory, `-` per stdout)
         #[arg(short, long, value_name = "PERCORSO")]
         output: Option<PathBuf>,
+
+        /// Dizionario zstd usato in compressione
+        #[arg(long, value_name = "DIZIONARIO")]
+        dict: Option<PathBuf>,
+
+        /// Estrae solo le entry che corrispondono al glob (ripetibile, es. 'etc/**')
+        #[arg(long, value_name = "GLOB")]
+        include: Vec<String>,
+
+        /// Salta le entry che corrispondono al glob (ripetibile)
+        #[arg(long, value_name = "GLOB")]
+        exclude: Vec<String>,
+
+        /// Metadati da ripristinare: none, basic (permessi e data) o all (anche proprietario)
+        #[arg(long, default_value = "basic", value_parser = parse_metadata_restore, value_name = "METADATI")]
+        preserve: MetadataRestore,
+
+        /// Entry con percorsi assoluti, '..' o link esterni: reject (errore), skip o sanitize
+        #[arg(long, default_value = "reject", value_parser = parse_unsafe_entry_policy, value_name = "POLITICA")]
+        unsafe_paths: UnsafeEntryPolicy,
+
+        /// Decomprime in parallelo i frame dei file creati con --seekable
+        #[arg(short, long)]
+        parallel: bool,
+
+        #[command(flatten)]
+        limits: LimitArgs,
     },
     /// Comprime più file in un archivio tar.zst
     MultiCompress {
@@ -68,9 +165,9 @@ enum Commands {
         #[arg(value_name = "FILES", num_args = 1..)]
         input_files: Vec<PathBuf>,
 
-        /// Nome del file di output (default: archivio.tar.zst)
-        #[arg(short, long, default_value = "archivio.tar.zst")]
-        output: PathBuf,
+        /// Nome del file di output (default: archivio.tar.zst, o l'estensione dell'algoritmo)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
 
         /// Livello di compressione (da 1 a 21)
         #[arg(short, long, default_value_t = 3, value_parser = parse_level, value_name = "LIVELLO")]
@@ -79,6 +176,26 @@ enum Commands {
         /// Sovrascrive il file di output se esiste già
         #[arg(short, long)]
         force: bool,
+
+        /// Algoritmo di compressione (zstd, lz4, gzip, xz, brotli)
+        #[arg(short, long, default_value = "zstd", value_parser = parse_algorithm, value_name = "ALGORITMO")]
+        algoritmo: CompressionAlgorithm,
+
+        /// Salva lo SHA-256 di ogni file: sidecar (file .sha256) o embedded (nel .zst)
+        #[arg(long, value_parser = parse_digest_mode, value_name = "MODO")]
+        digest: Option<DigestMode>,
+
+        /// Divide l'archivio in volumi .001, .002, ... di questa dimensione (es. 4G)
+        #[arg(long, value_parser = parse_size, value_name = "SIZE")]
+        split_size: Option<u64>,
+
+        /// Archivio indicizzato: frame indipendenti e indice dei membri per `list` e `cat`
+        #[arg(long)]
+        seekable: bool,
+
+        /// Dimensione dei frame dell'archivio indicizzato (default 1M, implica --seekable)
+        #[arg(long, value_parser = parse_size, value_name = "SIZE")]
+        frame_size: Option<u64>,
     },
     /// Comprime tutti i file che corrispondono a un pattern (es. *.log)
     Batch {
@@ -97,15 +214,379 @@ enum Commands {
         /// Elabora i file in parallelo
         #[arg(short, long)]
         parallel: bool,
+
+        /// Dizionario zstd da usare (creato con train-dict)
+        #[arg(long, value_name = "DIZIONARIO")]
+        dict: Option<PathBuf>,
+
+        /// Strategia zstd esplicita (fast, default, greedy, lazy, lazy2, btlazy2)
+        #[arg(long, value_parser = parse_strategy, value_name = "STRATEGIA")]
+        strategy: Option<CompressionStrategy>,
+
+        /// Algoritmo di compressione (zstd, lz4, gzip, xz, brotli)
+        #[arg(short, long, default_value = "zstd", value_parser = parse_algorithm, value_name = "ALGORITMO")]
+        algoritmo: CompressionAlgorithm,
+
+        /// Salva lo SHA-256 di ogni file: sidecar (file .sha256) o embedded (nel .zst)
+        #[arg(long, value_parser = parse_digest_mode, value_name = "MODO")]
+        digest: Option<DigestMode>,
     },
-    /// Verifica l'integrità di un file .zst
+    /// Verifica l'integrità di un file .zst (gli archivi tar entry per entry)
     Verifica {
         /// Il file .zst da verificare
         #[arg(value_name = "FILE")]
         input_file: PathBuf,
+
+        /// Ricalcola lo SHA-256 dei dati e lo confronta con il manifest (embedded o .sha256)
+        #[arg(long)]
+        deep: bool,
+
+        /// Dizionario zstd usato in compressione
+        #[arg(long, value_name = "DIZIONARIO")]
+        dict: Option<PathBuf>,
+
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    /// Elenca il contenuto di un archivio tar.zst senza estrarlo
+    List {
+        /// L'archivio da elencare
+        #[arg(value_name = "ARCHIVIO")]
+        input_file: PathBuf,
+
+        /// Output in formato JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Dizionario zstd usato in compressione
+        #[arg(long, value_name = "DIZIONARIO")]
+        dict: Option<PathBuf>,
+    },
+    /// Scrive su stdout (o su file) un singolo membro di un archivio tar.zst
+    Cat {
+        /// L'archivio da cui leggere
+        #[arg(value_name = "ARCHIVIO")]
+        input_file: PathBuf,
+
+        /// Percorso del membro dentro l'archivio (es. config/app.toml)
+        #[arg(value_name = "MEMBRO")]
+        member: PathBuf,
+
+        /// Scrive su file invece che su stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+
+        /// Dizionario zstd usato in compressione
+        #[arg(long, value_name = "DIZIONARIO")]
+        dict: Option<PathBuf>,
+    },
+    /// Estrae un intervallo di bytes da un file .zst seekable senza decomprimerlo tutto
+    Range {
+        /// Il file .zst creato con --seekable
+        #[arg(value_name = "FILE")]
+        input_file: PathBuf,
+
+        /// Primo byte (dei dati decompressi) da estrarre (es. 512M)
+        #[arg(long, value_parser = parse_size, value_name = "SIZE", default_value = "0")]
+        offset: u64,
+
+        /// Quanti bytes estrarre (default: fino alla fine)
+        #[arg(long, value_parser = parse_size, value_name = "SIZE")]
+        length: Option<u64>,
+
+        /// Scrive su file invece che su stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+
+        /// Dizionario zstd usato in compressione
+        #[arg(long, value_name = "DIZIONARIO")]
+        dict: Option<PathBuf>,
+    },
+    /// Ripristina un backup incrementale: applica in ordine la catena di archivi
+    /// dal backup completo fino a quello indicato
+    Restore {
+        /// L'ultimo archivio della catena da applicare
+        #[arg(value_name = "ARCHIVIO")]
+        input_file: PathBuf,
+
+        /// Sovrascrive la directory di destinazione se esiste già
+        #[arg(short, long)]
+        force: bool,
+
+        /// Directory di destinazione
+        #[arg(short, long, value_name = "PERCORSO")]
+        output: Option<PathBuf>,
+
+        /// Dizionario zstd usato in compressione
+        #[arg(long, value_name = "DIZIONARIO")]
+        dict: Option<PathBuf>,
+
+        /// Ripristina solo le entry che corrispondono al glob (ripetibile)
+        #[arg(long, value_name = "GLOB")]
+        include: Vec<String>,
+
+        /// Salta le entry che corrispondono al glob (ripetibile)
+        #[arg(long, value_name = "GLOB")]
+        exclude: Vec<String>,
+
+        /// Metadati da ripristinare: none, basic (permessi e data) o all (anche proprietario)
+        #[arg(long, default_value = "basic", value_parser = parse_metadata_restore, value_name = "METADATI")]
+        preserve: MetadataRestore,
+
+        /// Entry con percorsi assoluti, '..' o link esterni: reject (errore), skip o sanitize
+        #[arg(long, default_value = "reject", value_parser = parse_unsafe_entry_policy, value_name = "POLITICA")]
+        unsafe_paths: UnsafeEntryPolicy,
+
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    /// Addestra un dizionario zstd a partire da file di esempio
+    TrainDict {
+        /// I file di esempio (es. molti JSON o log simili)
+        #[arg(value_name = "FILES", num_args = 1..)]
+        sample_files: Vec<PathBuf>,
+
+        /// File di output del dizionario
+        #[arg(short, long, default_value = "custom.dict")]
+        output: PathBuf,
+
+        /// Dimensione massima del dizionario in bytes
+        #[arg(short, long, default_value_t = DEFAULT_DICT_SIZE, value_name = "BYTES")]
+        size: usize,
+
+        /// Sovrascrive il dizionario se esiste già
+        #[arg(short, long)]
+        force: bool,
     },
 }
 
+/// Limiti contro le decompression bomb (es. per file caricati da utenti)
+#[derive(Args, Debug)]
+struct LimitArgs {
+    /// Bytes decompressi massimi (es. 10G)
+    #[arg(long, value_parser = parse_size, value_name = "DIMENSIONE")]
+    max_output: Option<u64>,
+
+    /// Rapporto massimo tra dati decompressi e compressi (es. 100 per 100:1)
+    #[arg(long, value_name = "RAPPORTO")]
+    max_ratio: Option<u64>,
+
+    /// Numero massimo di entry in un archivio
+    #[arg(long, value_name = "NUMERO")]
+    max_entries: Option<u64>,
+
+    /// Dimensione massima di un singolo file estratto (es. 1G)
+    #[arg(long, value_parser = parse_size, value_name = "DIMENSIONE")]
+    max_entry_size: Option<u64>,
+}
+
+impl LimitArgs {
+    fn to_limits(&self) -> DecompressionLimits {
+        DecompressionLimits {
+            max_output_bytes: self.max_output,
+            max_ratio: self.max_ratio,
+            max_entries: self.max_entries,
+            max_entry_size: self.max_entry_size,
+        }
+    }
+}
+
+/// Carica il dizionario passato con --dict, se presente
+fn load_dictionary(path: Option<&Path>) -> Result<Option<CompressionDictionary>> {
+    match path {
+        Some(p) => {
+            let dict = CompressionDictionary::load(p)?;
+            println!(
+                "📖 Dizionario: {:?} ({})",
+                p,
+                format_size(dict.len() as u64)
+            );
+            Ok(Some(dict))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Carica il dizionario senza messaggi su stdout quando stdout è lo stream di dati
+fn load_dictionary_for(
+    path: Option<&Path>,
+    streaming: bool,
+) -> Result<Option<CompressionDictionary>> {
+    if streaming {
+        path.map(CompressionDictionary::load).transpose()
+    } else {
+        load_dictionary(path)
+    }
+}
+
+/// `-` indica stdin (come input) o stdout (come output)
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Apre l'input di uno stream: stdin per `-`, i volumi ricomposti per un `.001`,
+/// altrimenti il file
+fn open_stream_input(input_path: &Path) -> Result<Box<dyn std::io::Read>> {
+    if is_stdio(input_path) {
+        Ok(Box::new(std::io::stdin().lock()))
+    } else if volume::is_first_volume(input_path) {
+        Ok(Box::new(VolumeSet::open(input_path)?.reader()?))
+    } else {
+        Ok(Box::new(std::fs::File::open(input_path)?))
+    }
+}
+
+/// Output di uno stream: stdout, oppure un file scritto in modo atomico che
+/// compare sul percorso finale solo con [`StreamOutput::commit`]
+enum StreamOutput {
+    Stdout(std::io::BufWriter<std::io::StdoutLock<'static>>),
+    File(std::io::BufWriter<AtomicFile>),
+}
+
+impl StreamOutput {
+    /// Apre l'output: stdout se assente o `-`, altrimenti il file
+    fn open(output: Option<&Path>, force: bool) -> Result<Self> {
+        match output {
+            Some(path) if !is_stdio(path) => {
+                if path.exists() && !force {
+                    return Err(Error::AlreadyExists(path.to_path_buf()));
+                }
+                Ok(StreamOutput::File(std::io::BufWriter::new(
+                    AtomicFile::create(path)?,
+                )))
+            }
+            _ => Ok(StreamOutput::Stdout(std::io::BufWriter::new(
+                std::io::stdout().lock(),
+            ))),
+        }
+    }
+
+    /// Completa l'output; senza commit (errore o Ctrl-C) il file non viene creato
+    fn commit(self) -> Result<()> {
+        match self {
+            StreamOutput::Stdout(mut stdout) => Ok(stdout.flush()?),
+            StreamOutput::File(file) => {
+                file.into_inner().map_err(|e| e.into_error())?.commit()?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Write for StreamOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            StreamOutput::Stdout(stdout) => stdout.write(buf),
+            StreamOutput::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            StreamOutput::Stdout(stdout) => stdout.flush(),
+            StreamOutput::File(file) => file.flush(),
+        }
+    }
+}
+
+/// Scrive su stdout o su un file `-o` (sovrascritto) l'output prodotto da
+/// `write`; il file compare solo se `write` termina senza errori
+fn write_output<T>(
+    output: Option<&Path>,
+    write: impl FnOnce(&mut StreamOutput) -> Result<T>,
+) -> Result<()> {
+    let mut writer = StreamOutput::open(output, true)?;
+    let result = write(&mut writer).and_then(|_| writer.commit());
+    // `cat ... | head` chiude la pipe prima della fine: non è un errore
+    match result {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        other => other,
+    }
+}
+
+/// Comprime da/verso stdin/stdout; i messaggi vanno su stderr
+fn compress_stream_cli(
+    input_path: &Path,
+    output: Option<&Path>,
+    options: CompressOptions,
+) -> Result<()> {
+    let to_stdout = output.is_none_or(is_stdio);
+    if to_stdout && std::io::stdout().is_terminal() {
+        return Err(Error::InvalidInput(
+            "Dati compressi non scritti sul terminale: usa -o FILE o una pipe".to_string(),
+        ));
+    }
+
+    let reader = open_stream_input(input_path)?;
+    let mut writer = StreamOutput::open(output, options.force)?;
+    let result = compress_stream(reader, &mut writer, &options)?;
+    writer.commit()?;
+
+    eprintln!(
+        "✅ {} -> {} ({})",
+        format_size(result.input_size),
+        format_size(result.output_size),
+        format_ratio(result.input_size, result.output_size)
+    );
+    Ok(())
+}
+
+/// Decomprime da/verso stdin/stdout; i messaggi vanno su stderr
+fn decompress_stream_cli(
+    input_path: &Path,
+    output: Option<&Path>,
+    options: DecompressOptions,
+) -> Result<()> {
+    let reader = open_stream_input(input_path)?;
+    let mut writer = StreamOutput::open(output, options.force)?;
+    let result = decompress_stream(reader, &mut writer, &options)
+        .and_then(|result| writer.commit().map(|_| result));
+
+    match result {
+        Ok(result) => {
+            eprintln!(
+                "✅ {} -> {}",
+                format_size(result.input_size),
+                format_size(result.output_size)
+            );
+            Ok(())
+        }
+        // `decompress ... | head` chiude la pipe prima della fine: non è un errore
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Stampa le opzioni di compressione rilevanti per l'utente
+fn print_compress_options(options: &CompressOptions) {
+    if let Some(out) = &options.output_path {
+        println!("Destinazione: {:?}", out);
+    }
+    println!(
+        "Livello di compressione: {}{}",
+        options.level,
+        if options.parallel {
+            " (modalità parallela)"
+        } else {
+            ""
+        }
+    );
+    if options.algorithm != CompressionAlgorithm::Zstd {
+        println!("Algoritmo: {}", options.algorithm);
+    }
+    if let Some(strategy) = options.strategy {
+        println!("Strategia zstd: {:?}", strategy);
+    }
+    if options.smart_optimize {
+        println!("🧠 Ottimizzazioni intelligenti: ATTIVE");
+    }
+    match &options.backup {
+        Some(BackupMode::Full) => println!("Backup: completo"),
+        Some(BackupMode::Incremental(base)) => println!("Backup: incrementale da {:?}", base),
+        None => {}
+    }
+}
+
 /// Crea una progress bar con stile personalizzato
 fn create_progress_bar(total: u64, message: &str) -> ProgressBar {
     let pb = ProgressBar::new(total);
@@ -146,19 +627,63 @@ fn create_file_progress_bar(total: u64, message: &str) -> ProgressBar {
     pb
 }
 
+/// Avanza una progress bar in bytes con gli eventi `Bytes`
+fn track_bytes(pb: ProgressBar) -> impl Fn(&ProgressEvent) + Send + Sync + 'static {
+    move |event| match event {
+        ProgressEvent::Started {
+            total_bytes: Some(total),
+            ..
+        } => pb.set_length(*total),
+        ProgressEvent::Bytes { done, .. } => pb.set_position(*done),
+        _ => {}
+    }
+}
+
+/// Come [`track_bytes`] sui bytes compressi letti, mostrando nel messaggio
+/// i bytes decompressi (e il totale, se l'header lo riporta)
+fn track_decompressed(
+    pb: ProgressBar,
+    message: &'static str,
+) -> impl Fn(&ProgressEvent) + Send + Sync + 'static {
+    let track = track_bytes(pb.clone());
+    move |event| {
+        track(event);
+        if let ProgressEvent::Output { done, total } = event {
+            let total = total
+                .map(|t| format!(" di {}", format_size(t)))
+                .unwrap_or_default();
+            pb.set_message(format!("{} {}{}", message, format_size(*done), total));
+        }
+    }
+}
+
+/// Avanza una progress bar a file con gli eventi `FileFinished`
+fn track_files(pb: ProgressBar) -> impl Fn(&ProgressEvent) + Send + Sync + 'static {
+    move |event| match event {
+        ProgressEvent::Started {
+            total_files: Some(total),
+            ..
+        } => pb.set_length(*total),
+        ProgressEvent::FileFinished { .. } => pb.inc(1),
+        _ => {}
+    }
+}
+
 fn main() {
     // Setup handler SIGINT/SIGTERM per cleanup pulito
-    let running = Arc::new(AtomicU64::new(1));
-    let r = running.clone();
+    let cancel = cancel_flag();
 
     ctrlc::set_handler(move || {
-        if r.load(Ordering::Relaxed) == 1 {
+        if !cancel.swap(true, Ordering::Relaxed) {
             eprintln!("\n\n⚠️  Interruzione rilevata. Pulizia in corso...");
-            r.store(0, Ordering::Relaxed);
-            // Dare tempo per terminare operazioni in corso
-            std::thread::sleep(std::time::Duration::from_millis(500));
-            std::process::exit(130); // 130 = 128 + SIGINT (2)
+            // Le operazioni della libreria si fermano da sole rimuovendo gli output
+            // parziali; se non terminano in tempo (o al secondo Ctrl-C) si esce comunque
+            std::thread::sleep(std::time::Duration::from_secs(2));
         }
+        // exit non esegue i distruttori: i file temporanei vanno rimossi qui,
+        // gli output già completati non vengono toccati
+        cleanup_partial_outputs();
+        std::process::exit(130); // 130 = 128 + SIGINT (2)
     })
     .expect("Errore impostando handler Ctrl-C");
 
@@ -172,101 +697,470 @@ fn main() {
             parallel,
             no_smart,
             output,
+            dict,
+            strategy,
+            algoritmo,
+            include,
+            exclude,
+            gitignore,
+            follow_symlinks,
+            digest,
+            no_checksum,
+            split_size,
+            read_ahead,
+            seekable,
+            frame_size,
+            backup,
+            incremental,
         } => {
-            if input_file.is_dir() {
-                compress_directory_with_progress(
-                    input_file.as_path(),
-                    *livello,
-                    *force,
-                    !no_smart,
-                    output.as_deref(),
-                )
-            } else {
-                compress_file_with_progress(
-                    input_file.as_path(),
-                    *livello,
-                    *force,
-                    *parallel,
-                    !no_smart,
-                    output.as_deref(),
-                )
-            }
+            let streaming = is_stdio(input_file) || output.as_deref().is_some_and(is_stdio);
+            load_dictionary_for(dict.as_deref(), streaming).and_then(|dictionary| {
+                let mut options = CompressOptions::new(*livello)
+                    .with_cancel_flag(cancel_flag())
+                    .with_force(*force)
+                    .with_parallel(*parallel)
+                    .with_smart_optimize(!no_smart)
+                    .with_algorithm(*algoritmo)
+                    .with_filter(PathFilter::from_patterns(include, exclude)?)
+                    .with_ignore_files(*gitignore)
+                    .with_follow_symlinks(*follow_symlinks)
+                    .with_content_checksum(!no_checksum);
+                if let Some(out) = output {
+                    options = options.with_output_path(out);
+                }
+                if let Some(mode) = digest {
+                    if streaming {
+                        return Err(Error::InvalidInput(
+                            "--digest non è disponibile con stdin/stdout".to_string(),
+                        ));
+                    }
+                    options = options.with_digest(*mode);
+                }
+                if let Some(size) = split_size {
+                    if streaming {
+                        return Err(Error::InvalidInput(
+                            "--split-size non è disponibile con stdin/stdout".to_string(),
+                        ));
+                    }
+                    options = options.with_split_size(*size);
+                }
+                if let Some(size) = seekable_frame_size(*seekable, *frame_size) {
+                    options = options.with_seekable(size);
+                }
+                if let Some(bytes) = read_ahead {
+                    options = options.with_read_ahead(*bytes);
+                }
+                if *backup || incremental.is_some() {
+                    if streaming || !input_file.is_dir() {
+                        return Err(Error::InvalidInput(
+                            "--backup e --incremental sono disponibili solo per le directory"
+                                .to_string(),
+                        ));
+                    }
+                    options = options.with_backup(match incremental {
+                        Some(base) => BackupMode::Incremental(base.clone()),
+                        None => BackupMode::Full,
+                    });
+                }
+                if let Some(dict) = dictionary {
+                    options = options.with_dictionary(dict);
+                }
+                if let Some(strategy) = strategy {
+                    options = options.with_strategy(*strategy);
+                }
+
+                if streaming {
+                    compress_stream_cli(input_file.as_path(), output.as_deref(), options)
+                } else if input_file.is_dir() {
+                    compress_directory_with_progress(input_file.as_path(), options)
+                } else {
+                    compress_file_with_progress(input_file.as_path(), options)
+                }
+            })
         }
         Commands::Decompress {
             input_file,
             force,
             output,
-        } => decompress_file_with_progress(input_file.as_path(), *force, output.as_deref()),
+            dict,
+            include,
+            exclude,
+            preserve,
+            unsafe_paths,
+            parallel,
+            limits,
+        } => {
+            let streaming = is_stdio(input_file) || output.as_deref().is_some_and(is_stdio);
+            load_dictionary_for(dict.as_deref(), streaming).and_then(|dictionary| {
+                let filter = PathFilter::from_patterns(include, exclude)?;
+                let mut options = DecompressOptions::new()
+                    .with_cancel_flag(cancel_flag())
+                    .with_force(*force)
+                    .with_filter(filter)
+                    .with_restore_metadata(*preserve)
+                    .with_unsafe_entries(*unsafe_paths)
+                    .with_parallel(*parallel)
+                    .with_limits(limits.to_limits());
+                if let Some(out) = output {
+                    options = options.with_output_path(out);
+                }
+                if let Some(dict) = dictionary {
+                    options = options.with_dictionary(dict);
+                }
+                if streaming {
+                    decompress_stream_cli(input_file.as_path(), output.as_deref(), options)
+                } else {
+                    decompress_file_with_progress(input_file.as_path(), options)
+                }
+            })
+        }
         Commands::MultiCompress {
             input_files,
             output,
             livello,
             force,
-        } => compress_multiple_with_progress(input_files, output.as_path(), *livello, *force),
+            algoritmo,
+            digest,
+            split_size,
+            seekable,
+            frame_size,
+        } => {
+            let output_path = output.clone().unwrap_or_else(|| {
+                PathBuf::from(format!("archivio.tar.{}", algoritmo.extension()))
+            });
+            let mut options = CompressOptions::new(*livello)
+                .with_cancel_flag(cancel_flag())
+                .with_force(*force)
+                .with_algorithm(*algoritmo);
+            if let Some(mode) = digest {
+                options = options.with_digest(*mode);
+            }
+            if let Some(size) = split_size {
+                options = options.with_split_size(*size);
+            }
+            if let Some(size) = seekable_frame_size(*seekable, *frame_size) {
+                options = options.with_seekable(size);
+            }
+            compress_multiple_with_progress(input_files, output_path.as_path(), options)
+        }
         Commands::Batch {
             pattern,
             livello,
             force,
             parallel,
-        } => batch_compress(pattern, *livello, *force, *parallel),
-        Commands::Verifica { input_file } => verify_with_progress(input_file.as_path()),
+            dict,
+            strategy,
+            algoritmo,
+            digest,
+        } => load_dictionary(dict.as_deref()).and_then(|dictionary| {
+            let mut options = CompressOptions::new(*livello)
+                .with_cancel_flag(cancel_flag())
+                .with_force(*force)
+                .with_algorithm(*algoritmo);
+            if let Some(dict) = dictionary {
+                options = options.with_dictionary(dict);
+            }
+            if let Some(mode) = digest {
+                options = options.with_digest(*mode);
+            }
+            if let Some(strategy) = strategy {
+                options = options.with_strategy(*strategy);
+            }
+            batch_compress(pattern, *parallel, options)
+        }),
+        Commands::Verifica {
+            input_file,
+            deep,
+            dict,
+            limits,
+        } => load_dictionary(dict.as_deref()).and_then(|dictionary| {
+            let mut options = DecompressOptions::new()
+                .with_cancel_flag(cancel_flag())
+                .with_deep_verify(*deep)
+                .with_limits(limits.to_limits());
+            if let Some(dict) = dictionary {
+                options = options.with_dictionary(dict);
+            }
+            if is_tar_archive(input_file, &options) {
+                verify_archive_with_progress(input_file.as_path(), options)
+            } else {
+                verify_with_progress(input_file.as_path(), options)
+            }
+        }),
+        Commands::List {
+            input_file,
+            json,
+            dict,
+        } => list_archive_contents(input_file.as_path(), *json, dict.as_deref()),
+        Commands::Cat {
+            input_file,
+            member,
+            output,
+            dict,
+        } => cat_entry(
+            input_file.as_path(),
+            member.as_path(),
+            output.as_deref(),
+            dict.as_deref(),
+        ),
+        Commands::Range {
+            input_file,
+            offset,
+            length,
+            output,
+            dict,
+        } => range_extract(
+            input_file.as_path(),
+            *offset,
+            *length,
+            output.as_deref(),
+            dict.as_deref(),
+        ),
+        Commands::Restore {
+            input_file,
+            force,
+            output,
+            dict,
+            include,
+            exclude,
+            preserve,
+            unsafe_paths,
+            limits,
+        } => load_dictionary(dict.as_deref()).and_then(|dictionary| {
+            let mut options = DecompressOptions::new()
+                .with_cancel_flag(cancel_flag())
+                .with_force(*force)
+                .with_filter(PathFilter::from_patterns(include, exclude)?)
+                .with_restore_metadata(*preserve)
+                .with_unsafe_entries(*unsafe_paths)
+                .with_limits(limits.to_limits());
+            if let Some(out) = output {
+                options = options.with_output_path(out);
+            }
+            if let Some(dict) = dictionary {
+                options = options.with_dictionary(dict);
+            }
+            restore_with_progress(input_file.as_path(), options)
+        }),
+        Commands::TrainDict {
+            sample_files,
+            output,
+            size,
+            force,
+        } => train_dictionary(sample_files, output.as_path(), *size, *force),
     };
 
     if let Err(e) = result {
         eprintln!("Errore: {}", e);
-        process::exit(1);
+        process::exit(exit_code(&e));
     }
 }
 
-/// Comprime un file con progress bar
-fn compress_file_with_progress(
-    input_path: &Path,
-    level: i32,
-    force: bool,
-    parallel: bool,
-    smart_optimize: bool,
-    output: Option<&Path>,
-) -> std::io::Result<()> {
-    if !input_path.exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("Il file di input {:?} non esiste", input_path),
-        ));
+/// Codice di uscita per ogni tipo di errore (documentati nel README)
+fn exit_code(e: &Error) -> i32 {
+    match e {
+        Error::InvalidInput(_) | Error::UnsupportedFormat(_) => 2,
+        Error::NotFound(_) => 3,
+        Error::AlreadyExists(_) => 4,
+        Error::Corrupt(_) => 5,
+        Error::UnsafePath(_) => 6,
+        Error::LimitExceeded(_) => 7,
+        Error::Skipped { .. } => 8,
+        // Operazione annullata con Ctrl-C: stesso codice dell'uscita forzata
+        Error::Cancelled => 130,
+        _ => 1,
     }
+}
 
-    println!("File di input: {:?}", input_path);
-    if let Some(out) = output {
-        println!("Destinazione: {:?}", out);
+/// Flag di annullamento condiviso tra l'handler di Ctrl-C e le operazioni in corso
+static CANCEL_FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+fn cancel_flag() -> Arc<AtomicBool> {
+    Arc::clone(CANCEL_FLAG.get_or_init(Default::default))
+}
+
+/// Elenca il contenuto di un archivio come tabella o JSON
+fn list_archive_contents(input_path: &Path, json: bool, dict: Option<&Path>) -> Result<()> {
+    // Niente messaggi su stdout in modalità JSON: l'output deve restare parsabile
+    let mut options = DecompressOptions::new().with_cancel_flag(cancel_flag());
+    if let Some(dict) = dict.map(CompressionDictionary::load).transpose()? {
+        options = options.with_dictionary(dict);
+    }
+
+    let entries = list_archive(input_path, &options)?;
+    let total_size: u64 = entries.iter().map(|e| e.size).sum();
+    let file_count = entries
+        .iter()
+        .filter(|e| e.entry_type == ArchiveEntryType::File)
+        .count();
+    let dir_count = entries
+        .iter()
+        .filter(|e| e.entry_type == ArchiveEntryType::Directory)
+        .count();
+
+    if json {
+        let items: Vec<serde_json::Value> = entries
+            .iter()
+            .map(|e| {
+                serde_json::json!({
+                    "path": e.path.to_string_lossy(),
+                    "type": e.entry_type.name(),
+                    "size": e.size,
+                    "mode": e.mode,
+                    "mtime": e.mtime,
+                    "link_target": e.link_target.as_ref().map(|p| p.to_string_lossy()),
+                })
+            })
+            .collect();
+        let document = serde_json::json!({
+            "archive": input_path.to_string_lossy(),
+            "entries": items,
+            "total_entries": entries.len(),
+            "total_files": file_count,
+            "total_directories": dir_count,
+            "total_size": total_size,
+        });
+        let text = serde_json::to_string_pretty(&document).map_err(std::io::Error::from)?;
+        println!("{}", text);
+        return Ok(());
     }
+
     println!(
-        "Livello di compressione: {}{}",
-        level,
-        if parallel {
-            " (modalità parallela)"
-        } else {
-            ""
+        "{:<10} {:>12} {:<16} Percorso",
+        "Permessi", "Dimensione", "Modificato"
+    );
+    for entry in &entries {
+        let mut path = entry.path.display().to_string();
+        if let Some(target) = &entry.link_target {
+            path.push_str(&format!(" -> {}", target.display()));
         }
+        println!(
+            "{:<10} {:>12} {:<16} {}",
+            format_mode(entry),
+            format_size(entry.size),
+            format_mtime(entry.mtime),
+            path
+        );
+    }
+
+    println!(
+        "\n{} entry ({} file, {} directory) - {} totali",
+        entries.len(),
+        file_count,
+        dir_count,
+        format_size(total_size)
     );
-    if smart_optimize {
-        println!("🧠 Ottimizzazioni intelligenti: ATTIVE");
+
+    Ok(())
+}
+
+/// Scrive un membro dell'archivio su stdout o su file
+fn cat_entry(
+    input_path: &Path,
+    member: &Path,
+    output: Option<&Path>,
+    dict: Option<&Path>,
+) -> Result<()> {
+    let mut options = DecompressOptions::new().with_cancel_flag(cancel_flag());
+    if let Some(dict) = dict.map(CompressionDictionary::load).transpose()? {
+        options = options.with_dictionary(dict);
     }
 
-    let input_size = std::fs::metadata(input_path)?.len();
-    let pb = create_progress_bar(input_size, "Compressione in corso...");
-    let pb_clone = pb.clone();
+    write_output(output, |writer| {
+        extract_entry(input_path, member, writer, &options)
+    })
+}
 
-    let mut options = CompressOptions::new(level)
-        .with_force(force)
-        .with_parallel(parallel)
-        .with_smart_optimize(smart_optimize)
-        .with_progress(move |bytes| {
-            pb_clone.set_position(bytes);
-        });
+/// Dimensione dei frame seekable richiesta: `--frame-size` implica `--seekable`
+fn seekable_frame_size(seekable: bool, frame_size: Option<u64>) -> Option<u32> {
+    match frame_size {
+        // Un valore oltre u32 viene rifiutato dal writer come fuori intervallo
+        Some(size) => Some(u32::try_from(size).unwrap_or(u32::MAX)),
+        None if seekable => Some(seekable::DEFAULT_FRAME_SIZE),
+        None => None,
+    }
+}
 
-    if let Some(out) = output {
-        options = options.with_output_path(out);
+/// Scrive su stdout o su file un intervallo dei dati di un file seekable
+fn range_extract(
+    input_path: &Path,
+    offset: u64,
+    length: Option<u64>,
+    output: Option<&Path>,
+    dict: Option<&Path>,
+) -> Result<()> {
+    let mut options = DecompressOptions::new().with_cancel_flag(cancel_flag());
+    if let Some(dict) = dict.map(CompressionDictionary::load).transpose()? {
+        options = options.with_dictionary(dict);
     }
 
+    write_output(output, |writer| {
+        extract_range(input_path, offset, length, writer, &options)
+    })
+}
+
+/// Permessi in stile `ls -l` (es. -rw-r--r--)
+fn format_mode(entry: &ArchiveEntry) -> String {
+    let kind = match entry.entry_type {
+        ArchiveEntryType::Directory => 'd',
+        ArchiveEntryType::Symlink => 'l',
+        ArchiveEntryType::HardLink => 'h',
+        ArchiveEntryType::File => '-',
+        ArchiveEntryType::Other => '?',
+    };
+
+    let mut text = String::with_capacity(10);
+    text.push(kind);
+    for shift in [6, 3, 0] {
+        let bits = (entry.mode >> shift) & 0o7;
+        text.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        text.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        text.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    text
+}
+
+/// Data UTC nel formato AAAA-MM-GG HH:MM a partire dai secondi Unix
+fn format_mtime(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let minutes_of_day = (secs % 86_400) / 60;
+
+    // Conversione giorni -> data civile (algoritmo di Howard Hinnant)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        minutes_of_day / 60,
+        minutes_of_day % 60
+    )
+}
+
+/// Comprime un file con progress bar
+fn compress_file_with_progress(input_path: &Path, options: CompressOptions) -> Result<()> {
+    if !input_path.exists() {
+        return Err(Error::NotFound(format!(
+            "Il file di input {:?} non esiste",
+            input_path
+        )));
+    }
+
+    println!("File di input: {:?}", input_path);
+    print_compress_options(&options);
+
+    let pb = create_progress_bar(0, "Compressione in corso...");
+    let options = options.with_progress_events(track_bytes(pb.clone()));
+
     let result = compress_file(input_path, &options)?;
 
     pb.finish_with_message("Compressione completata!");
@@ -283,45 +1177,16 @@ fn compress_file_with_progress(
 }
 
 /// Comprime una directory con progress bar
-fn compress_directory_with_progress(
-    dir_path: &Path,
-    level: i32,
-    force: bool,
-    smart_optimize: bool,
-    output: Option<&Path>,
-) -> std::io::Result<()> {
+fn compress_directory_with_progress(dir_path: &Path, options: CompressOptions) -> Result<()> {
     println!("Directory di input: {:?}", dir_path);
-    if let Some(out) = output {
-        println!("Destinazione: {:?}", out);
-    }
-    println!("Livello di compressione: {}", level);
-    if smart_optimize {
-        println!("🧠 Ottimizzazioni intelligenti: ATTIVE");
-    }
-
-    let spinner = create_spinner("Analisi directory...");
-    let file_count = count_files_in_dir(dir_path)?;
-    spinner.finish_and_clear();
+    print_compress_options(&options);
 
-    let pb = create_file_progress_bar(file_count, "Compressione directory...");
-    let pb_clone = pb.clone();
-    let processed_files = Arc::new(AtomicU64::new(0));
-    let processed_clone = Arc::clone(&processed_files);
-
-    let mut options = CompressOptions::new(level)
-        .with_force(force)
-        .with_smart_optimize(smart_optimize)
-        .with_progress(move |_bytes| {
-            // Incrementa il conteggio dei file
-            let files = processed_clone.fetch_add(1, Ordering::Relaxed);
-            pb_clone.set_position(files + 1);
-        });
-
-    if let Some(out) = output {
-        options = options.with_output_path(out);
-    }
+    // La lunghezza arriva con l'evento Started, dopo l'analisi della directory
+    let pb = create_file_progress_bar(0, "Compressione directory...");
+    let options = options.with_progress_events(track_files(pb.clone()));
 
     let result = compress_directory(dir_path, &options)?;
+    let file_count = pb.length().unwrap_or(0);
 
     pb.finish_with_message("Archivio creato!");
 
@@ -336,28 +1201,47 @@ fn compress_directory_with_progress(
     Ok(())
 }
 
+/// Ripristina una catena di backup con progress bar
+fn restore_with_progress(archive_path: &Path, options: DecompressOptions) -> Result<()> {
+    let chain = backup_chain(archive_path)?;
+    println!("Archivi da applicare: {}", chain.len());
+    for (archive, _) in &chain {
+        println!("  {:?}", archive);
+    }
+    if let Some(out) = &options.output_path {
+        println!("Destinazione: {:?}", out);
+    }
+
+    // Ogni archivio della catena riparte da zero sulla barra
+    let pb = create_progress_bar(0, "Ripristino backup...");
+    let options = options.with_progress_events(track_bytes(pb.clone()));
+
+    let result = restore_backup(archive_path, &options)?;
+    pb.finish_with_message("Ripristino completato!");
+
+    println!("\n✅ Ripristino completato con successo!");
+    println!(
+        "Archivi applicati: {} - Dati estratti: {} - Elementi eliminati: {}",
+        result.archives.len(),
+        format_size(result.output_size),
+        result.deleted
+    );
+
+    Ok(())
+}
+
 /// Comprime più file con progress bar
 fn compress_multiple_with_progress(
     input_files: &[PathBuf],
     output_path: &Path,
-    level: i32,
-    force: bool,
-) -> std::io::Result<()> {
+    options: CompressOptions,
+) -> Result<()> {
     println!("File da comprimere: {} file", input_files.len());
     println!("File di output: {:?}", output_path);
-    println!("Livello di compressione: {}", level);
+    print_compress_options(&options);
 
     let pb = create_file_progress_bar(input_files.len() as u64, "Compressione multi-file...");
-    let pb_clone = pb.clone();
-    let processed = Arc::new(AtomicU64::new(0));
-    let processed_clone = Arc::clone(&processed);
-
-    let options = CompressOptions::new(level)
-        .with_force(force)
-        .with_progress(move |_bytes| {
-            let count = processed_clone.fetch_add(1, Ordering::Relaxed);
-            pb_clone.set_position(count + 1);
-        });
+    let options = options.with_progress_events(track_files(pb.clone()));
 
     let result = compress_multiple_files(input_files, output_path, &options)?;
 
@@ -375,45 +1259,43 @@ fn compress_multiple_with_progress(
 }
 
 /// Decomprime un file con progress bar
-fn decompress_file_with_progress(
-    input_path: &Path,
-    force: bool,
-    output: Option<&Path>,
-) -> std::io::Result<()> {
+fn decompress_file_with_progress(input_path: &Path, options: DecompressOptions) -> Result<()> {
     if !input_path.exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("Il file di input {:?} non esiste", input_path),
-        ));
+        return Err(Error::NotFound(format!(
+            "Il file di input {:?} non esiste",
+            input_path
+        )));
     }
 
-    let is_tar = input_path.to_string_lossy().ends_with(".tar.zst");
+    let is_tar = detect_format(input_path, options.dictionary.as_ref())?.is_tar;
 
     println!("File di input: {:?}", input_path);
-    if let Some(out) = output {
+    if let Some(out) = &options.output_path {
         println!("Destinazione: {:?}", out);
     }
+    if !is_tar && !options.filter.is_empty() {
+        println!("⚠️  --include/--exclude ignorati: il file non è un archivio tar");
+    }
 
     if is_tar {
-        let spinner = create_spinner("Estrazione archivio tar.zst...");
-        let spinner_clone = spinner.clone();
+        // Barra sui bytes compressi letti, file estratti nel messaggio
+        let pb = create_progress_bar(0, "Estrazione archivio tar...");
+        let track = track_bytes(pb.clone());
+        let pb_clone = pb.clone();
         let file_count = Arc::new(AtomicU64::new(0));
         let file_count_clone = Arc::clone(&file_count);
 
-        let mut options = DecompressOptions::new()
-            .with_force(force)
-            .with_progress(move |files| {
-                file_count_clone.store(files, Ordering::Relaxed);
-                spinner_clone.set_message(format!("Estratti {} file...", files));
-            });
-
-        if let Some(out) = output {
-            options = options.with_output_path(out);
-        }
+        let options = options.with_progress_events(move |event| {
+            track(event);
+            if let ProgressEvent::FileFinished { .. } = event {
+                let files = file_count_clone.fetch_add(1, Ordering::Relaxed) + 1;
+                pb_clone.set_message(format!("Estratti {} file...", files));
+            }
+        });
 
         let result = decompress_tar_zst(input_path, &options)?;
         let extracted = file_count.load(Ordering::Relaxed);
-        spinner.finish_with_message(format!("Estrazione completata: {} file", extracted));
+        pb.finish_with_message(format!("Estrazione completata: {} file", extracted));
 
         println!("\n✅ Estrazione archivio completata con successo!");
         println!(
@@ -422,19 +1304,8 @@ fn decompress_file_with_progress(
             extracted
         );
     } else {
-        let input_size = std::fs::metadata(input_path)?.len();
-        let pb = create_progress_bar(input_size, "Decompressione in corso...");
-        let pb_clone = pb.clone();
-
-        let mut options = DecompressOptions::new()
-            .with_force(force)
-            .with_progress(move |bytes| {
-                pb_clone.set_position(bytes);
-            });
-
-        if let Some(out) = output {
-            options = options.with_output_path(out);
-        }
+        let pb = create_progress_bar(0, "Decompressione in corso...");
+        let options = options.with_progress_events(track_decompressed(pb.clone(), "Decompressi"));
 
         let result = decompress_single_file(input_path, &options)?;
 
@@ -453,22 +1324,22 @@ fn decompress_file_with_progress(
 }
 
 /// Comprime tutti i file che corrispondono a un pattern glob
-fn batch_compress(pattern: &str, level: i32, force: bool, parallel: bool) -> std::io::Result<()> {
+fn batch_compress(pattern: &str, parallel: bool, options: CompressOptions) -> Result<()> {
     let files: Vec<PathBuf> = glob(pattern)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?
+        .map_err(|e| Error::InvalidInput(e.to_string()))?
         .filter_map(|entry| entry.ok())
         .filter(|path| path.is_file())
         .collect();
 
     if files.is_empty() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("Nessun file trovato con il pattern '{}'", pattern),
-        ));
+        return Err(Error::NotFound(format!(
+            "Nessun file trovato con il pattern '{}'",
+            pattern
+        )));
     }
 
     println!("Trovati {} file con il pattern '{}'", files.len(), pattern);
-    println!("Livello di compressione: {}", level);
+    print_compress_options(&options);
     println!(
         "Modalità: {}",
         if parallel { "parallela" } else { "sequenziale" }
@@ -482,6 +1353,7 @@ fn batch_compress(pattern: &str, level: i32, force: bool, parallel: bool) -> std
 
     if parallel {
         let pb_ref = &pb;
+        let options_ref = &options;
         let success_ref = &success_count;
         let error_ref = &error_count;
 
@@ -493,10 +1365,12 @@ fn batch_compress(pattern: &str, level: i32, force: bool, parallel: bool) -> std
             .unwrap()
             .install(|| {
                 files.par_iter().for_each(|file| {
-                    match compress_file_simple(file, level, force) {
+                    match compress_file(file, options_ref) {
                         Ok(_) => {
                             success_ref.fetch_add(1, Ordering::Relaxed);
                         }
+                        // Dopo Ctrl-C i file rimanenti vengono saltati in silenzio
+                        Err(Error::Cancelled) => {}
                         Err(e) => {
                             error_ref.fetch_add(1, Ordering::Relaxed);
                             eprintln!("Errore comprimendo {:?}: {}", file, e);
@@ -507,10 +1381,11 @@ fn batch_compress(pattern: &str, level: i32, force: bool, parallel: bool) -> std
             });
     } else {
         for file in &files {
-            match compress_file_simple(file, level, force) {
+            match compress_file(file, &options) {
                 Ok(_) => {
                     success_count.fetch_add(1, Ordering::Relaxed);
                 }
+                Err(Error::Cancelled) => return Err(Error::Cancelled),
                 Err(e) => {
                     error_count.fetch_add(1, Ordering::Relaxed);
                     eprintln!("Errore comprimendo {:?}: {}", file, e);
@@ -520,6 +1395,10 @@ fn batch_compress(pattern: &str, level: i32, force: bool, parallel: bool) -> std
         }
     }
 
+    if cancel_flag().load(Ordering::Relaxed) {
+        return Err(Error::Cancelled);
+    }
+
     pb.finish_with_message("Compressione batch completata!");
 
     let successes = success_count.load(Ordering::Relaxed);
@@ -535,18 +1414,13 @@ fn batch_compress(pattern: &str, level: i32, force: bool, parallel: bool) -> std
 }
 
 /// Verifica l'integrità di un file .zst con progress bar
-fn verify_with_progress(input_path: &Path) -> std::io::Result<()> {
+fn verify_with_progress(input_path: &Path, options: DecompressOptions) -> Result<()> {
     println!("Verifica integrità: {:?}", input_path);
 
-    let input_size = std::fs::metadata(input_path)?.len();
-    let pb = create_progress_bar(input_size, "Verifica in corso...");
-    let pb_clone = pb.clone();
-
-    let callback: ProgressCallback = Box::new(move |bytes: u64| {
-        pb_clone.set_position(bytes);
-    });
+    let pb = create_progress_bar(0, "Verifica in corso...");
+    let options = options.with_progress_events(track_decompressed(pb.clone(), "Verificati"));
 
-    let result = match verify_zst(input_path, Some(&callback)) {
+    let result = match verify_zst_with_options(input_path, &options) {
         Ok(r) => {
             pb.finish_with_message("Verifica completata!");
             r
@@ -570,6 +1444,108 @@ fn verify_with_progress(input_path: &Path) -> std::io::Result<()> {
         "Ratio: {}",
         format_ratio(result.decompressed_size, result.compressed_size)
     );
+    if result.content_checksum {
+        println!("Checksum del contenuto: verificato");
+    }
+    if let Some(digest) = result.digest {
+        println!("SHA-256: {}", digest);
+        match result.digest_source {
+            Some(DigestSource::Embedded) => println!("✅ Coincide con il manifest incorporato"),
+            Some(DigestSource::Sidecar) => println!("✅ Coincide con il file .sha256"),
+            None => println!("⚠️  Nessun digest di riferimento trovato: confronto non eseguito"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Rileva un archivio tar dal contenuto; in caso di errore decide la verifica normale
+fn is_tar_archive(input_path: &Path, options: &DecompressOptions) -> bool {
+    detect_format(input_path, options.dictionary.as_ref()).is_ok_and(|f| f.is_tar)
+}
+
+/// Verifica un archivio tar entry per entry con progress bar
+fn verify_archive_with_progress(input_path: &Path, options: DecompressOptions) -> Result<()> {
+    println!("Verifica archivio: {:?}", input_path);
+
+    let pb = create_progress_bar(0, "Verifica in corso...");
+    let options = options.with_progress_events(track_decompressed(pb.clone(), "Verificati"));
+
+    let result = match verify_archive(input_path, &options) {
+        Ok(r) => {
+            pb.finish_with_message("Verifica completata!");
+            r
+        }
+        Err(e) => {
+            pb.finish_with_message("Verifica fallita!");
+            return Err(e);
+        }
+    };
+
+    println!("\n✅ L'archivio è integro e valido!");
+    println!(
+        "Entry: {} - Dati: {}",
+        result.entries,
+        format_size(result.entry_bytes)
+    );
+    println!(
+        "Dimensione compressa: {} ({})",
+        format_size(result.compressed_size),
+        format_ratio(result.decompressed_size, result.compressed_size)
+    );
+    match result.digest_source {
+        Some(source) => {
+            let origin = match source {
+                DigestSource::Embedded => "manifest incorporato",
+                DigestSource::Sidecar => "file .sha256",
+            };
+            println!(
+                "✅ SHA-256 di {} file coincidenti con il {}",
+                result.files_checked, origin
+            );
+        }
+        None => println!("Nessun manifest dei file: digest non confrontati"),
+    }
+    if result.indexed {
+        println!("✅ Indice dei membri coerente con l'archivio");
+    }
+
+    Ok(())
+}
+
+/// Addestra un dizionario zstd dai file di esempio e lo salva su disco
+fn train_dictionary(
+    sample_files: &[PathBuf],
+    output_path: &Path,
+    size: usize,
+    force: bool,
+) -> Result<()> {
+    if output_path.exists() && !force {
+        return Err(Error::AlreadyExists(output_path.to_path_buf()));
+    }
+
+    println!("File di esempio: {} file", sample_files.len());
+    println!(
+        "Dimensione massima dizionario: {}",
+        format_size(size as u64)
+    );
+
+    let spinner = create_spinner("Addestramento dizionario...");
+    let dict = CompressionDictionary::train_from_files(sample_files, size);
+    spinner.finish_and_clear();
+    let dict = dict?;
+
+    dict.save(output_path)?;
+
+    println!("\n✅ Dizionario creato con successo!");
+    println!(
+        "File: {:?} - Dimensione: {}",
+        output_path,
+        format_size(dict.len() as u64)
+    );
+    if let Some(id) = dict.id() {
+        println!("ID dizionario: {}", id);
+    }
 
     Ok(())
 }
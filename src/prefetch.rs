@@ -0,0 +1,192 @@
+//! Lettura anticipata dei file di una directory da archiviare.
+//!
+//! Mentre il thread che scrive il tar comprime un file, alcuni thread di lettura
+//! aprono, interrogano e leggono in memoria i file successivi. I file vengono
+//! consegnati nell'ordine dell'elenco, quindi l'archivio è identico a quello
+//! della lettura sequenziale; la memoria dei file già letti e non ancora
+//! archiviati resta entro il limite configurato. I file più grandi del limite
+//! non vengono letti in anticipo: li legge il thread del tar.
+
+use crate::{Result, WalkEntry, WalkKind};
+use std::collections::BTreeMap;
+use std::fs::{File, Metadata};
+use std::io::Read;
+use std::sync::{Condvar, Mutex};
+use std::thread::Scope;
+
+/// Memoria di default per la lettura anticipata
+pub const DEFAULT_READ_AHEAD: u64 = 64 * 1024 * 1024;
+
+/// Thread di lettura al massimo
+const MAX_READ_THREADS: usize = 8;
+
+/// File elencati al massimo oltre quello in scrittura, anche se piccoli o vuoti
+const MAX_FILES_AHEAD: usize = 4096;
+
+/// File letto in anticipo da un thread di lettura
+pub(crate) struct PrefetchedFile {
+    pub(crate) metadata: Metadata,
+    pub(crate) data: Vec<u8>,
+}
+
+struct State {
+    /// Prossima entry da preparare
+    next_job: usize,
+    /// Prossima entry che il tar chiederà
+    next_take: usize,
+    /// Bytes letti e non ancora consegnati
+    reserved: u64,
+    ready: BTreeMap<usize, Result<Option<PrefetchedFile>>>,
+    stopped: bool,
+}
+
+/// Coda ordinata dei file letti in anticipo
+pub(crate) struct ReadAhead<'a> {
+    entries: &'a [WalkEntry],
+    budget: u64,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl<'a> ReadAhead<'a> {
+    /// Con `budget` 0 non viene avviato alcun thread e il tar legge ogni file
+    /// da sé, come senza lettura anticipata
+    pub(crate) fn new(entries: &'a [WalkEntry], budget: u64) -> Self {
+        Self {
+            entries,
+            budget,
+            state: Mutex::new(State {
+                next_job: 0,
+                next_take: 0,
+                reserved: 0,
+                ready: BTreeMap::new(),
+                stopped: false,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Avvia i thread di lettura nello scope indicato
+    pub(crate) fn start<'scope>(&'scope self, scope: &'scope Scope<'scope, '_>) {
+        if self.budget == 0 {
+            return;
+        }
+        let files = self
+            .entries
+            .iter()
+            .filter(|e| e.kind == WalkKind::File)
+            .count();
+        let threads = std::thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(MAX_READ_THREADS)
+            .min(files);
+        for _ in 0..threads {
+            scope.spawn(|| self.worker());
+        }
+    }
+
+    /// Ferma i thread di lettura (anche se il tar si è interrotto a metà)
+    pub(crate) fn stop(&self) {
+        self.lock().stopped = true;
+        self.changed.notify_all();
+    }
+
+    /// Entry dell'elenco
+    pub(crate) fn entries(&self) -> &'a [WalkEntry] {
+        self.entries
+    }
+
+    /// File in posizione `index` nell'elenco, atteso se non è ancora pronto;
+    /// `None` se il file va letto dal thread del tar.
+    ///
+    /// Va chiamato per i soli file, in ordine di posizione.
+    pub(crate) fn take(&self, index: usize) -> Result<Option<PrefetchedFile>> {
+        if self.budget == 0 {
+            return Ok(None);
+        }
+
+        let mut state = self.lock();
+        state.next_take = index;
+        self.changed.notify_all();
+        loop {
+            if let Some(result) = state.ready.remove(&index) {
+                if let Ok(Some(file)) = &result {
+                    state.reserved -= file.data.capacity() as u64;
+                }
+                self.changed.notify_all();
+                return result;
+            }
+            state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    fn worker(&self) {
+        loop {
+            let mut state = self.lock();
+            let index = loop {
+                if state.stopped {
+                    return;
+                }
+                // Salta directory e link: li archivia il thread del tar
+                while state.next_job < self.entries.len()
+                    && self.entries[state.next_job].kind != WalkKind::File
+                {
+                    state.next_job += 1;
+                }
+                if state.next_job == self.entries.len() {
+                    return;
+                }
+                let size = self.reservation(&self.entries[state.next_job]);
+                if state.next_job < state.next_take + MAX_FILES_AHEAD
+                    && state.reserved + size <= self.budget
+                {
+                    state.reserved += size;
+                    state.next_job += 1;
+                    break state.next_job - 1;
+                }
+                state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+            };
+            drop(state);
+
+            let entry = &self.entries[index];
+            let result = prepare(entry, self.budget);
+            let mut state = self.lock();
+            // La prenotazione segue la dimensione effettiva (il file può essere cambiato)
+            state.reserved -= self.reservation(entry);
+            if let Ok(Some(file)) = &result {
+                state.reserved += file.data.capacity() as u64;
+            }
+            state.ready.insert(index, result);
+            drop(state);
+            self.changed.notify_all();
+        }
+    }
+
+    /// Bytes prenotati per leggere un file in anticipo
+    fn reservation(&self, entry: &WalkEntry) -> u64 {
+        if entry.size <= self.budget {
+            entry.size
+        } else {
+            0
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        // Un thread di lettura non va mai in panic tenendo il lock
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Legge metadati e contenuto di un file che entra nel limite
+fn prepare(entry: &WalkEntry, budget: u64) -> Result<Option<PrefetchedFile>> {
+    if entry.size > budget {
+        return Ok(None);
+    }
+
+    let mut file = File::open(&entry.path)?;
+    let metadata = file.metadata()?;
+    let mut data = Vec::with_capacity(metadata.len() as usize);
+    file.read_to_end(&mut data)?;
+    data.shrink_to_fit();
+    Ok(Some(PrefetchedFile { metadata, data }))
+}
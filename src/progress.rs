@@ -0,0 +1,163 @@
+//! Eventi di progresso delle operazioni.
+//!
+//! Ogni operazione notifica l'inizio con i totali noti, l'avanzamento in bytes e
+//! l'inizio e la fine di ogni file o entry di archivio, così l'interfaccia non deve
+//! interpretare un numero in modo diverso per ogni funzione.
+
+use crate::CompressionResult;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Evento di progresso notificato durante un'operazione
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProgressEvent {
+    /// Inizio dell'operazione, con i totali quando sono noti in anticipo
+    Started {
+        total_bytes: Option<u64>,
+        total_files: Option<u64>,
+    },
+    /// Inizio di un file (per gli archivi, percorso relativo dell'entry)
+    FileStarted { path: PathBuf },
+    /// Bytes di input elaborati dall'inizio dell'operazione (in decompressione
+    /// i bytes compressi letti, con totale la dimensione del file)
+    Bytes { done: u64, total: Option<u64> },
+    /// Bytes decompressi prodotti; il totale è noto se l'header del frame
+    /// riporta la dimensione originale
+    Output { done: u64, total: Option<u64> },
+    /// File completato; per le entry di un archivio input e output coincidono
+    /// con la dimensione dell'entry
+    FileFinished {
+        path: PathBuf,
+        result: CompressionResult,
+    },
+    /// File o entry saltata senza errore
+    Skipped { path: PathBuf, reason: String },
+    /// Fine dell'operazione completata con successo
+    Finished,
+}
+
+/// Gestore degli eventi di progresso
+pub type ProgressHandler = Box<dyn Fn(&ProgressEvent) + Send + Sync>;
+
+/// Callback della vecchia API: riceve solo i bytes elaborati
+pub type ProgressCallback = Box<dyn Fn(u64) + Send + Sync>;
+
+/// Adatta un [`ProgressCallback`] agli eventi: riceve `done` degli eventi `Bytes`
+pub fn bytes_only<F>(callback: F) -> impl Fn(&ProgressEvent) + Send + Sync + 'static
+where
+    F: Fn(u64) + Send + Sync + 'static,
+{
+    move |event| {
+        if let ProgressEvent::Bytes { done, .. } = event {
+            callback(*done);
+        }
+    }
+}
+
+/// Adatta il [`ProgressCallback`] della vecchia API di decompressione: come
+/// prima degli eventi, per gli archivi riceve il numero di file estratti (le
+/// directory non generano eventi) e per i file singoli (`Started` con un solo
+/// file) i bytes degli eventi `Bytes`
+pub fn bytes_or_entries<F>(callback: F) -> impl Fn(&ProgressEvent) + Send + Sync + 'static
+where
+    F: Fn(u64) + Send + Sync + 'static,
+{
+    let single_file = AtomicBool::new(false);
+    let entries = AtomicBool::new(false);
+    let finished = AtomicU64::new(0);
+    move |event| match event {
+        ProgressEvent::Started { total_files, .. } => {
+            single_file.store(*total_files == Some(1), Ordering::Relaxed);
+        }
+        ProgressEvent::FileStarted { .. } if !single_file.load(Ordering::Relaxed) => {
+            entries.store(true, Ordering::Relaxed);
+        }
+        ProgressEvent::FileFinished { .. } if entries.load(Ordering::Relaxed) => {
+            callback(finished.fetch_add(1, Ordering::Relaxed) + 1);
+        }
+        ProgressEvent::Bytes { done, .. } if !entries.load(Ordering::Relaxed) => {
+            callback(*done);
+        }
+        _ => {}
+    }
+}
+
+/// Notifica gli eventi all'eventuale gestore
+#[derive(Clone, Copy)]
+pub(crate) struct Progress<'a> {
+    handler: Option<&'a (dyn Fn(&ProgressEvent) + Send + Sync)>,
+}
+
+impl<'a> Progress<'a> {
+    pub(crate) fn new(handler: Option<&'a ProgressHandler>) -> Self {
+        Self {
+            handler: handler.map(|h| h.as_ref()),
+        }
+    }
+
+    pub(crate) fn from_fn(handler: &'a (dyn Fn(&ProgressEvent) + Send + Sync)) -> Self {
+        Self {
+            handler: Some(handler),
+        }
+    }
+
+    pub(crate) fn none() -> Self {
+        Self { handler: None }
+    }
+
+    pub(crate) fn emit(&self, event: ProgressEvent) {
+        if let Some(handler) = self.handler {
+            handler(&event);
+        }
+    }
+
+    pub(crate) fn started(&self, total_bytes: Option<u64>, total_files: Option<u64>) {
+        self.emit(ProgressEvent::Started {
+            total_bytes,
+            total_files,
+        });
+    }
+
+    pub(crate) fn bytes(&self, done: u64, total: Option<u64>) {
+        self.emit(ProgressEvent::Bytes { done, total });
+    }
+
+    pub(crate) fn output(&self, done: u64, total: Option<u64>) {
+        self.emit(ProgressEvent::Output { done, total });
+    }
+
+    /// Notifica l'inizio di un file solo se c'è un gestore (evita di copiare il percorso)
+    pub(crate) fn file_started(&self, path: &Path) {
+        if self.handler.is_some() {
+            self.emit(ProgressEvent::FileStarted {
+                path: path.to_path_buf(),
+            });
+        }
+    }
+
+    pub(crate) fn file_finished(&self, path: &Path, input_size: u64, output_size: u64) {
+        if self.handler.is_some() {
+            self.emit(ProgressEvent::FileFinished {
+                path: path.to_path_buf(),
+                result: CompressionResult {
+                    input_size,
+                    output_size,
+                },
+            });
+        }
+    }
+
+    pub(crate) fn skipped(&self, path: &Path, reason: &str) {
+        if self.handler.is_some() {
+            self.emit(ProgressEvent::Skipped {
+                path: path.to_path_buf(),
+                reason: reason.to_string(),
+            });
+        }
+    }
+
+    pub(crate) fn finished(&self) {
+        self.emit(ProgressEvent::Finished);
+    }
+}
@@ -0,0 +1,515 @@
+//! Formato zstd seekable: frame indipendenti e seek table per l'accesso casuale.
+//!
+//! Il file è una sequenza di frame zstd che comprimono ciascuno al massimo
+//! `frame_size` bytes dell'input, seguita dalla seek table del formato seekable
+//! di zstd (`contrib/seekable_format`) in un frame skippable. Qualsiasi decoder
+//! zstd legge il file come uno stream normale; [`SeekableReader`] usa la tabella
+//! per decomprimere solo i frame che contengono i bytes richiesti.
+
+use crate::codec::{EncoderSettings, SKIPPABLE_MAGIC_START};
+use crate::{CompressionAlgorithm, CompressionDictionary, DecompressionLimits, Error, Result};
+use rayon::prelude::*;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use zstd::zstd_safe::CParameter;
+
+/// Magic del frame skippable con la seek table
+pub const SEEK_TABLE_MAGIC: u32 = SKIPPABLE_MAGIC_START + 0xE;
+
+/// Magic finale del formato seekable
+pub const SEEKABLE_MAGIC: u32 = 0x8F92_EAB1;
+
+/// Footer della seek table: numero di frame, descrittore e magic
+const SEEK_TABLE_FOOTER_SIZE: u64 = 9;
+
+/// Bit del descrittore che indica i checksum nelle entry
+const CHECKSUM_FLAG: u8 = 0x80;
+
+/// Dimensione decompressa massima di un frame (come nel formato di riferimento)
+pub const MAX_FRAME_SIZE: u32 = 1 << 30;
+
+/// Dimensione predefinita dei frame
+pub const DEFAULT_FRAME_SIZE: u32 = 1 << 20;
+
+/// Frame elencato nella seek table, con le posizioni calcolate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeekFrame {
+    pub compressed_offset: u64,
+    pub decompressed_offset: u64,
+    pub compressed_size: u32,
+    pub decompressed_size: u32,
+}
+
+/// Seek table di un file zstd seekable
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeekTable {
+    frames: Vec<SeekFrame>,
+}
+
+impl SeekTable {
+    /// Frame nell'ordine del file
+    pub fn frames(&self) -> &[SeekFrame] {
+        &self.frames
+    }
+
+    /// Dimensione dei dati decompressi
+    pub fn decompressed_size(&self) -> u64 {
+        self.frames.last().map_or(0, |f| {
+            f.decompressed_offset + u64::from(f.decompressed_size)
+        })
+    }
+
+    /// Bytes occupati dai frame di dati
+    pub fn compressed_size(&self) -> u64 {
+        self.frames
+            .last()
+            .map_or(0, |f| f.compressed_offset + u64::from(f.compressed_size))
+    }
+
+    /// Indice del frame che contiene la posizione decompressa `offset`
+    pub fn frame_at(&self, offset: u64) -> Option<usize> {
+        if offset >= self.decompressed_size() {
+            return None;
+        }
+        let index = self
+            .frames
+            .partition_point(|f| f.decompressed_offset + u64::from(f.decompressed_size) <= offset);
+        Some(index)
+    }
+
+    fn push(&mut self, compressed_size: u32, decompressed_size: u32) {
+        self.frames.push(SeekFrame {
+            compressed_offset: self.compressed_size(),
+            decompressed_offset: self.decompressed_size(),
+            compressed_size,
+            decompressed_size,
+        });
+    }
+
+    /// Scrive la seek table (senza checksum); ritorna i bytes scritti
+    pub fn write_to<W: Write + ?Sized>(&self, writer: &mut W) -> std::io::Result<u64> {
+        let frame_size = self.frames.len() as u64 * 8 + SEEK_TABLE_FOOTER_SIZE;
+        let mut table = Vec::with_capacity(frame_size as usize + 8);
+        table.extend_from_slice(&SEEK_TABLE_MAGIC.to_le_bytes());
+        table.extend_from_slice(&(frame_size as u32).to_le_bytes());
+        for frame in &self.frames {
+            table.extend_from_slice(&frame.compressed_size.to_le_bytes());
+            table.extend_from_slice(&frame.decompressed_size.to_le_bytes());
+        }
+        table.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        table.push(0);
+        table.extend_from_slice(&SEEKABLE_MAGIC.to_le_bytes());
+        writer.write_all(&table)?;
+        Ok(table.len() as u64)
+    }
+
+    /// Legge la seek table alla fine del file (o dell'ultimo volume), se presente
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        let (mut file, _) = crate::volume::open_input(path)?;
+        Ok(Self::read_from(&mut file)?.map(|(table, _)| table))
+    }
+
+    /// Legge la seek table alla fine di `reader`; ritorna anche i bytes del
+    /// frame che la contiene
+    pub fn read_from<R: Read + Seek + ?Sized>(reader: &mut R) -> Result<Option<(Self, u64)>> {
+        let file_size = reader.seek(SeekFrom::End(0))?;
+        if file_size < SEEK_TABLE_FOOTER_SIZE + 8 {
+            return Ok(None);
+        }
+
+        let mut footer = [0u8; SEEK_TABLE_FOOTER_SIZE as usize];
+        reader.seek(SeekFrom::End(-(SEEK_TABLE_FOOTER_SIZE as i64)))?;
+        reader.read_exact(&mut footer)?;
+        if u32::from_le_bytes([footer[5], footer[6], footer[7], footer[8]]) != SEEKABLE_MAGIC {
+            return Ok(None);
+        }
+        let count = u64::from(u32::from_le_bytes([
+            footer[0], footer[1], footer[2], footer[3],
+        ]));
+        let descriptor = footer[4];
+        if descriptor & !CHECKSUM_FLAG != 0 {
+            return Err(invalid_table("bit riservati del descrittore impostati"));
+        }
+        let entry_size = if descriptor & CHECKSUM_FLAG != 0 {
+            12
+        } else {
+            8
+        };
+
+        let frame_size = count * entry_size + SEEK_TABLE_FOOTER_SIZE;
+        let table_start = file_size
+            .checked_sub(frame_size + 8)
+            .ok_or_else(|| invalid_table("più frame di quanti ne contenga il file"))?;
+        let mut table = vec![0u8; (frame_size + 8) as usize];
+        reader.seek(SeekFrom::Start(table_start))?;
+        reader.read_exact(&mut table)?;
+        let magic = u32::from_le_bytes([table[0], table[1], table[2], table[3]]);
+        let size = u64::from(u32::from_le_bytes([table[4], table[5], table[6], table[7]]));
+        if magic != SEEK_TABLE_MAGIC || size != frame_size {
+            return Err(invalid_table("header del frame skippable non valido"));
+        }
+
+        let mut seek_table = SeekTable::default();
+        for entry in table[8..8 + (count * entry_size) as usize].chunks_exact(entry_size as usize) {
+            let compressed = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]);
+            let decompressed = u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]);
+            if decompressed > MAX_FRAME_SIZE {
+                return Err(invalid_table("frame più grande del massimo consentito"));
+            }
+            seek_table.push(compressed, decompressed);
+        }
+        if seek_table.compressed_size() > table_start {
+            return Err(invalid_table("i frame superano la fine dei dati"));
+        }
+        Ok(Some((seek_table, frame_size + 8)))
+    }
+}
+
+impl SeekTable {
+    /// Verifica i frame dichiarati contro i limiti di decompressione, prima che
+    /// un frame venga allocato e decompresso
+    pub fn check_limits(&self, limits: &DecompressionLimits) -> Result<()> {
+        for frame in &self.frames {
+            limits.check_frame(
+                u64::from(frame.compressed_size),
+                u64::from(frame.decompressed_size),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid_table(detail: &str) -> Error {
+    Error::Corrupt(format!("seek table non valida: {}", detail))
+}
+
+/// Comprime in frame zstd indipendenti di `frame_size` bytes di input.
+///
+/// [`SeekableWriter::finish`] scrive l'ultimo frame e ritorna la seek table,
+/// che il chiamante scrive dopo gli eventuali altri frame skippable.
+pub struct SeekableWriter<W: Write> {
+    writer: W,
+    compressor: zstd::bulk::Compressor<'static>,
+    frame_size: usize,
+    buffer: Vec<u8>,
+    table: SeekTable,
+}
+
+impl<W: Write> SeekableWriter<W> {
+    pub fn new(writer: W, settings: &EncoderSettings, frame_size: u32) -> Result<Self> {
+        if frame_size == 0 || frame_size > MAX_FRAME_SIZE {
+            return Err(Error::InvalidInput(format!(
+                "Dimensione dei frame non valida: da 1 byte a {}",
+                crate::format_size(u64::from(MAX_FRAME_SIZE))
+            )));
+        }
+
+        let level = settings.level_for(CompressionAlgorithm::Zstd);
+        let mut compressor = match settings.dictionary {
+            Some(dict) => zstd::bulk::Compressor::with_dictionary(level, dict.as_bytes())?,
+            None => zstd::bulk::Compressor::new(level)?,
+        };
+        // I frame sono piccoli e indipendenti: il multithreading interno non serve
+        for param in &settings.zstd_parameters {
+            if !matches!(param, CParameter::NbWorkers(_)) {
+                compressor.set_parameter(*param)?;
+            }
+        }
+
+        Ok(Self {
+            writer,
+            compressor,
+            frame_size: frame_size as usize,
+            buffer: Vec::with_capacity(frame_size as usize),
+            table: SeekTable::default(),
+        })
+    }
+
+    fn write_frame(&mut self) -> std::io::Result<()> {
+        let frame = self.compressor.compress(&self.buffer)?;
+        self.writer.write_all(&frame)?;
+        self.table
+            .push(frame.len() as u32, self.buffer.len() as u32);
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Posizione corrente nei dati non compressi
+    pub fn position(&self) -> u64 {
+        self.table.decompressed_size() + self.buffer.len() as u64
+    }
+
+    /// Chiude il frame corrente se contiene almeno `min_size` bytes, così i dati
+    /// successivi iniziano un nuovo frame
+    pub fn end_frame(&mut self, min_size: usize) -> std::io::Result<()> {
+        if self.buffer.is_empty() || self.buffer.len() < min_size {
+            return Ok(());
+        }
+        self.write_frame()
+    }
+
+    /// Dimensione massima dei frame
+    pub fn frame_size(&self) -> usize {
+        self.frame_size
+    }
+
+    /// Comprime i dati rimasti; un input vuoto produce comunque un frame
+    pub fn finish(mut self) -> std::io::Result<(W, SeekTable)> {
+        if !self.buffer.is_empty() || self.table.frames.is_empty() {
+            self.write_frame()?;
+        }
+        Ok((self.writer, self.table))
+    }
+}
+
+impl<W: Write> Write for SeekableWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = (self.frame_size - self.buffer.len()).min(buf.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+        if self.buffer.len() == self.frame_size {
+            self.write_frame()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Lettura con accesso casuale dei dati di un file zstd seekable.
+///
+/// Ogni lettura decomprime solo il frame che contiene la posizione corrente;
+/// l'ultimo frame decompresso resta in memoria per le letture successive.
+pub struct SeekableReader<R> {
+    inner: R,
+    table: SeekTable,
+    decompressor: zstd::bulk::Decompressor<'static>,
+    position: u64,
+    cached: Option<(usize, Vec<u8>)>,
+}
+
+impl SeekableReader<File> {
+    /// Apre un file zstd seekable
+    pub fn open(path: &Path, dictionary: Option<&CompressionDictionary>) -> Result<Self> {
+        if !path.exists() {
+            return Err(Error::NotFound(format!("Il file {:?} non esiste", path)));
+        }
+        Self::new(File::open(path)?, dictionary)
+    }
+}
+
+impl<R: Read + Seek> SeekableReader<R> {
+    /// Legge la seek table di `inner`; senza tabella il file non è seekable
+    pub fn new(mut inner: R, dictionary: Option<&CompressionDictionary>) -> Result<Self> {
+        let Some((table, _)) = SeekTable::read_from(&mut inner)? else {
+            return Err(Error::UnsupportedFormat(
+                "Il file non è in formato zstd seekable: ricomprimi con --seekable".to_string(),
+            ));
+        };
+        Ok(Self {
+            inner,
+            table,
+            decompressor: new_decompressor(dictionary)?,
+            position: 0,
+            cached: None,
+        })
+    }
+
+    /// Seek table del file
+    pub fn table(&self) -> &SeekTable {
+        &self.table
+    }
+
+    /// Dimensione dei dati decompressi
+    pub fn len(&self) -> u64 {
+        self.table.decompressed_size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn load_frame(&mut self, index: usize) -> std::io::Result<&[u8]> {
+        if self
+            .cached
+            .as_ref()
+            .is_none_or(|(cached, _)| *cached != index)
+        {
+            let frame = self.table.frames[index];
+            let mut compressed = vec![0u8; frame.compressed_size as usize];
+            self.inner.seek(SeekFrom::Start(frame.compressed_offset))?;
+            self.inner.read_exact(&mut compressed)?;
+
+            let data = decompress_frame(&mut self.decompressor, &compressed, index, &frame)?;
+            self.cached = Some((index, data));
+        }
+        Ok(&self.cached.as_ref().expect("frame appena caricato").1)
+    }
+}
+
+impl<R: Read + Seek> Read for SeekableReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let Some(index) = self.table.frame_at(self.position) else {
+            return Ok(0);
+        };
+        let start = (self.position - self.table.frames[index].decompressed_offset) as usize;
+        let data = self.load_frame(index)?;
+        let n = (data.len() - start).min(buf.len());
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for SeekableReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        }
+        .ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "posizione non valida")
+        })?;
+        self.position = target;
+        Ok(target)
+    }
+}
+
+/// Frame letti al massimo per thread in un gruppo del decoder parallelo
+const FRAMES_PER_THREAD: usize = 2;
+
+/// Bytes decompressi al massimo in un gruppo del decoder parallelo
+const MAX_BATCH_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Decomprime in parallelo i frame di un file seekable letto dall'inizio.
+///
+/// I frame vengono letti a gruppi, decompressi sul pool di rayon e restituiti
+/// nell'ordine della seek table; in memoria resta al massimo un gruppo.
+pub(crate) struct ParallelDecoder<'a, R> {
+    inner: R,
+    frames: Vec<SeekFrame>,
+    next_frame: usize,
+    dictionary: Option<&'a CompressionDictionary>,
+    decoded: VecDeque<Vec<u8>>,
+    position: usize,
+}
+
+impl<'a, R: Read> ParallelDecoder<'a, R> {
+    pub(crate) fn new(
+        inner: R,
+        table: SeekTable,
+        dictionary: Option<&'a CompressionDictionary>,
+    ) -> Self {
+        Self {
+            inner,
+            frames: table.frames,
+            next_frame: 0,
+            dictionary,
+            decoded: VecDeque::new(),
+            position: 0,
+        }
+    }
+
+    /// Legge e decomprime il gruppo di frame successivo
+    fn decode_batch(&mut self) -> Result<()> {
+        let max_frames = rayon::current_num_threads() * FRAMES_PER_THREAD;
+        let mut end = self.next_frame;
+        let mut batch_bytes = 0u64;
+        while end < self.frames.len()
+            && (end == self.next_frame
+                || (end - self.next_frame < max_frames && batch_bytes < MAX_BATCH_BYTES))
+        {
+            batch_bytes += u64::from(self.frames[end].decompressed_size);
+            end += 1;
+        }
+
+        // I frame sono contigui: la lettura resta sequenziale
+        let batch = &self.frames[self.next_frame..end];
+        let mut compressed = Vec::with_capacity(batch.len());
+        for frame in batch {
+            let mut data = vec![0u8; frame.compressed_size as usize];
+            self.inner.read_exact(&mut data)?;
+            compressed.push(data);
+        }
+
+        let first = self.next_frame;
+        let dictionary = self.dictionary;
+        let decoded = compressed
+            .par_iter()
+            .zip(batch)
+            .enumerate()
+            .map_init(
+                || None,
+                |decompressor, (i, (data, frame))| {
+                    if decompressor.is_none() {
+                        *decompressor = Some(new_decompressor(dictionary)?);
+                    }
+                    let decompressor = decompressor.as_mut().expect("decompressore creato");
+                    decompress_frame(decompressor, data, first + i, frame)
+                },
+            )
+            .collect::<Result<Vec<_>>>()?;
+
+        self.decoded.extend(decoded);
+        self.next_frame = end;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for ParallelDecoder<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if let Some(data) = self.decoded.front() {
+                if self.position < data.len() {
+                    let n = (data.len() - self.position).min(buf.len());
+                    buf[..n].copy_from_slice(&data[self.position..self.position + n]);
+                    self.position += n;
+                    return Ok(n);
+                }
+                self.decoded.pop_front();
+                self.position = 0;
+                continue;
+            }
+            if self.next_frame == self.frames.len() {
+                // Consuma i frame skippable finali (manifest, indice, seek table)
+                std::io::copy(&mut self.inner, &mut std::io::sink())?;
+                return Ok(0);
+            }
+            self.decode_batch()?;
+        }
+    }
+}
+
+fn new_decompressor(
+    dictionary: Option<&CompressionDictionary>,
+) -> std::io::Result<zstd::bulk::Decompressor<'static>> {
+    match dictionary {
+        Some(dict) => zstd::bulk::Decompressor::with_dictionary(dict.as_bytes()),
+        None => zstd::bulk::Decompressor::new(),
+    }
+}
+
+/// Decomprime un frame controllando la dimensione indicata dalla seek table
+fn decompress_frame(
+    decompressor: &mut zstd::bulk::Decompressor<'static>,
+    compressed: &[u8],
+    index: usize,
+    frame: &SeekFrame,
+) -> Result<Vec<u8>> {
+    let data = decompressor
+        .decompress(compressed, frame.decompressed_size as usize)
+        .map_err(|e| Error::Corrupt(format!("frame {} non decomprimibile: {}", index, e)))?;
+    if data.len() != frame.decompressed_size as usize {
+        return Err(Error::Corrupt(format!(
+            "il frame {} contiene {} bytes, la seek table ne indica {}",
+            index,
+            data.len(),
+            frame.decompressed_size
+        )));
+    }
+    Ok(data)
+}
@@ -0,0 +1,582 @@
+//! Archivi divisi in volumi di dimensione massima.
+//!
+//! I volumi `<archivio>.001`, `.002`, ... sono la divisione in byte dello stream
+//! compresso, quindi `cat archivio.tar.zst.* | tar --zstd -x` continua a funzionare.
+//! L'ultimo volume termina con una tabella in un frame skippable, ignorato dai
+//! decoder zstd, con dimensione e digest dell'inizio di ogni volume: in lettura
+//! i volumi mancanti, in più o fuori ordine vengono segnalati prima di decomprimere.
+//! Per questo i volumi sono disponibili solo con zstd e lz4, i cui decoder saltano
+//! i frame skippable.
+
+use crate::codec::SKIPPABLE_MAGIC_START;
+use crate::digest::DigestWriter;
+use crate::{AtomicFile, CompressionAlgorithm, Error, Result, Sha256Digest};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Magic del frame skippable con la tabella dei volumi
+pub const VOLUME_TABLE_MAGIC: u32 = SKIPPABLE_MAGIC_START + 0xC;
+
+/// Marcatore finale della tabella, preceduto dal numero di volumi
+const VOLUME_TABLE_TAG: [u8; 4] = *b"FCVT";
+
+/// Bytes per volume nella tabella: dimensione e digest dell'inizio
+const VOLUME_ENTRY_SIZE: usize = 8 + 32;
+
+/// Bytes iniziali di ogni volume coperti dal digest di identificazione
+const HEAD_DIGEST_BYTES: u64 = 64 * 1024;
+
+/// Dimensione minima di un volume
+pub const MIN_VOLUME_SIZE: u64 = 64 * 1024;
+
+/// Numero massimo di volumi: il suffisso ha sempre tre cifre
+pub const MAX_VOLUMES: usize = 999;
+
+/// Percorso del volume `index` (da 1): `<base>.001`
+pub fn volume_path(base: &Path, index: usize) -> PathBuf {
+    let mut name = base.as_os_str().to_os_string();
+    name.push(format!(".{:03}", index));
+    PathBuf::from(name)
+}
+
+/// Archivio di cui `path` è un volume, se l'estensione è un numero di volume
+pub fn volume_base(path: &Path) -> Option<PathBuf> {
+    let extension = path.extension()?.to_str()?;
+    if extension.len() == 3 && extension.bytes().all(|b| b.is_ascii_digit()) {
+        Some(path.with_extension(""))
+    } else {
+        None
+    }
+}
+
+/// Ritorna true se `path` è il primo volume di un set (`.001`): seguito da
+/// altri volumi o con la tabella dei volumi. Un `.001` isolato senza tabella
+/// (es. `dati.001`) è un file normale.
+pub fn is_first_volume(path: &Path) -> bool {
+    let Some(base) = volume_base(path).filter(|_| path.extension().is_some_and(|e| e == "001"))
+    else {
+        return false;
+    };
+    volume_path(&base, 2).exists()
+        || matches!(later_volume(&base, 1), Ok(Some(_)))
+        || matches!(read_table(path), Ok(Some(_)))
+}
+
+/// Percorso con cui nominare gli output di un input: l'archivio per un volume
+pub(crate) fn logical_path(path: &Path) -> PathBuf {
+    match volume_base(path) {
+        Some(base) if is_first_volume(path) => base,
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Dimensione e digest dell'inizio di un volume
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct VolumeInfo {
+    /// Bytes dello stream nel volume (tabella esclusa)
+    size: u64,
+    head: Sha256Digest,
+}
+
+fn encode_table(volumes: &[VolumeInfo]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(volumes.len() * VOLUME_ENTRY_SIZE + 8);
+    for volume in volumes {
+        payload.extend_from_slice(&volume.size.to_le_bytes());
+        payload.extend_from_slice(&volume.head.0);
+    }
+    payload.extend_from_slice(&(volumes.len() as u32).to_le_bytes());
+    payload.extend_from_slice(&VOLUME_TABLE_TAG);
+
+    let mut frame = Vec::with_capacity(payload.len() + 8);
+    frame.extend_from_slice(&VOLUME_TABLE_MAGIC.to_le_bytes());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&payload);
+    frame
+}
+
+/// Legge la tabella alla fine di un volume; ritorna i volumi e la dimensione
+/// del frame della tabella, o `None` se il volume non la contiene
+fn read_table(path: &Path) -> Result<Option<(Vec<VolumeInfo>, u64)>> {
+    let mut file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    if file_size < 16 {
+        return Ok(None);
+    }
+
+    let mut footer = [0u8; 8];
+    file.seek(SeekFrom::End(-8))?;
+    file.read_exact(&mut footer)?;
+    if footer[4..] != VOLUME_TABLE_TAG {
+        return Ok(None);
+    }
+    let count = u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]) as usize;
+    let payload_size = (count * VOLUME_ENTRY_SIZE + 8) as u64;
+    let Some(frame_start) = file_size.checked_sub(payload_size + 8) else {
+        return Ok(None);
+    };
+
+    let mut frame = vec![0u8; (payload_size + 8) as usize];
+    file.seek(SeekFrom::Start(frame_start))?;
+    file.read_exact(&mut frame)?;
+    let magic = u32::from_le_bytes([frame[0], frame[1], frame[2], frame[3]]);
+    let size = u64::from(u32::from_le_bytes([frame[4], frame[5], frame[6], frame[7]]));
+    if magic != VOLUME_TABLE_MAGIC || size != payload_size {
+        return Ok(None);
+    }
+
+    let volumes = frame[8..8 + count * VOLUME_ENTRY_SIZE]
+        .chunks_exact(VOLUME_ENTRY_SIZE)
+        .map(|entry| {
+            let mut size = [0u8; 8];
+            size.copy_from_slice(&entry[..8]);
+            let mut head = [0u8; 32];
+            head.copy_from_slice(&entry[8..]);
+            VolumeInfo {
+                size: u64::from_le_bytes(size),
+                head: Sha256Digest(head),
+            }
+        })
+        .collect();
+    Ok(Some((volumes, payload_size + 8)))
+}
+
+fn head_digest(path: &Path, size: u64) -> Result<Sha256Digest> {
+    let mut head = vec![0u8; size.min(HEAD_DIGEST_BYTES) as usize];
+    File::open(path)?.read_exact(&mut head)?;
+    Ok(Sha256Digest::of(&head))
+}
+
+/// Scrive lo stream compresso in volumi di al massimo `volume_size` bytes.
+///
+/// Come [`AtomicFile`], i volumi compaiono sui percorsi finali solo con
+/// [`VolumeWriter::commit`]; scartato il writer, i file temporanei sono rimossi.
+pub struct VolumeWriter {
+    base: PathBuf,
+    volume_size: u64,
+    finished: Vec<(AtomicFile, VolumeInfo)>,
+    current: Option<(AtomicFile, u64, DigestWriter)>,
+}
+
+impl VolumeWriter {
+    /// Prepara i volumi `<base>.001`, `.002`, ...
+    pub fn create(base: &Path, volume_size: u64) -> Result<Self> {
+        if volume_size < MIN_VOLUME_SIZE {
+            return Err(Error::InvalidInput(format!(
+                "Dimensione dei volumi troppo piccola: minimo {}",
+                crate::format_size(MIN_VOLUME_SIZE)
+            )));
+        }
+        Ok(Self {
+            base: base.to_path_buf(),
+            volume_size,
+            finished: Vec::new(),
+            current: None,
+        })
+    }
+
+    fn next_volume(&mut self) -> std::io::Result<()> {
+        if self.finished.len() + usize::from(self.current.is_some()) >= MAX_VOLUMES {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                too_many_volumes(),
+            ));
+        }
+        if let Some((file, size, head)) = self.current.take() {
+            self.finished.push((
+                file,
+                VolumeInfo {
+                    size,
+                    head: head.finish(),
+                },
+            ));
+        }
+        let path = volume_path(&self.base, self.finished.len() + 1);
+        self.current = Some((AtomicFile::create(&path)?, 0, DigestWriter::default()));
+        Ok(())
+    }
+
+    /// Scrive la tabella nell'ultimo volume e rinomina tutti i volumi sui
+    /// percorsi finali; ritorna i percorsi dei volumi scritti
+    pub fn commit(mut self) -> Result<Vec<PathBuf>> {
+        if self.current.is_none() {
+            self.next_volume()?;
+        }
+        let (file, size, head) = self.current.take().expect("volume corrente");
+        let mut volumes: Vec<VolumeInfo> = self.finished.iter().map(|(_, info)| *info).collect();
+        volumes.push(VolumeInfo {
+            size,
+            head: head.finish(),
+        });
+        let mut files: Vec<AtomicFile> = self.finished.drain(..).map(|(file, _)| file).collect();
+        files.push(file);
+
+        // La tabella non viene divisa: se non c'è spazio va in un volume a sé
+        let mut table = encode_table(&volumes);
+        if size + table.len() as u64 > self.volume_size {
+            if files.len() >= MAX_VOLUMES {
+                return Err(Error::InvalidInput(too_many_volumes()));
+            }
+            let path = volume_path(&self.base, files.len() + 1);
+            files.push(AtomicFile::create(&path)?);
+            volumes.push(VolumeInfo {
+                size: 0,
+                head: Sha256Digest::of(&[]),
+            });
+            table = encode_table(&volumes);
+            if table.len() as u64 > self.volume_size {
+                return Err(Error::InvalidInput(format!(
+                    "Troppi volumi ({}): aumenta la dimensione dei volumi",
+                    volumes.len()
+                )));
+            }
+        }
+        let last = files.len() - 1;
+        files[last].write_all(&table)?;
+
+        let paths: Vec<PathBuf> = (1..=files.len())
+            .map(|i| volume_path(&self.base, i))
+            .collect();
+        for file in files {
+            file.commit()?;
+        }
+
+        // Con --force i volumi di un set precedente più lungo non devono restare
+        let mut stale = paths.len() + 1;
+        while volume_path(&self.base, stale).exists() {
+            std::fs::remove_file(volume_path(&self.base, stale))?;
+            stale += 1;
+        }
+        Ok(paths)
+    }
+}
+
+fn too_many_volumes() -> String {
+    format!(
+        "Troppi volumi (massimo {}): aumenta la dimensione dei volumi",
+        MAX_VOLUMES
+    )
+}
+
+impl Write for VolumeWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self
+            .current
+            .as_ref()
+            .is_none_or(|(_, size, _)| *size == self.volume_size)
+        {
+            self.next_volume()?;
+        }
+        let (file, size, head) = self.current.as_mut().expect("volume corrente");
+        let room = (self.volume_size - *size).min(buf.len() as u64) as usize;
+        let n = file.write(&buf[..room])?;
+        if *size < HEAD_DIGEST_BYTES {
+            let hashed = (HEAD_DIGEST_BYTES - *size).min(n as u64) as usize;
+            head.update(&buf[..hashed]);
+        }
+        *size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.current.as_mut() {
+            Some((file, _, _)) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Set di volumi controllato: tutti presenti, nell'ordine giusto
+#[derive(Debug, Clone)]
+pub struct VolumeSet {
+    paths: Vec<PathBuf>,
+    sizes: Vec<u64>,
+}
+
+impl VolumeSet {
+    /// Apre il set a partire dal primo volume (`<archivio>.001`)
+    pub fn open(first: &Path) -> Result<Self> {
+        let base = volume_base(first).ok_or_else(|| {
+            Error::InvalidInput(format!("{:?} non è il primo volume di un set", first))
+        })?;
+        let mut paths = Vec::new();
+        while volume_path(&base, paths.len() + 1).exists() {
+            paths.push(volume_path(&base, paths.len() + 1));
+        }
+        if paths.is_empty() {
+            return Err(Error::NotFound(format!("Il volume {:?} non esiste", first)));
+        }
+        if let Some(later) = later_volume(&base, paths.len())? {
+            return Err(Error::NotFound(format!(
+                "Manca il volume {:?} (esiste {:?})",
+                volume_path(&base, paths.len() + 1),
+                later
+            )));
+        }
+
+        let last = &paths[paths.len() - 1];
+        let Some((volumes, table_size)) = read_table(last)? else {
+            return Err(Error::NotFound(format!(
+                "Tabella dei volumi assente in {:?}: mancano gli ultimi volumi del set",
+                last
+            )));
+        };
+        if volumes.len() != paths.len() {
+            return Err(Error::Corrupt(format!(
+                "il set ha {} volumi, trovati {}",
+                volumes.len(),
+                paths.len()
+            )));
+        }
+
+        let mut sizes = Vec::with_capacity(paths.len());
+        for (index, path) in paths.iter().enumerate() {
+            let mut size = std::fs::metadata(path)?.len();
+            if index == paths.len() - 1 {
+                size -= table_size;
+            }
+            let head = head_digest(path, size)?;
+            let actual = VolumeInfo { size, head };
+            if actual != volumes[index] {
+                return Err(match volumes.iter().position(|v| *v == actual) {
+                    Some(other) => Error::Corrupt(format!(
+                        "il volume {:?} è fuori ordine: contiene il volume {:03}",
+                        path,
+                        other + 1
+                    )),
+                    None => Error::Corrupt(format!(
+                        "il volume {:?} è danneggiato o non appartiene al set",
+                        path
+                    )),
+                });
+            }
+            sizes.push(size);
+        }
+
+        Ok(Self { paths, sizes })
+    }
+
+    /// Percorsi dei volumi in ordine
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Bytes dello stream compresso in tutti i volumi
+    pub fn len(&self) -> u64 {
+        self.sizes.iter().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Lettore dello stream compresso ricomposto
+    pub fn reader(&self) -> Result<VolumeReader> {
+        Ok(VolumeReader {
+            set: self.clone(),
+            index: 0,
+            file: File::open(&self.paths[0])?,
+            position: 0,
+            volume_start: 0,
+        })
+    }
+}
+
+/// Primo volume esistente dopo `count`, se ce n'è uno (set con un buco)
+fn later_volume(base: &Path, count: usize) -> Result<Option<PathBuf>> {
+    let (Some(dir), Some(name)) = (base.parent(), base.file_name()) else {
+        return Ok(None);
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let mut later = None;
+    for entry in std::fs::read_dir(dir)? {
+        let path = base.with_file_name(entry?.file_name());
+        let index = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .filter(|_| path.with_extension("").file_name() == Some(name))
+            .filter(|_| volume_base(&path).is_some())
+            .and_then(|ext| ext.parse::<usize>().ok());
+        if let Some(index) = index.filter(|&i| i > count) {
+            if later.as_ref().is_none_or(|(i, _)| index < *i) {
+                later = Some((index, path));
+            }
+        }
+    }
+    Ok(later.map(|(_, path)| path))
+}
+
+/// Lettura dello stream compresso di un [`VolumeSet`] come un unico file
+pub struct VolumeReader {
+    set: VolumeSet,
+    index: usize,
+    file: File,
+    /// Posizione nello stream ricomposto
+    position: u64,
+    /// Posizione nello stream dell'inizio del volume corrente
+    volume_start: u64,
+}
+
+impl VolumeReader {
+    fn open_volume(&mut self, index: usize) -> std::io::Result<()> {
+        self.file = File::open(&self.set.paths[index])?;
+        self.index = index;
+        self.volume_start = self.set.sizes[..index].iter().sum();
+        Ok(())
+    }
+}
+
+impl Read for VolumeReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let remaining = self.volume_start + self.set.sizes[self.index] - self.position;
+            if remaining > 0 {
+                let len = remaining.min(buf.len() as u64) as usize;
+                let n = self.file.read(&mut buf[..len])?;
+                if n == 0 {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        format!("volume {:?} troncato", self.set.paths[self.index]),
+                    ));
+                }
+                self.position += n as u64;
+                return Ok(n);
+            }
+            if self.index + 1 == self.set.paths.len() || buf.is_empty() {
+                return Ok(0);
+            }
+            self.open_volume(self.index + 1)?;
+        }
+    }
+}
+
+impl Seek for VolumeReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.set.len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        }
+        .ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "posizione non valida")
+        })?;
+
+        // Il volume che contiene la posizione (l'ultimo se è oltre la fine)
+        let mut start = 0;
+        let mut index = 0;
+        while index + 1 < self.set.sizes.len() && target >= start + self.set.sizes[index] {
+            start += self.set.sizes[index];
+            index += 1;
+        }
+        if index != self.index {
+            self.open_volume(index)?;
+        }
+        self.file.seek(SeekFrom::Start(target - start))?;
+        self.position = target;
+        Ok(target)
+    }
+}
+
+/// File compresso in input: un file singolo o un set di volumi
+pub(crate) enum InputReader {
+    File(File),
+    Volumes(VolumeReader),
+}
+
+impl Read for InputReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            InputReader::File(file) => file.read(buf),
+            InputReader::Volumes(volumes) => volumes.read(buf),
+        }
+    }
+}
+
+impl Seek for InputReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            InputReader::File(file) => file.seek(pos),
+            InputReader::Volumes(volumes) => volumes.seek(pos),
+        }
+    }
+}
+
+/// Apre un file compresso o, per il primo volume di un set, lo stream ricomposto;
+/// ritorna anche la dimensione dello stream
+pub(crate) fn open_input(path: &Path) -> Result<(InputReader, u64)> {
+    if let Some(base) = volume_base(path) {
+        if is_first_volume(path) {
+            let set = VolumeSet::open(path)?;
+            let len = set.len();
+            return Ok((InputReader::Volumes(set.reader()?), len));
+        }
+        if is_first_volume(&volume_path(&base, 1)) {
+            return Err(Error::InvalidInput(format!(
+                "{:?} è un volume intermedio: indica il primo volume {:?}",
+                path,
+                volume_path(&base, 1)
+            )));
+        }
+    }
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    Ok((InputReader::File(file), len))
+}
+
+/// Output di un archivio: un file singolo o un set di volumi
+pub(crate) enum ArchiveOutput {
+    File(AtomicFile),
+    Volumes(VolumeWriter),
+}
+
+impl ArchiveOutput {
+    /// Crea `path`, oppure i volumi `<path>.001`, ... se è indicata una dimensione
+    pub(crate) fn create(
+        path: &Path,
+        volume_size: Option<u64>,
+        algorithm: CompressionAlgorithm,
+    ) -> Result<Self> {
+        match volume_size {
+            Some(_) if !algorithm.skips_skippable_frames() => Err(Error::InvalidInput(format!(
+                "La divisione in volumi richiede zstd o lz4, non {}",
+                algorithm
+            ))),
+            Some(size) => Ok(ArchiveOutput::Volumes(VolumeWriter::create(path, size)?)),
+            None => Ok(ArchiveOutput::File(AtomicFile::create(path)?)),
+        }
+    }
+
+    /// Rende definitivo l'output; ritorna i file scritti
+    pub(crate) fn commit(self, path: &Path) -> Result<Vec<PathBuf>> {
+        match self {
+            ArchiveOutput::File(file) => {
+                file.commit()?;
+                Ok(vec![path.to_path_buf()])
+            }
+            ArchiveOutput::Volumes(volumes) => volumes.commit(),
+        }
+    }
+}
+
+impl Write for ArchiveOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            ArchiveOutput::File(file) => file.write(buf),
+            ArchiveOutput::Volumes(volumes) => volumes.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            ArchiveOutput::File(file) => file.flush(),
+            ArchiveOutput::Volumes(volumes) => volumes.flush(),
+        }
+    }
+}